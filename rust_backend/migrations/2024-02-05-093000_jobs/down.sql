@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS jobs;
+
+DROP TYPE IF EXISTS job_state;
@@ -0,0 +1,13 @@
+-- Create ENUM type for the lifecycle of a submitted job
+CREATE TYPE job_state AS ENUM('queued', 'compiling', 'running', 'finished', 'failed', 'timed_out', 'cancelled');
+
+-- Create jobs table
+CREATE TABLE jobs (
+    id UUID PRIMARY KEY NOT NULL, user_uuid UUID REFERENCES users (id) ON DELETE CASCADE NOT NULL, file_id UUID REFERENCES files (id), language VARCHAR(32) NOT NULL, source BYTEA NOT NULL, -- Kept so that unfinished jobs can be requeued after a restart
+    state job_state NOT NULL DEFAULT 'queued', created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP, started_at TIMESTAMP, finished_at TIMESTAMP, output JSONB -- Final output as returned by the API
+);
+
+CREATE INDEX idx_jobs_user_uuid ON jobs (user_uuid);
+
+-- Unfinished jobs are looked up at startup
+CREATE INDEX idx_jobs_state ON jobs (state);
@@ -0,0 +1,2 @@
+-- Test cases the job is graded against, NULL for a plain run
+ALTER TABLE jobs ADD COLUMN test_cases JSONB;
@@ -0,0 +1,2 @@
+-- Input written to the program's stdin, empty if none was given
+ALTER TABLE jobs ADD COLUMN stdin BYTEA NOT NULL DEFAULT '';
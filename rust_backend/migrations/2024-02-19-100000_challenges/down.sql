@@ -0,0 +1,5 @@
+ALTER TABLE jobs DROP COLUMN IF EXISTS challenge_id, DROP COLUMN IF EXISTS limits;
+
+DROP TABLE IF EXISTS challenge_test_cases;
+
+DROP TABLE IF EXISTS challenges;
@@ -0,0 +1,20 @@
+-- Create challenges table
+CREATE TABLE challenges (
+    id UUID PRIMARY KEY NOT NULL DEFAULT uuid_generate_v4 (), name VARCHAR(255) NOT NULL, description TEXT NOT NULL DEFAULT '', allowed_languages VARCHAR(32) [] NOT NULL DEFAULT '{}', -- Empty allows every supported language
+    limits JSONB, -- Overrides of the default runner limits
+    is_published BOOLEAN NOT NULL DEFAULT FALSE, -- Unpublished challenges are only visible to admins
+    created_by UUID REFERENCES users (id) ON DELETE SET NULL, created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP, updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create challenge_test_cases table
+CREATE TABLE challenge_test_cases (
+    id UUID PRIMARY KEY NOT NULL DEFAULT uuid_generate_v4 (), challenge_id UUID REFERENCES challenges (id) ON DELETE CASCADE NOT NULL, name VARCHAR(255) NOT NULL, input TEXT NOT NULL DEFAULT '', expected_output TEXT NOT NULL, comparison JSONB NOT NULL DEFAULT '{"mode": "exact"}', is_sample BOOLEAN NOT NULL DEFAULT FALSE, -- Samples are shown with the challenge, the rest stay hidden
+    position INT NOT NULL DEFAULT 0 -- Cases run in ascending position
+);
+
+CREATE INDEX idx_challenge_test_cases_challenge_id ON challenge_test_cases (challenge_id);
+
+-- Jobs submitted against a challenge keep its limits in case the challenge changes
+ALTER TABLE jobs
+ADD COLUMN challenge_id UUID REFERENCES challenges (id) ON DELETE SET NULL,
+ADD COLUMN limits JSONB;
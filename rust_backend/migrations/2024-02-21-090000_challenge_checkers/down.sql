@@ -0,0 +1,5 @@
+ALTER TABLE jobs DROP COLUMN IF EXISTS checker;
+
+ALTER TABLE challenges
+DROP COLUMN IF EXISTS checker_language,
+DROP COLUMN IF EXISTS checker_source;
@@ -0,0 +1,7 @@
+-- A checker program judges the output instead of comparing it to the expected output
+ALTER TABLE challenges
+ADD COLUMN checker_language VARCHAR(32),
+ADD COLUMN checker_source TEXT;
+
+-- Jobs keep a copy of the checker in case the challenge changes
+ALTER TABLE jobs ADD COLUMN checker JSONB;
@@ -0,0 +1,3 @@
+ALTER TABLE jobs DROP COLUMN IF EXISTS game;
+
+ALTER TABLE challenges DROP COLUMN IF EXISTS game;
@@ -0,0 +1,5 @@
+-- Interactive challenges are played against a referee instead of graded by test cases
+ALTER TABLE challenges ADD COLUMN game JSONB;
+
+-- Jobs keep a copy of the game settings in case the challenge changes
+ALTER TABLE jobs ADD COLUMN game JSONB;
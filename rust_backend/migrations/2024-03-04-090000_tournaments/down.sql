@@ -0,0 +1,11 @@
+DROP TABLE IF EXISTS ratings;
+
+DROP TABLE IF EXISTS matches;
+
+DROP TABLE IF EXISTS tournament_entries;
+
+DROP TABLE IF EXISTS tournaments;
+
+DROP TYPE IF EXISTS tournament_state;
+
+DROP TYPE IF EXISTS tournament_format;
@@ -0,0 +1,32 @@
+-- Create ENUM types for tournaments
+CREATE TYPE tournament_format AS ENUM('round_robin', 'swiss');
+
+CREATE TYPE tournament_state AS ENUM('pending', 'running', 'finished', 'failed');
+
+-- Create tournaments table, played on the referee of an interactive challenge
+CREATE TABLE tournaments (
+    id UUID PRIMARY KEY NOT NULL DEFAULT uuid_generate_v4 (), challenge_id UUID REFERENCES challenges (id) ON DELETE CASCADE NOT NULL, name VARCHAR(255) NOT NULL, format tournament_format NOT NULL, rounds INT NOT NULL DEFAULT 0, -- Set when the tournament starts for round robin
+    played_rounds INT NOT NULL DEFAULT 0, -- Rounds are stored as a whole, an interrupted round is played again
+    state tournament_state NOT NULL DEFAULT 'pending', created_by UUID REFERENCES users (id) ON DELETE SET NULL, created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP, started_at TIMESTAMP, finished_at TIMESTAMP
+);
+
+-- Create tournament_entries table, one submission per user
+CREATE TABLE tournament_entries (
+    tournament_id UUID REFERENCES tournaments (id) ON DELETE CASCADE NOT NULL, user_uuid UUID REFERENCES users (id) ON DELETE CASCADE NOT NULL, file_id UUID REFERENCES files (id) ON DELETE CASCADE NOT NULL, points DOUBLE PRECISION NOT NULL DEFAULT 0, -- 1 per win or bye, 0.5 per draw
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP, PRIMARY KEY (tournament_id, user_uuid)
+);
+
+-- Create matches table
+CREATE TABLE matches (
+    id UUID PRIMARY KEY NOT NULL DEFAULT uuid_generate_v4 (), tournament_id UUID REFERENCES tournaments (id) ON DELETE CASCADE NOT NULL, round INT NOT NULL, player_one UUID REFERENCES users (id) ON DELETE CASCADE NOT NULL, player_two UUID REFERENCES users (id) ON DELETE CASCADE, -- NULL for a bye
+    winner UUID REFERENCES users (id) ON DELETE SET NULL, -- NULL for a draw
+    reason TEXT NOT NULL DEFAULT '', transcript JSONB NOT NULL DEFAULT '[]', -- Every move in order, enough to replay the match
+    played_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_matches_tournament_id ON matches (tournament_id);
+
+-- Create ratings table, Elo ratings per user per game
+CREATE TABLE ratings (
+    user_uuid UUID REFERENCES users (id) ON DELETE CASCADE NOT NULL, challenge_id UUID REFERENCES challenges (id) ON DELETE CASCADE NOT NULL, rating DOUBLE PRECISION NOT NULL DEFAULT 1500, games_played INT NOT NULL DEFAULT 0, updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP, PRIMARY KEY (user_uuid, challenge_id)
+);
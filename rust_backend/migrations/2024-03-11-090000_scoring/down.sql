@@ -0,0 +1,6 @@
+ALTER TABLE simulations DROP COLUMN IF EXISTS score_breakdown;
+ALTER TABLE simulations DROP COLUMN IF EXISTS score;
+
+ALTER TABLE jobs DROP COLUMN IF EXISTS scoring;
+
+ALTER TABLE challenges DROP COLUMN IF EXISTS scoring;
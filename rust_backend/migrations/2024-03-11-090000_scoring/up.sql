@@ -0,0 +1,9 @@
+-- Weights and easing of the score, the defaults apply if unset
+ALTER TABLE challenges ADD COLUMN scoring JSONB;
+
+-- Jobs keep a copy of the scoring settings in case the challenge changes
+ALTER TABLE jobs ADD COLUMN scoring JSONB;
+
+-- Final score from 1 to 100 and the components it was made of
+ALTER TABLE simulations ADD COLUMN score INT4;
+ALTER TABLE simulations ADD COLUMN score_breakdown JSONB;
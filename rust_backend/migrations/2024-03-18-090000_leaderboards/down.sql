@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS idx_jobs_leaderboard;
+
+ALTER TABLE jobs DROP COLUMN IF EXISTS peak_memory_kb;
+ALTER TABLE jobs DROP COLUMN IF EXISTS cpu_time_ms;
+ALTER TABLE jobs DROP COLUMN IF EXISTS score;
@@ -0,0 +1,11 @@
+-- Copied out of the output of scored jobs so leaderboards can be ranked in SQL
+ALTER TABLE jobs ADD COLUMN score INT4;
+ALTER TABLE jobs ADD COLUMN cpu_time_ms INT8;
+ALTER TABLE jobs ADD COLUMN peak_memory_kb INT8;
+
+UPDATE jobs SET score = (output -> 'scoring' ->> 'score')::INT4
+WHERE output -> 'scoring' ->> 'score' IS NOT NULL;
+
+-- The best run of every user on a challenge, in the order the leaderboard query picks it
+CREATE INDEX idx_jobs_leaderboard ON jobs (user_uuid, challenge_id, score DESC, created_at, cpu_time_ms)
+WHERE score IS NOT NULL;
@@ -0,0 +1,9 @@
+DROP TABLE IF EXISTS contest_submissions;
+
+DROP TABLE IF EXISTS contest_participants;
+
+DROP TABLE IF EXISTS contest_challenges;
+
+DROP TABLE IF EXISTS contests;
+
+DROP TYPE IF EXISTS contest_scoring;
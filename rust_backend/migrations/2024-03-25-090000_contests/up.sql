@@ -0,0 +1,25 @@
+-- Create ENUM type for contest scoring
+CREATE TYPE contest_scoring AS ENUM('icpc', 'ioi');
+
+-- Create contests table, a set of challenges open for a fixed time
+CREATE TABLE contests (
+    id UUID PRIMARY KEY NOT NULL DEFAULT uuid_generate_v4 (), name VARCHAR(255) NOT NULL, description TEXT NOT NULL DEFAULT '', scoring contest_scoring NOT NULL, starts_at TIMESTAMP NOT NULL, ends_at TIMESTAMP NOT NULL, freeze_minutes INT NOT NULL DEFAULT 60, -- The scoreboard stops changing this long before the end
+    created_by UUID REFERENCES users (id) ON DELETE SET NULL, created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP, CHECK (starts_at < ends_at)
+);
+
+-- Create contest_challenges table
+CREATE TABLE contest_challenges (
+    contest_id UUID REFERENCES contests (id) ON DELETE CASCADE NOT NULL, challenge_id UUID REFERENCES challenges (id) ON DELETE CASCADE NOT NULL, position INT NOT NULL DEFAULT 0, PRIMARY KEY (contest_id, challenge_id)
+);
+
+-- Create contest_participants table
+CREATE TABLE contest_participants (
+    contest_id UUID REFERENCES contests (id) ON DELETE CASCADE NOT NULL, user_uuid UUID REFERENCES users (id) ON DELETE CASCADE NOT NULL, registered_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP, PRIMARY KEY (contest_id, user_uuid)
+);
+
+-- Create contest_submissions table, the verdict is the simulation of the file
+CREATE TABLE contest_submissions (
+    id UUID PRIMARY KEY NOT NULL DEFAULT uuid_generate_v4 (), contest_id UUID REFERENCES contests (id) ON DELETE CASCADE NOT NULL, challenge_id UUID REFERENCES challenges (id) ON DELETE CASCADE NOT NULL, user_uuid UUID REFERENCES users (id) ON DELETE CASCADE NOT NULL, file_id UUID REFERENCES files (id) ON DELETE CASCADE NOT NULL, submitted_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_contest_submissions_contest_id ON contest_submissions (contest_id);
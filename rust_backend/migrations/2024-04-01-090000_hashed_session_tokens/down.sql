@@ -0,0 +1,7 @@
+DELETE FROM session_tokens;
+
+ALTER TABLE session_tokens ALTER COLUMN token_hash TYPE VARCHAR(255);
+
+ALTER TABLE session_tokens RENAME COLUMN token_hash TO token;
+
+CREATE INDEX idx_token ON session_tokens (token);
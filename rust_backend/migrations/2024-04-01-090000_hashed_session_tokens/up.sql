@@ -0,0 +1,9 @@
+-- Plaintext tokens cannot be checked against a hash, everyone logs in again
+DELETE FROM session_tokens;
+
+DROP INDEX IF EXISTS idx_token;
+
+-- Hex HMAC-SHA256 of the whole token, the row is found by its id
+ALTER TABLE session_tokens RENAME COLUMN token TO token_hash;
+
+ALTER TABLE session_tokens ALTER COLUMN token_hash TYPE VARCHAR(64);
@@ -0,0 +1,9 @@
+DROP TABLE IF EXISTS teacher_students;
+
+ALTER TABLE users ADD COLUMN is_admin BOOLEAN DEFAULT FALSE;
+
+UPDATE users SET is_admin = TRUE WHERE role = 'admin';
+
+ALTER TABLE users DROP COLUMN role;
+
+DROP TYPE IF EXISTS user_role;
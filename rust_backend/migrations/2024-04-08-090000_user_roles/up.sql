@@ -0,0 +1,16 @@
+-- Create ENUM type for user roles, each role can do everything the ones before it can
+CREATE TYPE user_role AS ENUM('student', 'teacher', 'admin');
+
+ALTER TABLE users ADD COLUMN role user_role NOT NULL DEFAULT 'student';
+
+UPDATE users SET role = 'admin' WHERE is_admin;
+
+-- The role replaces the flag
+ALTER TABLE users DROP COLUMN is_admin;
+
+-- Create teacher_students table, teachers can see the submissions of their students
+CREATE TABLE teacher_students (
+    teacher_uuid UUID REFERENCES users (id) ON DELETE CASCADE NOT NULL, student_uuid UUID REFERENCES users (id) ON DELETE CASCADE NOT NULL, added_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP, PRIMARY KEY (teacher_uuid, student_uuid), CHECK (teacher_uuid <> student_uuid)
+);
+
+CREATE INDEX idx_teacher_students_student_uuid ON teacher_students (student_uuid);
@@ -0,0 +1,10 @@
+-- Create ENUM type for what a personal access token may be used for
+CREATE TYPE token_scope AS ENUM('read', 'submit');
+
+-- Create personal_access_tokens table, tokens look like pat_<id>_<secret>
+CREATE TABLE personal_access_tokens (
+    id UUID PRIMARY KEY NOT NULL, user_uuid UUID REFERENCES users (id) ON DELETE CASCADE NOT NULL, name VARCHAR(255) NOT NULL, token_hash VARCHAR(64) NOT NULL, -- Hex HMAC-SHA256 of the whole token, like session tokens
+    scopes token_scope[] NOT NULL, created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP, expires_at TIMESTAMP NOT NULL, last_used_at TIMESTAMP, CHECK (cardinality(scopes) > 0 AND array_position(scopes, NULL) IS NULL)
+);
+
+CREATE INDEX idx_personal_access_tokens_user_uuid ON personal_access_tokens (user_uuid);
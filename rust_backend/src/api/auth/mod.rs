@@ -1 +1,3 @@
 pub mod hashing;
+pub mod roles;
+pub mod tokens;
@@ -0,0 +1,14 @@
+use uuid::Uuid;
+
+use crate::ctx::Ctx;
+use crate::database::connection::is_teacher_of;
+use crate::database::models::Role;
+use crate::Result;
+
+// Users see their own submissions, teachers those of their students and admins all
+pub async fn can_view_submissions_of(ctx: &Ctx, user_id: Uuid) -> Result<bool> {
+    if user_id == ctx.user_id() || ctx.has_role(Role::Admin) {
+        return Ok(true);
+    }
+    Ok(ctx.has_role(Role::Teacher) && is_teacher_of(ctx.user_id(), user_id).await?)
+}
@@ -0,0 +1,82 @@
+use std::env;
+use std::sync::OnceLock;
+
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, Rng, RngCore};
+use sha2::Sha256;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Session tokens look like ses_<session id>_<secret>
+pub const SESSION_TOKEN_PREFIX: &str = "ses";
+// Personal access tokens look like pat_<token id>_<secret>
+pub const PERSONAL_TOKEN_PREFIX: &str = "pat";
+
+const SECRET_LENGTH: usize = 32;
+
+// Only the hash is stored, the token itself is shown to the client once
+pub struct IssuedToken {
+    pub id: Uuid,
+    pub token: String,
+    pub hash: String,
+}
+
+// Read from TOKEN_HASH_KEY. Without it a random key is used,
+// which logs everyone out whenever the backend restarts.
+fn hash_key() -> &'static [u8] {
+    static KEY: OnceLock<Vec<u8>> = OnceLock::new();
+    KEY.get_or_init(|| {
+        dotenv::dotenv().ok();
+        env::var("TOKEN_HASH_KEY").map_or_else(
+            |_| {
+                warn!("TOKEN_HASH_KEY is not set, sessions will not survive a restart");
+                let mut key = vec![0; 32];
+                rand::thread_rng().fill_bytes(&mut key);
+                key
+            },
+            String::into_bytes,
+        )
+    })
+}
+
+fn token_mac(token: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(hash_key()).expect("HMAC accepts keys of any length");
+    mac.update(token.as_bytes());
+    mac
+}
+
+pub fn issue_token(prefix: &str) -> IssuedToken {
+    let id = Uuid::new_v4();
+    let secret: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(SECRET_LENGTH)
+        .map(char::from)
+        .collect();
+    let token = format!("{prefix}_{}_{secret}", id.simple());
+
+    IssuedToken {
+        id,
+        hash: hash_token(&token),
+        token,
+    }
+}
+
+// The id the token was issued with, None if it is not a token of this kind
+pub fn token_id(prefix: &str, token: &str) -> Option<Uuid> {
+    let mut parts = token.splitn(3, '_');
+    if parts.next()? != prefix {
+        return None;
+    }
+    let id = Uuid::try_parse(parts.next()?).ok()?;
+    (parts.next()?.len() == SECRET_LENGTH).then_some(id)
+}
+
+pub fn hash_token(token: &str) -> String {
+    hex::encode(token_mac(token).finalize().into_bytes())
+}
+
+// Compared in constant time, so the hash cannot be guessed byte by byte
+pub fn verify_token(token: &str, hash: &str) -> bool {
+    hex::decode(hash).is_ok_and(|expected| token_mac(token).verify_slice(&expected).is_ok())
+}
@@ -2,13 +2,13 @@ use std::str::FromStr;
 
 use crate::{
     ctx::Ctx,
-    database::{self, connection, SessionToken},
+    database::{self, connection, Role, SessionToken, TokenScope},
     Error,
 };
 use async_trait::async_trait;
 use axum::{
     body::Body,
-    extract::{FromRequestParts, Request},
+    extract::{FromRequestParts, Request, State},
     middleware::Next,
     response::Response,
     routing::get,
@@ -16,6 +16,7 @@ use axum::{
 };
 use chrono::{NaiveDateTime, Utc};
 use diesel::query_dsl::methods::FilterDsl;
+use http::header::AUTHORIZATION;
 use http::request::Parts;
 use hyper::server::conn;
 use lazy_regex::regex_captures;
@@ -28,28 +29,30 @@ async fn get_new_ctx(token: Option<String>) -> Result<Ctx, Error> {
     match parse_token(token) {
         Ok(token_id) => {
             let user = connection::get_token_owner(&token_id)
-                .await
-                .map_err(|_| Error::AuthFailTokenWrongFormat)?
+                .await?
                 .ok_or(Error::AuthFailTokenWrongFormat)?;
 
-            let user_id = user.id.to_string();
-
-            Ok(Ctx::new(Uuid::from_str(user_id.as_str()).map_err(
-                |_| {
-                    error!("Failed to parse user_id from token");
-                    Error::AuthFailTokenWrongFormat
-                },
-            )?))
+            // Loaded on every request, so role changes apply right away
+            Ok(Ctx::new(user.id, user.role))
         }
         Err(e) => Err(e),
     }
 }
 
+async fn get_personal_token_ctx(token: &str) -> Result<Ctx, Error> {
+    let (user, scopes) = connection::get_personal_token_owner(token)
+        .await?
+        .ok_or(Error::AuthFailInvalidToken)?;
+
+    Ok(Ctx::new(user.id, user.role).with_scopes(scopes))
+}
+
+// Route layer for everything but the public routes, anonymous requests get a 401
 pub async fn mw_require_auth(
     ctx: Result<Ctx, Error>,
     req: Request<Body>,
     next: Next,
-) -> Result<Response, Error> {
+) -> crate::Result<Response> {
     info!("->> {:<12} - mw_require_auth - {ctx:?}", "MIDDLEWARE");
 
     ctx?;
@@ -57,6 +60,63 @@ pub async fn mw_require_auth(
     Ok(next.run(req).await)
 }
 
+// Route layer for routes that need more than a login, the role is the state:
+// `middleware::from_fn_with_state(Role::Admin, mw_require_role)`
+pub async fn mw_require_role(
+    State(required): State<Role>,
+    ctx: Result<Ctx, Error>,
+    req: Request<Body>,
+    next: Next,
+) -> crate::Result<Response> {
+    info!(
+        "->> {:<12} - mw_require_role {required:?} - {ctx:?}",
+        "MIDDLEWARE"
+    );
+
+    if !ctx?.has_role(required) {
+        return Err(Error::Forbidden.into());
+    }
+
+    Ok(next.run(req).await)
+}
+
+// Route layer declaring what a personal access token needs to be used on the
+// route, sessions are never limited by it:
+// `middleware::from_fn_with_state(TokenScope::Read, mw_require_scope)`
+pub async fn mw_require_scope(
+    State(scope): State<TokenScope>,
+    ctx: Result<Ctx, Error>,
+    req: Request<Body>,
+    next: Next,
+) -> crate::Result<Response> {
+    info!(
+        "->> {:<12} - mw_require_scope {scope:?} - {ctx:?}",
+        "MIDDLEWARE"
+    );
+
+    if !ctx?.allows(scope) {
+        return Err(Error::MissingTokenScope.into());
+    }
+
+    Ok(next.run(req).await)
+}
+
+// Route layer for managing the account itself, personal access tokens cannot
+// be used to log out, create more tokens or for anything that needs a role
+pub async fn mw_require_session(
+    ctx: Result<Ctx, Error>,
+    req: Request<Body>,
+    next: Next,
+) -> crate::Result<Response> {
+    info!("->> {:<12} - mw_require_session - {ctx:?}", "MIDDLEWARE");
+
+    if ctx?.is_personal_token() {
+        return Err(Error::MissingTokenScope.into());
+    }
+
+    Ok(next.run(req).await)
+}
+
 pub const AUTH_TOKEN: &str = "auth_token";
 
 pub async fn mw_ctx_resolver(
@@ -66,19 +126,34 @@ pub async fn mw_ctx_resolver(
 ) -> Result<Response, Error> {
     info!("->> {:<12} - mw_ctx_resolver", "MIDDLEWARE");
 
-    let auth_token = cookies.get(AUTH_TOKEN).map(|c| c.value().to_string());
-
-    info!("Auth token: {:?}", auth_token);
+    // Scripts send a personal access token instead of the session cookie.
+    // The scheme is case-insensitive (RFC 7235)
+    let bearer = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split_once(' '))
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("Bearer"))
+        .map(|(_, token)| token.trim().to_string());
 
     // Compute Result<Ctx>.
-    let result_ctx = get_new_ctx(auth_token).await;
+    let result_ctx = if let Some(token) = bearer {
+        get_personal_token_ctx(&token).await
+    } else {
+        let auth_token = cookies.get(AUTH_TOKEN).map(|c| c.value().to_string());
+        let result_ctx = get_new_ctx(auth_token).await;
+
+        // Remove the cookie if something went wrong other than NoAuthTokenCookie.
+        if let Err(e) = &result_ctx {
+            if !matches!(e, Error::AuthFailNoAuthTokenCookie) {
+                cookies.remove(Cookie::from(AUTH_TOKEN));
+            }
+        }
+        result_ctx
+    };
 
-    // Remove the cookie if something went wrong other than NoAuthTokenCookie.
     if let Err(e) = &result_ctx {
         error!("Error in mw_ctx_resolver: {:?}", e);
-        if !matches!(e, Error::AuthFailNoAuthTokenCookie) {
-            cookies.remove(Cookie::from(AUTH_TOKEN));
-        }
     }
 
     req.extensions_mut().insert(result_ctx);
@@ -103,7 +178,7 @@ impl<S: Send + Sync> FromRequestParts<S> for Ctx {
 
 // This is stupid, but it's a placeholder for now.
 #[allow(clippy::needless_pass_by_value)]
-fn parse_token(token: String) -> Result<(String), Error> {
+pub fn parse_token(token: String) -> Result<(String), Error> {
     if let Some(index) = token.find('=') {
         // Return the substring after the '=' sign
         Ok(token[index + 1..].to_string())
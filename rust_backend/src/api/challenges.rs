@@ -0,0 +1,498 @@
+use axum::extract::{Multipart, Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::Utc;
+use serde::{Deserialize, Deserializer};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::api::run_code::{build_file, extract_program_from_multipart, queue_job, store_program};
+use crate::ctx::Ctx;
+use crate::database::connection::{
+    delete_challenge, delete_test_case, get_challenge, get_challenges, get_test_cases,
+    insert_challenge, insert_test_case, update_challenge,
+};
+use crate::database::models::{
+    Challenge, ChallengeChanges, ChallengeTestCase, NewChallenge, NewChallengeTestCase, Role,
+};
+use crate::docker::job::SandboxJob;
+use crate::docker::languages::Language;
+use crate::docker::limits::ResourceLimits;
+use crate::error::Error;
+use crate::simulation::grading::{Checker, Comparison, TestCase};
+use crate::simulation::scoring::ScoringConfig;
+use crate::simulation::sim::{GameSettings, Referee};
+use crate::tasks::CodeJob;
+use crate::utils::temp_file;
+use crate::{AppState, Result};
+
+#[derive(Deserialize)]
+pub struct ChallengePayload {
+    name: String,
+    #[serde(default)]
+    description: String,
+    // Empty allows every supported language
+    #[serde(default)]
+    allowed_languages: Vec<Language>,
+    limits: Option<ResourceLimits>,
+    #[serde(default)]
+    is_published: bool,
+    // Graded submissions are scored with the defaults if missing
+    scoring: Option<ScoringConfig>,
+}
+
+#[derive(Deserialize)]
+pub struct ChallengeUpdate {
+    name: Option<String>,
+    description: Option<String>,
+    allowed_languages: Option<Vec<Language>>,
+    // null removes the override
+    #[serde(default, deserialize_with = "nullable")]
+    limits: Option<Option<ResourceLimits>>,
+    is_published: Option<bool>,
+    scoring: Option<ScoringConfig>,
+}
+
+// Tells an explicit null, Some(None), from a missing field
+fn nullable<'de, D, T>(deserializer: D) -> std::result::Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Deserialize)]
+pub struct TestCasePayload {
+    name: String,
+    #[serde(default)]
+    input: String,
+    expected_output: String,
+    #[serde(default)]
+    comparison: Comparison,
+    // Samples are shown to everyone with the challenge
+    #[serde(default)]
+    is_sample: bool,
+    #[serde(default)]
+    position: i32,
+}
+
+fn language_names(languages: &[Language]) -> Vec<Option<String>> {
+    languages
+        .iter()
+        .map(|language| Some(language.as_ref().to_string()))
+        .collect()
+}
+
+fn allowed_languages(challenge: &Challenge) -> Vec<Language> {
+    challenge
+        .allowed_languages
+        .iter()
+        .flatten()
+        .filter_map(|name| Language::from_name(name))
+        .collect()
+}
+
+fn challenge_limits(challenge: &Challenge) -> Option<ResourceLimits> {
+    challenge
+        .limits
+        .clone()
+        .and_then(|limits| serde_json::from_value(limits).ok())
+}
+
+fn challenge_checker(challenge: &Challenge) -> Option<Checker> {
+    Some(Checker {
+        language: Language::from_name(challenge.checker_language.as_deref()?)?,
+        source: challenge.checker_source.clone()?,
+    })
+}
+
+fn challenge_game(challenge: &Challenge) -> Option<GameSettings> {
+    challenge
+        .game
+        .clone()
+        .and_then(|game| serde_json::from_value(game).ok())
+}
+
+fn challenge_scoring(challenge: &Challenge) -> ScoringConfig {
+    challenge
+        .scoring
+        .clone()
+        .and_then(|scoring| serde_json::from_value(scoring).ok())
+        .unwrap_or_default()
+}
+
+pub fn challenge_json(challenge: &Challenge) -> Value {
+    json!({
+        "challenge_id": challenge.id,
+        "name": challenge.name,
+        "description": challenge.description,
+        "allowed_languages": allowed_languages(challenge),
+        "limits": challenge_limits(challenge),
+        "is_published": challenge.is_published,
+        // The checker source stays private, like the hidden test cases
+        "checker_language": challenge_checker(challenge).map(|checker| checker.language),
+        "interactive": challenge.game.is_some(),
+        "scoring": challenge_scoring(challenge),
+        "created_at": challenge.created_at,
+        "updated_at": challenge.updated_at,
+    })
+}
+
+fn test_case_json(test_case: &ChallengeTestCase) -> Value {
+    json!({
+        "test_case_id": test_case.id,
+        "name": test_case.name,
+        "input": test_case.input,
+        "expected_output": test_case.expected_output,
+        "comparison": test_case.comparison,
+        "is_sample": test_case.is_sample,
+        "position": test_case.position,
+    })
+}
+
+impl From<ChallengeTestCase> for TestCase {
+    fn from(test_case: ChallengeTestCase) -> Self {
+        Self {
+            name: test_case.name,
+            input: test_case.input,
+            expected_output: test_case.expected_output,
+            comparison: serde_json::from_value(test_case.comparison).unwrap_or_default(),
+            hidden: !test_case.is_sample,
+        }
+    }
+}
+
+// Unpublished challenges are reported as missing to everyone but admins
+pub async fn get_visible_challenge(challenge_id: Uuid, ctx: &Ctx) -> Result<Challenge> {
+    let challenge = get_challenge(challenge_id)
+        .await?
+        .ok_or(Error::ChallengeNotFound)?;
+    if challenge.is_published || ctx.has_role(Role::Admin) {
+        Ok(challenge)
+    } else {
+        Err(Error::ChallengeNotFound.into())
+    }
+}
+
+pub async fn list_challenges(ctx: Ctx) -> Result<Json<Value>> {
+    let challenges = get_challenges(ctx.has_role(Role::Admin)).await?;
+    Ok(Json(json!({
+        "challenges": challenges.iter().map(challenge_json).collect::<Vec<_>>(),
+    })))
+}
+
+pub async fn get_challenge_details(
+    ctx: Ctx,
+    Path(challenge_id): Path<Uuid>,
+) -> Result<Json<Value>> {
+    let challenge = get_visible_challenge(challenge_id, &ctx).await?;
+    let samples: Vec<Value> = get_test_cases(challenge_id)
+        .await?
+        .iter()
+        .filter(|test_case| test_case.is_sample)
+        .map(test_case_json)
+        .collect();
+
+    let mut json = challenge_json(&challenge);
+    json["samples"] = Value::from(samples);
+    Ok(Json(json))
+}
+
+pub async fn create_challenge(
+    ctx: Ctx,
+    Json(payload): Json<ChallengePayload>,
+) -> Result<(StatusCode, Json<Value>)> {
+    if payload.name.trim().is_empty()
+        || payload.limits.is_some_and(|limits| !limits.is_valid())
+        || payload.scoring.is_some_and(|scoring| !scoring.is_valid())
+    {
+        return Err(Error::InvalidChallenge.into());
+    }
+
+    let challenge_id = insert_challenge(NewChallenge {
+        name: payload.name.trim(),
+        description: &payload.description,
+        allowed_languages: language_names(&payload.allowed_languages),
+        limits: payload.limits.map(serde_json::to_value).transpose()?,
+        is_published: payload.is_published,
+        created_by: Some(ctx.user_id()),
+        scoring: payload.scoring.map(serde_json::to_value).transpose()?,
+    })
+    .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({ "challenge_id": challenge_id })),
+    ))
+}
+
+pub async fn edit_challenge(
+    _ctx: Ctx,
+    Path(challenge_id): Path<Uuid>,
+    Json(payload): Json<ChallengeUpdate>,
+) -> Result<Json<Value>> {
+    if payload
+        .name
+        .as_deref()
+        .is_some_and(|name| name.trim().is_empty())
+        || payload
+            .limits
+            .flatten()
+            .is_some_and(|limits| !limits.is_valid())
+        || payload.scoring.is_some_and(|scoring| !scoring.is_valid())
+    {
+        return Err(Error::InvalidChallenge.into());
+    }
+
+    let changes = ChallengeChanges {
+        name: payload.name.map(|name| name.trim().to_string()),
+        description: payload.description,
+        allowed_languages: payload.allowed_languages.as_deref().map(language_names),
+        limits: payload
+            .limits
+            .map(|limits| limits.map(serde_json::to_value).transpose())
+            .transpose()?,
+        is_published: payload.is_published,
+        scoring: payload
+            .scoring
+            .map(serde_json::to_value)
+            .transpose()?
+            .map(Some),
+        updated_at: Some(Utc::now().naive_utc()),
+        ..Default::default()
+    };
+    if !update_challenge(challenge_id, changes).await? {
+        return Err(Error::ChallengeNotFound.into());
+    }
+
+    let challenge = get_challenge(challenge_id)
+        .await?
+        .ok_or(Error::ChallengeNotFound)?;
+    Ok(Json(challenge_json(&challenge)))
+}
+
+pub async fn remove_challenge(_ctx: Ctx, Path(challenge_id): Path<Uuid>) -> Result<Json<Value>> {
+    if !delete_challenge(challenge_id).await? {
+        return Err(Error::ChallengeNotFound.into());
+    }
+    Ok(Json(
+        json!({ "status": "deleted", "challenge_id": challenge_id }),
+    ))
+}
+
+// Programs uploaded with a challenge are compiled once up front,
+// so a broken one does not fail every submission
+async fn check_compiles(language: Language, source: &str) -> Result<()> {
+    let file = temp_file(source.as_bytes()).await?;
+    let compile = build_file(file, language, &SandboxJob::anonymous()).await?;
+    if compile.artifact.is_none() {
+        return Err(Error::CompilationFailed.into());
+    }
+    Ok(())
+}
+
+// Replaces the checker, it has to compile to be accepted
+pub async fn set_checker(
+    _ctx: Ctx,
+    Path(challenge_id): Path<Uuid>,
+    multipart: Multipart,
+) -> Result<Json<Value>> {
+    let program = extract_program_from_multipart(multipart).await?;
+    let source = String::from_utf8(program.source).map_err(|_| Error::InvalidChallenge)?;
+    if source.trim().is_empty() {
+        return Err(Error::InvalidChallenge.into());
+    }
+
+    check_compiles(program.language, &source).await?;
+
+    let changes = ChallengeChanges {
+        checker_language: Some(Some(program.language.as_ref().to_string())),
+        checker_source: Some(Some(source)),
+        updated_at: Some(Utc::now().naive_utc()),
+        ..Default::default()
+    };
+    if !update_challenge(challenge_id, changes).await? {
+        return Err(Error::ChallengeNotFound.into());
+    }
+
+    Ok(Json(json!({
+        "status": "updated",
+        "challenge_id": challenge_id,
+        "checker_language": program.language,
+    })))
+}
+
+// Outputs are compared to the expected output again
+pub async fn remove_checker(_ctx: Ctx, Path(challenge_id): Path<Uuid>) -> Result<Json<Value>> {
+    let changes = ChallengeChanges {
+        checker_language: Some(None),
+        checker_source: Some(None),
+        updated_at: Some(Utc::now().naive_utc()),
+        ..Default::default()
+    };
+    if !update_challenge(challenge_id, changes).await? {
+        return Err(Error::ChallengeNotFound.into());
+    }
+    Ok(Json(
+        json!({ "status": "deleted", "challenge_id": challenge_id }),
+    ))
+}
+
+// Makes the challenge interactive, referee programs have to compile to be accepted
+pub async fn set_game(
+    _ctx: Ctx,
+    Path(challenge_id): Path<Uuid>,
+    Json(settings): Json<GameSettings>,
+) -> Result<Json<Value>> {
+    if settings.move_timeout_ms == 0 || settings.max_moves == 0 {
+        return Err(Error::InvalidChallenge.into());
+    }
+
+    match &settings.referee {
+        Referee::PingPong { rounds } if *rounds == 0 => {
+            return Err(Error::InvalidChallenge.into());
+        }
+        Referee::PingPong { .. } => {}
+        Referee::Program { language, source } => {
+            if source.trim().is_empty() {
+                return Err(Error::InvalidChallenge.into());
+            }
+            check_compiles(*language, source).await?;
+        }
+    }
+
+    let changes = ChallengeChanges {
+        game: Some(Some(serde_json::to_value(&settings)?)),
+        updated_at: Some(Utc::now().naive_utc()),
+        ..Default::default()
+    };
+    if !update_challenge(challenge_id, changes).await? {
+        return Err(Error::ChallengeNotFound.into());
+    }
+
+    Ok(Json(json!({
+        "status": "updated",
+        "challenge_id": challenge_id,
+        "move_timeout_ms": settings.move_timeout_ms,
+        "max_moves": settings.max_moves,
+    })))
+}
+
+// Submissions are graded by the test cases again
+pub async fn remove_game(_ctx: Ctx, Path(challenge_id): Path<Uuid>) -> Result<Json<Value>> {
+    let changes = ChallengeChanges {
+        game: Some(None),
+        updated_at: Some(Utc::now().naive_utc()),
+        ..Default::default()
+    };
+    if !update_challenge(challenge_id, changes).await? {
+        return Err(Error::ChallengeNotFound.into());
+    }
+    Ok(Json(
+        json!({ "status": "deleted", "challenge_id": challenge_id }),
+    ))
+}
+
+pub async fn list_test_cases(_ctx: Ctx, Path(challenge_id): Path<Uuid>) -> Result<Json<Value>> {
+    get_challenge(challenge_id)
+        .await?
+        .ok_or(Error::ChallengeNotFound)?;
+
+    let test_cases = get_test_cases(challenge_id).await?;
+    Ok(Json(json!({
+        "test_cases": test_cases.iter().map(test_case_json).collect::<Vec<_>>(),
+    })))
+}
+
+pub async fn add_test_case(
+    _ctx: Ctx,
+    Path(challenge_id): Path<Uuid>,
+    Json(payload): Json<TestCasePayload>,
+) -> Result<(StatusCode, Json<Value>)> {
+    get_challenge(challenge_id)
+        .await?
+        .ok_or(Error::ChallengeNotFound)?;
+    if payload.name.trim().is_empty() {
+        return Err(Error::InvalidChallenge.into());
+    }
+
+    let test_case_id = insert_test_case(NewChallengeTestCase {
+        challenge_id,
+        name: payload.name.trim(),
+        input: &payload.input,
+        expected_output: &payload.expected_output,
+        comparison: serde_json::to_value(&payload.comparison)?,
+        is_sample: payload.is_sample,
+        position: payload.position,
+    })
+    .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({ "test_case_id": test_case_id })),
+    ))
+}
+
+pub async fn remove_test_case(
+    _ctx: Ctx,
+    Path((challenge_id, test_case_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<Value>> {
+    if !delete_test_case(challenge_id, test_case_id).await? {
+        return Err(Error::TestCaseNotFound.into());
+    }
+    Ok(Json(
+        json!({ "status": "deleted", "test_case_id": test_case_id }),
+    ))
+}
+
+// Grades the submission against every test case of the challenge, or plays
+// it against the referee of an interactive challenge. The challenge is
+// copied into the job, later edits do not affect it.
+pub async fn submit_solution(
+    State(state): State<AppState>,
+    ctx: Ctx,
+    Path(challenge_id): Path<Uuid>,
+    multipart: Multipart,
+) -> Result<(StatusCode, Json<Value>)> {
+    let challenge = get_challenge(challenge_id)
+        .await?
+        .filter(|challenge| challenge.is_published)
+        .ok_or(Error::ChallengeNotFound)?;
+
+    let job = challenge_job(&ctx, &challenge, multipart).await?;
+    queue_job(&state, job).await
+}
+
+// Checks the submission against the challenge and stores it, ready to queue
+pub async fn challenge_job(
+    ctx: &Ctx,
+    challenge: &Challenge,
+    multipart: Multipart,
+) -> Result<CodeJob> {
+    let program = extract_program_from_multipart(multipart).await?;
+    let allowed = allowed_languages(challenge);
+    if !allowed.is_empty() && !allowed.contains(&program.language) {
+        return Err(Error::LanguageNotAllowed.into());
+    }
+
+    let test_cases: Vec<TestCase> = get_test_cases(challenge.id)
+        .await?
+        .into_iter()
+        .map(TestCase::from)
+        .collect();
+    let game = challenge_game(challenge);
+    if test_cases.is_empty() && game.is_none() {
+        return Err(Error::InvalidChallenge.into());
+    }
+
+    let file_id = store_program(ctx, &program).await?;
+    Ok(
+        CodeJob::new(ctx.user_id(), program.language, program.source)
+            .with_file(file_id)
+            .with_challenge(challenge.id, test_cases, challenge_limits(challenge))
+            .with_checker(challenge_checker(challenge))
+            .with_game(game)
+            .with_scoring(Some(challenge_scoring(challenge))),
+    )
+}
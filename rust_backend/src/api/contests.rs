@@ -0,0 +1,354 @@
+use std::collections::HashSet;
+
+use axum::extract::{Multipart, Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::{NaiveDateTime, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::api::challenges::{challenge_job, challenge_json};
+use crate::api::run_code::queue_job;
+use crate::ctx::Ctx;
+use crate::database::connection::{
+    delete_contest, delete_contest_challenge, delete_contest_submission, get_challenge,
+    get_contest, get_contest_challenges, get_contest_participant, get_contest_participants,
+    get_contest_submissions, get_contests, insert_contest, insert_contest_participant,
+    insert_contest_submission, update_contest, upsert_contest_challenge,
+};
+use crate::database::models::{
+    Contest, ContestChanges, ContestScoring, NewContest, NewContestChallenge, NewContestSubmission,
+    Role,
+};
+use crate::error::Error;
+use crate::simulation::contest::{freeze_time, scoreboard, Attempt};
+use crate::{AppState, Result};
+
+const fn default_freeze_minutes() -> i32 {
+    60
+}
+
+#[derive(Deserialize)]
+pub struct ContestPayload {
+    name: String,
+    #[serde(default)]
+    description: String,
+    scoring: ContestScoring,
+    starts_at: NaiveDateTime,
+    ends_at: NaiveDateTime,
+    // 0 keeps the scoreboard live until the end
+    #[serde(default = "default_freeze_minutes")]
+    freeze_minutes: i32,
+    // In the order they are shown in
+    #[serde(default)]
+    challenges: Vec<Uuid>,
+}
+
+#[derive(Deserialize)]
+pub struct ContestUpdate {
+    name: Option<String>,
+    description: Option<String>,
+    scoring: Option<ContestScoring>,
+    starts_at: Option<NaiveDateTime>,
+    ends_at: Option<NaiveDateTime>,
+    freeze_minutes: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct ContestChallengePayload {
+    #[serde(default)]
+    position: i32,
+}
+
+fn is_valid_window(starts_at: NaiveDateTime, ends_at: NaiveDateTime, freeze_minutes: i32) -> bool {
+    starts_at < ends_at && freeze_minutes >= 0
+}
+
+fn is_running(contest: &Contest, now: NaiveDateTime) -> bool {
+    contest.starts_at <= now && now < contest.ends_at
+}
+
+fn contest_json(contest: &Contest) -> Value {
+    json!({
+        "contest_id": contest.id,
+        "name": contest.name,
+        "description": contest.description,
+        "scoring": contest.scoring,
+        "starts_at": contest.starts_at,
+        "ends_at": contest.ends_at,
+        "freeze_minutes": contest.freeze_minutes,
+        "frozen_at": freeze_time(contest.starts_at, contest.ends_at, contest.freeze_minutes),
+        "created_at": contest.created_at,
+    })
+}
+
+async fn find_contest(contest_id: Uuid) -> Result<Contest> {
+    Ok(get_contest(contest_id)
+        .await?
+        .ok_or(Error::ContestNotFound)?)
+}
+
+pub async fn list_contests(_ctx: Ctx) -> Result<Json<Value>> {
+    let contests = get_contests().await?;
+    Ok(Json(json!({
+        "contests": contests.iter().map(contest_json).collect::<Vec<_>>(),
+    })))
+}
+
+// The challenges stay hidden from everyone but admins until the start
+pub async fn get_contest_details(ctx: Ctx, Path(contest_id): Path<Uuid>) -> Result<Json<Value>> {
+    let contest = find_contest(contest_id).await?;
+    let now = Utc::now().naive_utc();
+
+    let challenges: Vec<Value> = if now >= contest.starts_at || ctx.has_role(Role::Admin) {
+        get_contest_challenges(contest_id)
+            .await?
+            .iter()
+            .map(challenge_json)
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let participants = get_contest_participants(contest_id).await?;
+
+    let mut json = contest_json(&contest);
+    json["running"] = Value::from(is_running(&contest, now));
+    json["registered"] = Value::from(
+        participants
+            .iter()
+            .any(|(user_id, _)| *user_id == ctx.user_id()),
+    );
+    json["participants"] = Value::from(participants.len());
+    json["challenges"] = Value::from(challenges);
+    Ok(Json(json))
+}
+
+pub async fn create_contest(
+    ctx: Ctx,
+    Json(payload): Json<ContestPayload>,
+) -> Result<(StatusCode, Json<Value>)> {
+    let unique: HashSet<&Uuid> = payload.challenges.iter().collect();
+    if payload.name.trim().is_empty()
+        || !is_valid_window(payload.starts_at, payload.ends_at, payload.freeze_minutes)
+        || unique.len() != payload.challenges.len()
+    {
+        return Err(Error::InvalidContest.into());
+    }
+    for challenge_id in &payload.challenges {
+        get_challenge(*challenge_id)
+            .await?
+            .ok_or(Error::ChallengeNotFound)?;
+    }
+
+    let contest_id = insert_contest(
+        NewContest {
+            name: payload.name.trim(),
+            description: &payload.description,
+            scoring: payload.scoring,
+            starts_at: payload.starts_at,
+            ends_at: payload.ends_at,
+            freeze_minutes: payload.freeze_minutes,
+            created_by: Some(ctx.user_id()),
+        },
+        &payload.challenges,
+    )
+    .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({ "contest_id": contest_id })),
+    ))
+}
+
+pub async fn edit_contest(
+    _ctx: Ctx,
+    Path(contest_id): Path<Uuid>,
+    Json(payload): Json<ContestUpdate>,
+) -> Result<Json<Value>> {
+    let contest = find_contest(contest_id).await?;
+    if payload
+        .name
+        .as_deref()
+        .is_some_and(|name| name.trim().is_empty())
+        || !is_valid_window(
+            payload.starts_at.unwrap_or(contest.starts_at),
+            payload.ends_at.unwrap_or(contest.ends_at),
+            payload.freeze_minutes.unwrap_or(contest.freeze_minutes),
+        )
+    {
+        return Err(Error::InvalidContest.into());
+    }
+
+    let changes = ContestChanges {
+        name: payload.name.map(|name| name.trim().to_string()),
+        description: payload.description,
+        scoring: payload.scoring,
+        starts_at: payload.starts_at,
+        ends_at: payload.ends_at,
+        freeze_minutes: payload.freeze_minutes,
+    };
+    if !update_contest(contest_id, changes).await? {
+        return Err(Error::ContestNotFound.into());
+    }
+
+    Ok(Json(contest_json(&find_contest(contest_id).await?)))
+}
+
+pub async fn remove_contest(_ctx: Ctx, Path(contest_id): Path<Uuid>) -> Result<Json<Value>> {
+    if !delete_contest(contest_id).await? {
+        return Err(Error::ContestNotFound.into());
+    }
+    Ok(Json(
+        json!({ "status": "deleted", "contest_id": contest_id }),
+    ))
+}
+
+pub async fn add_contest_challenge(
+    _ctx: Ctx,
+    Path((contest_id, challenge_id)): Path<(Uuid, Uuid)>,
+    Json(payload): Json<ContestChallengePayload>,
+) -> Result<Json<Value>> {
+    find_contest(contest_id).await?;
+    get_challenge(challenge_id)
+        .await?
+        .ok_or(Error::ChallengeNotFound)?;
+
+    upsert_contest_challenge(NewContestChallenge {
+        contest_id,
+        challenge_id,
+        position: payload.position,
+    })
+    .await?;
+
+    Ok(Json(json!({
+        "status": "added",
+        "contest_id": contest_id,
+        "challenge_id": challenge_id,
+    })))
+}
+
+pub async fn remove_contest_challenge(
+    _ctx: Ctx,
+    Path((contest_id, challenge_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<Value>> {
+    if !delete_contest_challenge(contest_id, challenge_id).await? {
+        return Err(Error::ChallengeNotFound.into());
+    }
+    Ok(Json(json!({
+        "status": "removed",
+        "contest_id": contest_id,
+        "challenge_id": challenge_id,
+    })))
+}
+
+// Open until the contest ends, late participants just have less time
+pub async fn register_for_contest(ctx: Ctx, Path(contest_id): Path<Uuid>) -> Result<Json<Value>> {
+    let contest = find_contest(contest_id).await?;
+    if Utc::now().naive_utc() >= contest.ends_at {
+        return Err(Error::ContestNotRunning.into());
+    }
+
+    insert_contest_participant(contest_id, ctx.user_id()).await?;
+    Ok(Json(json!({
+        "status": "registered",
+        "contest_id": contest_id,
+    })))
+}
+
+// Graded like a regular submission, unpublished challenges included
+pub async fn submit_contest_solution(
+    State(state): State<AppState>,
+    ctx: Ctx,
+    Path((contest_id, challenge_id)): Path<(Uuid, Uuid)>,
+    multipart: Multipart,
+) -> Result<(StatusCode, Json<Value>)> {
+    let contest = find_contest(contest_id).await?;
+    if !is_running(&contest, Utc::now().naive_utc()) {
+        return Err(Error::ContestNotRunning.into());
+    }
+    if get_contest_participant(contest_id, ctx.user_id())
+        .await?
+        .is_none()
+    {
+        return Err(Error::NotRegistered.into());
+    }
+
+    // Games have no verdict to put on a scoreboard
+    let challenge = get_contest_challenges(contest_id)
+        .await?
+        .into_iter()
+        .find(|challenge| challenge.id == challenge_id)
+        .ok_or(Error::ChallengeNotFound)?;
+    if challenge.game.is_some() {
+        return Err(Error::InvalidChallenge.into());
+    }
+
+    let job = challenge_job(&ctx, &challenge, multipart).await?;
+    let file_id = job.file_id.ok_or(Error::InternalServerError)?;
+
+    // Stored before the job is queued, so no judged job is ever left without one
+    let submission_id = insert_contest_submission(NewContestSubmission {
+        contest_id,
+        challenge_id,
+        user_uuid: ctx.user_id(),
+        file_id,
+    })
+    .await?;
+    let (status, Json(mut json)) = match queue_job(&state, job).await {
+        Ok(queued) => queued,
+        Err(e) => {
+            delete_contest_submission(submission_id).await?;
+            return Err(e);
+        }
+    };
+
+    json["contest_id"] = Value::from(contest_id.to_string());
+    json["submission_id"] = Value::from(submission_id.to_string());
+    Ok((status, Json(json)))
+}
+
+// Admins always see the live scoreboard
+pub async fn get_contest_scoreboard(ctx: Ctx, Path(contest_id): Path<Uuid>) -> Result<Json<Value>> {
+    let contest = find_contest(contest_id).await?;
+    let now = Utc::now().naive_utc();
+    let frozen_at = freeze_time(contest.starts_at, contest.ends_at, contest.freeze_minutes)
+        .filter(|frozen_at| *frozen_at <= now && now < contest.ends_at);
+    let frozen_at = if ctx.has_role(Role::Admin) {
+        None
+    } else {
+        frozen_at
+    };
+
+    let challenges: Vec<Uuid> = get_contest_challenges(contest_id)
+        .await?
+        .iter()
+        .map(|challenge| challenge.id)
+        .collect();
+    let participants = get_contest_participants(contest_id).await?;
+    let attempts: Vec<Attempt> = get_contest_submissions(contest_id)
+        .await?
+        .iter()
+        .filter_map(|(submission, simulation)| {
+            Attempt::from_submission(submission, simulation.as_ref())
+        })
+        .collect();
+
+    let rows = scoreboard(
+        contest.scoring,
+        contest.starts_at,
+        frozen_at,
+        &challenges,
+        &participants,
+        &attempts,
+    );
+
+    Ok(Json(json!({
+        "contest_id": contest_id,
+        "scoring": contest.scoring,
+        "frozen": frozen_at.is_some(),
+        "frozen_at": frozen_at,
+        "challenges": challenges,
+        "rows": rows,
+    })))
+}
@@ -1,12 +1,18 @@
 use crate::{
     database::{connection::upload_file, File},
+    docker::languages::Language,
     Result,
 };
 use chrono::{NaiveDateTime, Utc};
 use md5;
 use uuid::Uuid;
 
-pub async fn upload(content: Vec<u8>, user_id: Uuid, name: String) -> Result<Uuid> {
+pub async fn upload(
+    content: Vec<u8>,
+    user_id: Uuid,
+    name: String,
+    language: Language,
+) -> Result<Uuid> {
     // Calculate hash from content
 
     let hash_str = format!("{:x}", md5::compute(&content));
@@ -16,7 +22,7 @@ pub async fn upload(content: Vec<u8>, user_id: Uuid, name: String) -> Result<Uui
         file_name: name,
         file_hash: hash_str,
         file_size: content.len() as i32,
-        file_type: None,
+        file_type: Some(language.as_ref().to_string()),
         created_at: Utc::now().naive_utc(),
         last_modified_at: Utc::now().naive_utc(),
         file_content: Some(content),
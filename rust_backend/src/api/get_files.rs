@@ -1,17 +1,38 @@
+use axum::extract::Path;
 use axum::Json;
+use uuid::Uuid;
 
 use super::get_user;
-use super::root::FileInfo;
+use super::root::{FileInfo, FileResult};
+use crate::api::auth::roles::can_view_submissions_of;
 use crate::ctx::Ctx;
 use crate::database::connection::get_user;
-use crate::database::connection::{get_file_from_id, get_files_from_user};
-use crate::Result;
+use crate::database::connection::{
+    get_file_from_id, get_file_info, get_file_simulations, get_files_from_user,
+    get_latest_simulation,
+};
+use crate::docker::languages::Language;
+use crate::simulation::history::RunInfo;
+use crate::{Error, Result};
 
 pub async fn get_user_files(ctx: Ctx) -> Result<Json<Vec<FileInfo>>> {
-    let user_id = ctx.user_id();
-    let user = get_user(user_id).await?;
+    let user = get_user(ctx.user_id()).await?;
+    Ok(Json(files_of(user.id).await?))
+}
+
+// Files of a student of the teacher making the request
+pub async fn get_student_files(
+    ctx: Ctx,
+    Path(student_id): Path<Uuid>,
+) -> Result<Json<Vec<FileInfo>>> {
+    if !can_view_submissions_of(&ctx, student_id).await? {
+        return Err(Error::UserNotFound.into());
+    }
+    Ok(Json(files_of(student_id).await?))
+}
 
-    let file_ids = get_files_from_user(user.id).await?;
+async fn files_of(user_id: Uuid) -> Result<Vec<FileInfo>> {
+    let file_ids = get_files_from_user(user_id).await?;
 
     let mut json_of_files: Vec<FileInfo> = Vec::new();
     // create json like this {files: []}
@@ -21,10 +42,24 @@ pub async fn get_user_files(ctx: Ctx) -> Result<Json<Vec<FileInfo>>> {
             file_id: file.id.to_string(),
             file_name: file.file_name,
             time_submitted: file.last_modified_at,
-            result: None,
+            language: file.file_type.as_deref().and_then(Language::from_name),
+            result: get_latest_simulation(file.id).await?.map(FileResult::from),
         };
         json_of_files.push(new_file);
     }
 
-    Ok(Json(json_of_files))
+    Ok(json_of_files)
+}
+
+// Run history of a file, newest first. Only for users who may see its owner's submissions
+pub async fn get_file_runs(ctx: Ctx, Path(file_id): Path<Uuid>) -> Result<Json<Vec<RunInfo>>> {
+    let file = get_file_info(file_id)
+        .await
+        .map_err(|_| Error::FileNotFound)?;
+    if !can_view_submissions_of(&ctx, file.owner_uuid).await? {
+        return Err(Error::FileNotFound.into());
+    }
+
+    let runs = get_file_simulations(file_id).await?;
+    Ok(Json(runs.into_iter().map(RunInfo::from).collect()))
 }
@@ -0,0 +1,143 @@
+use axum::extract::{Path, State};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::Json;
+use futures::stream::{self, Stream, StreamExt};
+use serde_json::{json, Value};
+use tokio::sync::broadcast::{self, error::RecvError};
+use uuid::Uuid;
+
+use crate::api::auth::roles::can_view_submissions_of;
+use crate::ctx::Ctx;
+use crate::database::connection::{get_job, is_contest_file};
+use crate::database::models::{Job, JobState};
+use crate::docker::job::JobEvent;
+use crate::error::Error;
+use crate::{AppState, Result};
+
+// Jobs of other users are reported as missing so ids cannot be probed
+async fn get_owned_job(job_id: Uuid, ctx: &Ctx) -> Result<Job> {
+    get_job(job_id)
+        .await?
+        .filter(|job| job.user_uuid == ctx.user_id())
+        .ok_or_else(|| Error::JobNotFound.into())
+}
+
+// Like get_owned_job, but admins may watch any job and teachers those of their students
+async fn get_watchable_job(job_id: Uuid, ctx: &Ctx) -> Result<Job> {
+    let job = get_job(job_id).await?.ok_or(Error::JobNotFound)?;
+    if !can_view_submissions_of(ctx, job.user_uuid).await? {
+        return Err(Error::JobNotFound.into());
+    }
+    Ok(job)
+}
+
+pub async fn get_job_status(
+    State(state): State<AppState>,
+    ctx: Ctx,
+    Path(job_id): Path<Uuid>,
+) -> Result<Json<Value>> {
+    let job = get_owned_job(job_id, &ctx).await?;
+
+    let duration_ms = job
+        .started_at
+        .zip(job.finished_at)
+        .map(|(started_at, finished_at)| (finished_at - started_at).num_milliseconds());
+
+    Ok(Json(json!({
+        "job_id": job.id,
+        "state": job.state,
+        "language": job.language,
+        "file_id": job.file_id,
+        "challenge_id": job.challenge_id,
+        "created_at": job.created_at,
+        "started_at": job.started_at,
+        "finished_at": job.finished_at,
+        "duration_ms": duration_ms,
+        // Only set while the job is waiting for a worker
+        "queue": state.tm.position(job_id),
+        "output": job.output,
+    })))
+}
+
+pub async fn cancel_job(
+    State(state): State<AppState>,
+    ctx: Ctx,
+    Path(job_id): Path<Uuid>,
+) -> Result<Json<Value>> {
+    let job = get_owned_job(job_id, &ctx).await?;
+    if job.state.is_finished() {
+        return Err(Error::JobAlreadyFinished.into());
+    }
+    // A cancelled contest submission would never be judged, and never penalised
+    if let Some(file_id) = job.file_id {
+        if is_contest_file(file_id).await? {
+            return Err(Error::ContestSubmissionLocked.into());
+        }
+    }
+
+    // The job may have finished since it was loaded
+    if !state.tm.cancel(job_id, ctx.user_id()).await {
+        return Err(Error::JobAlreadyFinished.into());
+    }
+
+    Ok(Json(json!({
+        "status": "success",
+        "job_id": job_id,
+    })))
+}
+
+enum EventSource {
+    Live(broadcast::Receiver<JobEvent>),
+    Done(JobState),
+    Closed,
+}
+
+// Streams state changes, compiler output and program output of a job as
+// server-sent events. The stream ends with a `finished` event.
+pub async fn stream_job_events(
+    State(state): State<AppState>,
+    ctx: Ctx,
+    Path(job_id): Path<Uuid>,
+) -> Result<Sse<impl Stream<Item = std::result::Result<Event, axum::Error>>>> {
+    let job = get_watchable_job(job_id, &ctx).await?;
+
+    let source = match state.tm.subscribe(job_id) {
+        Some(receiver) => EventSource::Live(receiver),
+        // The job finished in the meantime, its final state is stored by now
+        None if !job.state.is_finished() => {
+            let job = get_job(job_id).await?.ok_or(Error::JobNotFound)?;
+            EventSource::Done(job.state)
+        }
+        None => EventSource::Done(job.state),
+    };
+
+    // Subscribers joining late start from the current state
+    let current = match source {
+        EventSource::Live(_) => Some(JobEvent::State { state: job.state }),
+        _ => None,
+    };
+
+    let events = stream::unfold(source, |source| async move {
+        match source {
+            EventSource::Live(mut receiver) => loop {
+                match receiver.recv().await {
+                    Ok(event @ JobEvent::Finished { .. }) => {
+                        return Some((event, EventSource::Closed))
+                    }
+                    Ok(event) => return Some((event, EventSource::Live(receiver))),
+                    // Slow subscribers miss output rather than holding up the job
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                }
+            },
+            EventSource::Done(state) => Some((JobEvent::Finished { state }, EventSource::Closed)),
+            EventSource::Closed => None,
+        }
+    });
+
+    let events = stream::iter(current)
+        .chain(events)
+        .map(|event| Event::default().event(event.name()).json_data(&event));
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
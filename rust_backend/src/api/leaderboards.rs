@@ -0,0 +1,132 @@
+use axum::extract::{Path, Query};
+use axum::Json;
+use chrono::{Datelike, Duration, NaiveDateTime, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::api::challenges::get_visible_challenge;
+use crate::ctx::Ctx;
+use crate::database::connection::get_leaderboard;
+use crate::database::models::LeaderboardStanding;
+use crate::Result;
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 100;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LeaderboardWindow {
+    // Since Monday 00:00 UTC
+    Week,
+    #[default]
+    All,
+}
+
+impl LeaderboardWindow {
+    pub fn since(self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        match self {
+            Self::Week => {
+                let monday =
+                    now.date() - Duration::days(i64::from(now.weekday().num_days_from_monday()));
+                Some(monday.and_time(NaiveTime::MIN))
+            }
+            Self::All => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct LeaderboardQuery {
+    #[serde(default)]
+    window: LeaderboardWindow,
+    // Starts at 1
+    page: Option<usize>,
+    per_page: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Page {
+    pub number: usize,
+    pub size: usize,
+}
+
+impl Page {
+    pub fn new(number: Option<usize>, size: Option<usize>) -> Self {
+        Self {
+            number: number.unwrap_or(1).max(1),
+            size: size.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE),
+        }
+    }
+
+    pub const fn offset(self) -> usize {
+        (self.number - 1).saturating_mul(self.size)
+    }
+}
+
+fn leaderboard_json(
+    window: LeaderboardWindow,
+    page: Page,
+    standings: &[LeaderboardStanding],
+    total: i64,
+) -> Value {
+    let entries: Vec<Value> = standings
+        .iter()
+        .enumerate()
+        .map(|(i, standing)| {
+            json!({
+                "rank": page.offset() + i + 1,
+                "user_id": standing.user_uuid,
+                "username": standing.username,
+                "score": standing.score,
+                "solved": standing.solved,
+                "cpu_time_ms": standing.cpu_time_ms,
+                "peak_memory_kb": standing.peak_memory_kb,
+                "submitted_at": standing.submitted_at,
+            })
+        })
+        .collect();
+
+    json!({
+        "window": window,
+        "page": page.number,
+        "per_page": page.size,
+        "total": total,
+        "entries": entries,
+    })
+}
+
+// Ranks and pages in the database, so only the requested page is loaded
+async fn leaderboard(challenge: Option<Uuid>, query: &LeaderboardQuery) -> Result<Value> {
+    let page = Page::new(query.page, query.per_page);
+    let since = query.window.since(Utc::now().naive_utc());
+    let (standings, total) = get_leaderboard(
+        challenge,
+        since,
+        i64::try_from(page.size).unwrap_or(i64::MAX),
+        i64::try_from(page.offset()).unwrap_or(i64::MAX),
+    )
+    .await?;
+    Ok(leaderboard_json(query.window, page, &standings, total))
+}
+
+// Best score of every user on the challenge
+pub async fn get_challenge_leaderboard(
+    ctx: Ctx,
+    Path(challenge_id): Path<Uuid>,
+    Query(query): Query<LeaderboardQuery>,
+) -> Result<Json<Value>> {
+    get_visible_challenge(challenge_id, &ctx).await?;
+
+    let mut json = leaderboard(Some(challenge_id), &query).await?;
+    json["challenge_id"] = json!(challenge_id);
+    Ok(Json(json))
+}
+
+// Best scores summed over all published challenges
+pub async fn get_global_leaderboard(
+    _ctx: Ctx,
+    Query(query): Query<LeaderboardQuery>,
+) -> Result<Json<Value>> {
+    Ok(Json(leaderboard(None, &query).await?))
+}
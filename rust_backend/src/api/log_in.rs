@@ -1,4 +1,5 @@
 use crate::api::auth::hashing::check_password;
+use crate::api::auth::tokens::{issue_token, SESSION_TOKEN_PREFIX};
 use crate::api::authentication::AUTH_TOKEN;
 use crate::error::AppError;
 use crate::error::ClientError;
@@ -54,7 +55,7 @@ pub async fn login_route(cookies: Cookies, payload: Json<LoginPayload>) -> Resul
         })));
     }
 
-    let token = generate_session_token();
+    let session = issue_token(SESSION_TOKEN_PREFIX);
 
     let mut now = OffsetDateTime::now_utc();
     now += Duration::days(7);
@@ -64,14 +65,17 @@ pub async fn login_route(cookies: Cookies, payload: Json<LoginPayload>) -> Resul
         None => return Err(anyhow::anyhow!("Failed to create expiration date").into()),
     };
 
+    // Only the hash is stored, the token is in the response and the cookie
     let session_token = UploadToken {
+        id: session.id,
         user_uuid: user.id,
-        token: token.clone(),
+        token_hash: session.hash,
         expiration_date: one_week,
     };
-    upload_session_token(session_token.clone()).await;
+    upload_session_token(session_token).await?;
 
-    let cookie_str = create_cookie(session_token);
+    let token = session.token;
+    let cookie_str = create_cookie(&token, one_week);
     let mut cookie = Cookie::new(AUTH_TOKEN, token.clone());
     cookie.set_http_only(true);
     cookie.set_path("/");
@@ -79,7 +83,8 @@ pub async fn login_route(cookies: Cookies, payload: Json<LoginPayload>) -> Resul
     cookie.set_expires(now);
     cookies.add(cookie);
 
-    info!("Created cookie: {}", &cookie_str);
+    // Only the id, anyone reading the logs could use the token itself
+    info!("Created session {}", session.id);
 
     // Create the success body.
     Ok(Json(json!({
@@ -91,20 +96,8 @@ pub async fn login_route(cookies: Cookies, payload: Json<LoginPayload>) -> Resul
     })))
 }
 
-pub fn generate_session_token() -> String {
-    let mut rng = rand::thread_rng();
-    let session_token: String = std::iter::repeat(())
-        .map(|()| rng.sample(Alphanumeric))
-        .map(char::from)
-        .take(30) // you can specify the length of the token here
-        .collect();
-    session_token
-}
-
 // Example cookie: sessionToken=abc123; Expires=Wed, 09 Jun 2021 10:18:14 GMT; HttpOnly; Path=/
-fn create_cookie(token: UploadToken) -> String {
-    let expiration_date = token.expiration_date;
-    let session_token = token.token;
+fn create_cookie(session_token: &str, expiration_date: NaiveDateTime) -> String {
     let cookie =
         format!("sessionToken={session_token}; Expires={expiration_date}; HttpOnly; Path=/");
     cookie
@@ -0,0 +1,39 @@
+use crate::api::authentication::{parse_token, AUTH_TOKEN};
+use crate::ctx::Ctx;
+use crate::database::connection::{delete_session_token, delete_user_sessions};
+use crate::Json;
+use crate::Result;
+use serde_json::{json, Value};
+use tower_cookies::{Cookie, Cookies};
+
+// Ends the session the request was made with
+pub async fn logout_route(_ctx: Ctx, cookies: Cookies) -> Result<Json<Value>> {
+    println!("->> {:<12} - api_logout", "HANDLER");
+
+    if let Some(cookie) = cookies.get(AUTH_TOKEN) {
+        let token = parse_token(cookie.value().to_string())?;
+        delete_session_token(&token).await?;
+    }
+    cookies.remove(Cookie::from(AUTH_TOKEN));
+
+    Ok(Json(json!({
+        "result": {
+            "success": true
+        }
+    })))
+}
+
+// Ends every session of the user, e.g. after a token was leaked
+pub async fn logout_all_route(ctx: Ctx, cookies: Cookies) -> Result<Json<Value>> {
+    println!("->> {:<12} - api_logout_all", "HANDLER");
+
+    let sessions = delete_user_sessions(ctx.user_id()).await?;
+    cookies.remove(Cookie::from(AUTH_TOKEN));
+
+    Ok(Json(json!({
+        "result": {
+            "success": true,
+            "sessions": sessions
+        }
+    })))
+}
@@ -1,12 +1,21 @@
 pub mod auth;
 pub mod authentication;
 pub mod backend;
+pub mod challenges;
+pub mod contests;
 pub mod create_account;
 pub mod file_upload;
 pub mod get_files;
 pub mod get_user;
 pub mod get_user_data;
+pub mod jobs;
+pub mod leaderboards;
 pub mod log_in;
+pub mod log_out;
+pub mod personal_tokens;
+pub mod queue;
+pub mod roles;
 pub mod root;
 pub mod run_code;
+pub mod tournaments;
 pub mod upload_file;
@@ -0,0 +1,163 @@
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::{Duration, NaiveDateTime, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::api::auth::tokens::{issue_token, IssuedToken, PERSONAL_TOKEN_PREFIX};
+use crate::ctx::Ctx;
+use crate::database::connection::{
+    delete_personal_token, get_personal_token, get_personal_tokens, insert_personal_token,
+    rotate_personal_token,
+};
+use crate::database::models::{NewPersonalAccessToken, PersonalAccessToken, TokenScope};
+use crate::error::Error;
+use crate::Result;
+
+const DEFAULT_EXPIRY_DAYS: i64 = 30;
+const MAX_EXPIRY_DAYS: i64 = 365;
+const MAX_NAME_LENGTH: usize = 255;
+
+#[derive(Deserialize)]
+pub struct PersonalTokenPayload {
+    name: String,
+    scopes: Vec<TokenScope>,
+    expires_in_days: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct RotatePayload {
+    // Defaults to the lifetime of the old token
+    expires_in_days: Option<i64>,
+}
+
+// Duplicates are dropped, None if nothing is left
+pub fn normalize_scopes(scopes: &[TokenScope]) -> Option<Vec<TokenScope>> {
+    let mut unique: Vec<TokenScope> = Vec::new();
+    for scope in scopes {
+        if !unique.contains(scope) {
+            unique.push(*scope);
+        }
+    }
+    (!unique.is_empty()).then_some(unique)
+}
+
+// Every token expires, at most a year after it was issued
+pub fn token_expiry(now: NaiveDateTime, days: Option<i64>) -> Option<NaiveDateTime> {
+    let days = days.unwrap_or(DEFAULT_EXPIRY_DAYS);
+    (1..=MAX_EXPIRY_DAYS)
+        .contains(&days)
+        .then(|| now + Duration::days(days))
+}
+
+fn token_json(token: &PersonalAccessToken, now: NaiveDateTime) -> Value {
+    json!({
+        "token_id": token.id,
+        "name": token.name,
+        "scopes": token.scopes,
+        "created_at": token.created_at,
+        "expires_at": token.expires_at,
+        "expired": token.expires_at <= now,
+        "last_used_at": token.last_used_at,
+    })
+}
+
+// The only time the token itself is shown
+fn issued_json(
+    issued: &IssuedToken,
+    name: &str,
+    scopes: &[TokenScope],
+    expires_at: NaiveDateTime,
+) -> Value {
+    json!({
+        "token_id": issued.id,
+        "token": issued.token,
+        "name": name,
+        "scopes": scopes,
+        "expires_at": expires_at,
+    })
+}
+
+pub async fn list_personal_tokens(ctx: Ctx) -> Result<Json<Value>> {
+    let now = Utc::now().naive_utc();
+    let tokens = get_personal_tokens(ctx.user_id()).await?;
+    Ok(Json(json!({
+        "tokens": tokens.iter().map(|token| token_json(token, now)).collect::<Vec<_>>(),
+    })))
+}
+
+pub async fn create_personal_token(
+    ctx: Ctx,
+    Json(payload): Json<PersonalTokenPayload>,
+) -> Result<(StatusCode, Json<Value>)> {
+    let name = payload.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(Error::InvalidPersonalToken.into());
+    }
+    let scopes = normalize_scopes(&payload.scopes).ok_or(Error::InvalidPersonalToken)?;
+    let now = Utc::now().naive_utc();
+    let expires_at =
+        token_expiry(now, payload.expires_in_days).ok_or(Error::InvalidPersonalToken)?;
+
+    let issued = issue_token(PERSONAL_TOKEN_PREFIX);
+    insert_personal_token(NewPersonalAccessToken {
+        id: issued.id,
+        user_uuid: ctx.user_id(),
+        name,
+        token_hash: &issued.hash,
+        scopes: &scopes,
+        created_at: now,
+        expires_at,
+    })
+    .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(issued_json(&issued, name, &scopes, expires_at)),
+    ))
+}
+
+// Replaces the token with a new one with the same name and scopes
+pub async fn rotate_personal_token_route(
+    ctx: Ctx,
+    Path(token_id): Path<Uuid>,
+    payload: Option<Json<RotatePayload>>,
+) -> Result<(StatusCode, Json<Value>)> {
+    let token = get_personal_token(token_id, ctx.user_id())
+        .await?
+        .ok_or(Error::PersonalTokenNotFound)?;
+    let days = payload
+        .and_then(|Json(payload)| payload.expires_in_days)
+        .unwrap_or_else(|| (token.expires_at - token.created_at).num_days().max(1));
+    let now = Utc::now().naive_utc();
+    let expires_at = token_expiry(now, Some(days)).ok_or(Error::InvalidPersonalToken)?;
+
+    let issued = issue_token(PERSONAL_TOKEN_PREFIX);
+    let replacement = NewPersonalAccessToken {
+        id: issued.id,
+        user_uuid: ctx.user_id(),
+        name: &token.name,
+        token_hash: &issued.hash,
+        scopes: &token.scopes,
+        created_at: now,
+        expires_at,
+    };
+    // Revoked in the meantime
+    if !rotate_personal_token(token_id, replacement).await? {
+        return Err(Error::PersonalTokenNotFound.into());
+    }
+
+    Ok((
+        StatusCode::CREATED,
+        Json(issued_json(&issued, &token.name, &token.scopes, expires_at)),
+    ))
+}
+
+pub async fn revoke_personal_token(ctx: Ctx, Path(token_id): Path<Uuid>) -> Result<Json<Value>> {
+    if !delete_personal_token(token_id, ctx.user_id()).await? {
+        return Err(Error::PersonalTokenNotFound.into());
+    }
+    Ok(Json(json!({ "status": "revoked", "token_id": token_id })))
+}
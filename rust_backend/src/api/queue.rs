@@ -0,0 +1,14 @@
+use axum::extract::State;
+use axum::Json;
+
+use crate::ctx::Ctx;
+use crate::tasks::QueuePosition;
+use crate::{AppState, Result};
+
+// Queue positions and estimated start times of the caller's jobs
+pub async fn get_queue(
+    State(state): State<AppState>,
+    ctx: Ctx,
+) -> Result<Json<Vec<QueuePosition>>> {
+    Ok(Json(state.tm.user_positions(ctx.user_id())))
+}
@@ -0,0 +1,88 @@
+use axum::extract::Path;
+use axum::Json;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::ctx::Ctx;
+use crate::database::connection::{
+    delete_teacher_student, get_students, get_user, insert_teacher_student, update_user_role,
+};
+use crate::database::models::{NewTeacherStudent, Role, User};
+use crate::error::Error;
+use crate::Result;
+
+#[derive(Deserialize)]
+pub struct RolePayload {
+    role: Role,
+}
+
+async fn find_user(user_id: Uuid) -> Result<User> {
+    Ok(get_user(user_id).await.map_err(|_| Error::UserNotFound)?)
+}
+
+// Admins cannot demote themselves, so they cannot lock themselves out
+pub async fn set_user_role(
+    ctx: Ctx,
+    Path(user_id): Path<Uuid>,
+    Json(payload): Json<RolePayload>,
+) -> Result<Json<Value>> {
+    if user_id == ctx.user_id() && payload.role != Role::Admin {
+        return Err(Error::InvalidRole.into());
+    }
+    if !update_user_role(user_id, payload.role).await? {
+        return Err(Error::UserNotFound.into());
+    }
+
+    Ok(Json(json!({
+        "user_id": user_id,
+        "role": payload.role,
+    })))
+}
+
+pub async fn add_student(
+    _ctx: Ctx,
+    Path((teacher_id, student_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<Value>> {
+    let teacher = find_user(teacher_id).await?;
+    find_user(student_id).await?;
+    if !teacher.role.includes(Role::Teacher) || teacher_id == student_id {
+        return Err(Error::InvalidRole.into());
+    }
+
+    insert_teacher_student(NewTeacherStudent {
+        teacher_uuid: teacher_id,
+        student_uuid: student_id,
+    })
+    .await?;
+
+    Ok(Json(json!({
+        "status": "added",
+        "teacher_id": teacher_id,
+        "student_id": student_id,
+    })))
+}
+
+pub async fn remove_student(
+    _ctx: Ctx,
+    Path((teacher_id, student_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<Value>> {
+    if !delete_teacher_student(teacher_id, student_id).await? {
+        return Err(Error::UserNotFound.into());
+    }
+    Ok(Json(json!({
+        "status": "removed",
+        "teacher_id": teacher_id,
+        "student_id": student_id,
+    })))
+}
+
+// Students of the teacher making the request
+pub async fn list_students(ctx: Ctx) -> Result<Json<Value>> {
+    let students: Vec<Value> = get_students(ctx.user_id())
+        .await?
+        .into_iter()
+        .map(|(user_id, username)| json!({ "user_id": user_id, "username": username }))
+        .collect();
+    Ok(Json(json!({ "students": students })))
+}
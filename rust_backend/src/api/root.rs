@@ -22,6 +22,7 @@ use std::time::SystemTime;
 use uuid::Uuid;
 
 use crate::api::backend::server_status::ServerStatus;
+use crate::docker::languages::Language;
 
 // basic handler that responds with a static string
 pub async fn root() -> Result<Json<Value>> {
@@ -43,6 +44,7 @@ pub struct FileInfo {
     pub file_id: String,
     pub file_name: String,
     pub time_submitted: NaiveDateTime,
+    pub language: Option<Language>,
     pub result: Option<FileResult>,
 }
 
@@ -54,6 +56,6 @@ pub struct FileResult {
     pub success: bool,
 }
 
-pub async fn get_server_status(headers: HeaderMap) -> Result<Json<ServerStatus>> {
+pub async fn get_server_status() -> Result<Json<ServerStatus>> {
     Ok(Json(ServerStatus::new().await))
 }
@@ -3,21 +3,32 @@ use std::{
     sync::Arc,
 };
 
+use crate::{
+    api::file_upload::upload,
+    error::{AppError, ClientError},
+    tasks::{CodeJob, JobOutput},
+    AppState, Error, Json,
+};
 use crate::{ctx::Ctx, docker::api::run_preset, schema::session_tokens::user_uuid};
 use crate::{
-    database::connection::get_file_from_id,
+    database::{
+        connection::{delete_job, get_file_from_id, insert_job},
+        models::{JobState, NewJob},
+    },
     docker::{
-        api::{gcc_container, ContainerOutput},
+        api::{compile_preset, CompileResult, ContainerOutput, RunOutcome},
         common::{extract_file_from_tar_archive, print_containers},
-        profiles::{CodeRunnerPreset, CODE_RUNNER_PRESET, COMPILER_PRESET, HELLO_WORLD_PRESET},
+        job::SandboxJob,
+        languages::Language,
+        limits::ResourceLimits,
+        profiles::{language_presets, CodeRunnerPreset, HELLO_WORLD_PRESET},
     },
 };
-use crate::{error::AppError, Json};
 
 use argon2::password_hash::Output;
 use axum::{
     debug_handler,
-    extract::{self, Multipart},
+    extract::{self, Multipart, State},
     http::StatusCode,
 };
 use serde::Deserialize;
@@ -29,50 +40,219 @@ use tokio::{
 };
 use uuid::Uuid;
 
-async fn extract_file_from_multipart(
+// Larger inputs belong in a test case
+const MAX_STDIN_BYTES: usize = 1024 * 1024;
+
+pub struct SubmittedProgram {
+    pub source: Vec<u8>,
+    pub file_name: Option<String>,
+    pub language: Language,
+    pub stdin: Vec<u8>,
+}
+
+pub async fn extract_program_from_multipart(
     mut multipart: Multipart,
-) -> std::result::Result<File, anyhow::Error> {
-    let mut file = File::from_std(tempfile()?);
+) -> std::result::Result<SubmittedProgram, anyhow::Error> {
+    let mut source = Vec::new();
+    let mut language = None;
+    let mut file_name = None;
+    let mut stdin = Vec::new();
 
     while let Ok(Some(mut field)) = multipart.next_field().await {
         let name = match field.name() {
-            Some(name) => name,
+            Some(name) => name.to_string(),
             None => {
                 continue;
             }
         };
-        let data = field.bytes().await?;
 
-        // Write data to the file
-        file.write_all(&data).await?;
-        file.flush().await?;
+        if name == "language" {
+            let value = field.text().await?;
+            language = Some(Language::from_name(&value).ok_or(Error::UnsupportedLanguage)?);
+            continue;
+        }
+
+        // Written to the program's stdin, as text or as a file
+        if name == "stdin" {
+            let data = field.bytes().await?;
+            if data.len() > MAX_STDIN_BYTES {
+                return Err(Error::InputTooLarge.into());
+            }
+            stdin = data.to_vec();
+            continue;
+        }
+
+        file_name = field.file_name().map(ToString::to_string).or(file_name);
+        let data = field.bytes().await?;
+        source.extend_from_slice(&data);
     }
 
-    file.seek(std::io::SeekFrom::Start(0)).await?;
+    // Fall back to the file extension if no language was given
+    let language = language
+        .or_else(|| file_name.as_deref().and_then(Language::from_filename))
+        .unwrap_or_default();
+
+    Ok(SubmittedProgram {
+        source,
+        file_name,
+        language,
+        stdin,
+    })
+}
 
-    Ok(file)
+// The submission is kept as a file so its runs can be looked up later
+pub async fn store_program(ctx: &Ctx, program: &SubmittedProgram) -> Result<Uuid, AppError> {
+    let file_name = program
+        .file_name
+        .clone()
+        .unwrap_or_else(|| program.language.info().source_file.to_string());
+    let file_id = upload(
+        program.source.clone(),
+        ctx.user_id(),
+        file_name,
+        program.language,
+    )
+    .await?;
+    Ok(file_id)
 }
 
-pub async fn build_and_run(ctx: Ctx, mut multipart: Multipart) -> Result<Json<Value>, AppError> {
-    // Create the file outside the loop
-    let mut file = extract_file_from_multipart(multipart).await?;
+// Stores the job and queues it, poll GET /jobs/:job_id for the result
+pub async fn queue_job(
+    state: &AppState,
+    job: CodeJob,
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let job_id = job.id;
+    let language = job.language;
+    let file_id = job.file_id;
+    let challenge_id = job.challenge_id;
 
-    // TODO Return build errors to user
-    let mut artifact_file = build_file(file).await?;
+    insert_job(NewJob {
+        id: job.id,
+        user_uuid: job.user_id,
+        file_id: job.file_id,
+        language: job.language.as_ref(),
+        source: &job.source,
+        test_cases: if job.test_cases.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_value(&job.test_cases)?)
+        },
+        stdin: &job.stdin,
+        challenge_id: job.challenge_id,
+        limits: job.limits.map(serde_json::to_value).transpose()?,
+        checker: job.checker.as_ref().map(serde_json::to_value).transpose()?,
+        game: job.game.as_ref().map(serde_json::to_value).transpose()?,
+        scoring: job.scoring.map(serde_json::to_value).transpose()?,
+    })
+    .await?;
 
-    let output = run_file(artifact_file).await?;
-    let output_log = output
-        .logs
-        .last()
-        .map_or_else(|| "".to_string(), |log| log.to_string().trim().to_string());
+    if state.tm.enqueue(job).is_err() {
+        delete_job(job_id).await?;
+        return Err(Error::QueueFull.into());
+    }
 
     let json = Json(json!({
-        "message": "Successfully uploaded file",
-        "status": "success",
-        "output": output_log,
+        "status": "queued",
+        "job_id": job_id,
+        "file_id": file_id,
+        "challenge_id": challenge_id,
+        "language": language,
+        "queue": state.tm.position(job_id),
     }));
 
-    Ok(json)
+    Ok((StatusCode::ACCEPTED, json))
+}
+
+// Runs the program once without grading it
+pub async fn build_and_run(
+    State(state): State<AppState>,
+    ctx: Ctx,
+    multipart: Multipart,
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let program = extract_program_from_multipart(multipart).await?;
+    let file_id = store_program(&ctx, &program).await?;
+
+    let job = CodeJob::new(ctx.user_id(), program.language, program.source)
+        .with_file(file_id)
+        .with_stdin(program.stdin);
+
+    queue_job(&state, job).await
+}
+
+// The final state of a job and the output stored with it
+pub fn job_output_json(language: Language, output: JobOutput) -> (JobState, Value) {
+    // Losing a game is a result like any other
+    if let Some(game) = output.game {
+        let json = json!({
+            "message": "Game played",
+            "status": "played",
+            "language": language,
+            "compile": output.compile,
+            "winner": game.winner,
+            "reason": game.reason,
+            "moves": game.moves,
+            "stderr": game.stderr,
+            "time_ms": game.time_ms,
+            "cpu_time_ms": game.cpu_time_ms,
+            "peak_memory_kb": game.peak_memory_kb,
+        });
+        return (JobState::Finished, json);
+    }
+
+    // Wrong answers are a result like any other, the job itself finished
+    if let Some(grading) = output.grading {
+        let json = json!({
+            "message": "Program graded",
+            "status": "graded",
+            "language": language,
+            "compile": output.compile,
+            "verdict": grading.verdict,
+            "passed": grading.passed,
+            "total": grading.total,
+            "score": grading.score,
+            "cases": grading.cases,
+            // The 1-100 score and what it was made of
+            "scoring": output.score,
+        });
+        return (JobState::Finished, json);
+    }
+
+    // Build errors are the submitter's fault, not the server's
+    let Some(run) = output.run else {
+        let json = json!({
+            "message": "Compilation failed",
+            "status": "compilation_failed",
+            "error": ClientError::COMPILATION_FAILED.as_ref(),
+            "language": language,
+            "compile": output.compile,
+        });
+        return (JobState::Failed, json);
+    };
+
+    let state = match run.outcome {
+        RunOutcome::TimedOut => JobState::TimedOut,
+        _ => JobState::Finished,
+    };
+
+    let json = json!({
+        "message": "Program finished",
+        "status": "success",
+        "language": language,
+        "compile": output.compile,
+        "outcome": run.outcome,
+        "exit_code": run.exit_code,
+        "stdout": run.output.stdout_lossy(),
+        "stderr": run.output.stderr_lossy(),
+        "truncated": run.output.truncated,
+        "metrics": run.metrics.map(|metrics| json!({
+            "cpu_user_ms": metrics.cpu_user.as_millis(),
+            "cpu_system_ms": metrics.cpu_system.as_millis(),
+            "wall_time_ms": metrics.wall_time.as_millis(),
+            "peak_memory_kb": metrics.peak_memory_kb(),
+        })),
+    });
+
+    (state, json)
 }
 
 pub async fn run_hello_world_test() -> Result<(), anyhow::Error> {
@@ -82,10 +262,13 @@ pub async fn run_hello_world_test() -> Result<(), anyhow::Error> {
         crate::Error::InternalServerError
     })?;
 
-    let mut artifact_file = build_file(file).await.map_err(|e| {
+    let job = SandboxJob::anonymous();
+
+    let compile_result = build_file(file, Language::C, &job).await.map_err(|e| {
         error!("Failed to build file: {}", e);
         crate::Error::InternalServerError
     })?;
+    let mut artifact_file = compile_result.artifact.ok_or(Error::CompilationFailed)?;
 
     // Print artifact file size
     let mut buffer = Vec::new();
@@ -95,32 +278,52 @@ pub async fn run_hello_world_test() -> Result<(), anyhow::Error> {
     })?;
     info!("Artifact file size: {}", buffer.len());
 
-    let status = run_file(artifact_file).await.map_err(|e| {
-        error!("Failed to run file: {}", e);
-        crate::Error::InternalServerError
-    })?;
+    let status = run_file(artifact_file, Language::C, None, &[], &job)
+        .await
+        .map_err(|e| {
+            error!("Failed to run file: {}", e);
+            crate::Error::InternalServerError
+        })?;
 
     info!("Status: {:?}", status);
 
     Ok(())
 }
 
-pub async fn run_file(file: File) -> Result<ContainerOutput, anyhow::Error> {
-    let preset = CODE_RUNNER_PRESET;
-    let status = run_preset(file, preset).await.map_err(|e| {
+// Runs the artifact with the given stdin,
+// limits override the preset defaults, e.g. for a challenge
+pub async fn run_file(
+    file: File,
+    language: Language,
+    limits: Option<ResourceLimits>,
+    stdin: &[u8],
+    job: &SandboxJob,
+) -> Result<ContainerOutput, anyhow::Error> {
+    let (_, mut preset) = language_presets(language);
+    if let Some(limits) = limits {
+        preset = preset.with_limits(limits);
+    }
+    let status = run_preset(file, preset, stdin, job).await.map_err(|e| {
         error!("Failed to run file: {}", e);
         crate::Error::InternalServerError
     })?;
     Ok(status)
 }
 
-pub async fn build_file(file: File) -> Result<File, anyhow::Error> {
-    let preset: crate::docker::profiles::CompilerPreset = COMPILER_PRESET;
+// Compiles the source file, interpreted languages return the source unchanged
+pub async fn build_file(
+    file: File,
+    language: Language,
+    job: &SandboxJob,
+) -> Result<CompileResult, anyhow::Error> {
+    let Some(preset) = language_presets(language).0 else {
+        return Ok(CompileResult::skipped(file));
+    };
 
-    let mut bin = gcc_container(file, preset).await.map_err(|e| {
+    let result = compile_preset(file, preset, job).await.map_err(|e| {
         error!("Failed to build file: {}", e);
         crate::Error::InternalServerError
     })?;
 
-    Ok(bin)
+    Ok(result)
 }
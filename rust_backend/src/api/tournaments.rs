@@ -0,0 +1,265 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::ctx::Ctx;
+use crate::database::connection::{
+    get_challenge, get_file_info, get_match, get_matches, get_ratings, get_tournament,
+    get_tournament_entries, get_tournaments, insert_tournament, start_tournament,
+    upsert_tournament_entry,
+};
+use crate::database::models::{
+    Match, NewTournament, NewTournamentEntry, Tournament, TournamentFormat, TournamentState,
+};
+use crate::docker::languages::Language;
+use crate::error::Error;
+use crate::simulation::tournament::{round_robin_rounds, swiss_rounds};
+use crate::{AppState, Result};
+
+#[derive(Deserialize)]
+pub struct TournamentPayload {
+    challenge_id: Uuid,
+    name: String,
+    format: TournamentFormat,
+    // Swiss tournaments pick a number of rounds from the entries if missing,
+    // round robin always plays everyone once
+    rounds: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct EntryPayload {
+    file_id: Uuid,
+}
+
+fn tournament_json(tournament: &Tournament) -> Value {
+    json!({
+        "tournament_id": tournament.id,
+        "challenge_id": tournament.challenge_id,
+        "name": tournament.name,
+        "format": tournament.format,
+        "rounds": tournament.rounds,
+        "played_rounds": tournament.played_rounds,
+        "state": tournament.state,
+        "created_at": tournament.created_at,
+        "started_at": tournament.started_at,
+        "finished_at": tournament.finished_at,
+    })
+}
+
+fn match_json(game: &Match) -> Value {
+    json!({
+        "match_id": game.id,
+        "tournament_id": game.tournament_id,
+        "round": game.round,
+        "player_one": game.player_one,
+        "player_two": game.player_two,
+        // Missing for a draw
+        "winner": game.winner,
+        "reason": game.reason,
+        "played_at": game.played_at,
+    })
+}
+
+pub async fn list_tournaments(_ctx: Ctx) -> Result<Json<Value>> {
+    let tournaments = get_tournaments().await?;
+    Ok(Json(json!({
+        "tournaments": tournaments.iter().map(tournament_json).collect::<Vec<_>>(),
+    })))
+}
+
+// Standings and the matches played so far, transcripts are fetched per match
+pub async fn get_tournament_details(
+    _ctx: Ctx,
+    Path(tournament_id): Path<Uuid>,
+) -> Result<Json<Value>> {
+    let tournament = get_tournament(tournament_id)
+        .await?
+        .ok_or(Error::TournamentNotFound)?;
+    let standings: Vec<Value> = get_tournament_entries(tournament_id)
+        .await?
+        .iter()
+        .enumerate()
+        .map(|(rank, entry)| {
+            json!({
+                "rank": rank + 1,
+                "user_id": entry.user_uuid,
+                "file_id": entry.file_id,
+                "points": entry.points,
+            })
+        })
+        .collect();
+    let matches: Vec<Value> = get_matches(tournament_id)
+        .await?
+        .iter()
+        .map(match_json)
+        .collect();
+
+    let mut json = tournament_json(&tournament);
+    json["standings"] = Value::from(standings);
+    json["matches"] = Value::from(matches);
+    Ok(Json(json))
+}
+
+pub async fn create_tournament(
+    ctx: Ctx,
+    Json(payload): Json<TournamentPayload>,
+) -> Result<(StatusCode, Json<Value>)> {
+    if payload.name.trim().is_empty() || payload.rounds.is_some_and(|rounds| rounds < 1) {
+        return Err(Error::InvalidChallenge.into());
+    }
+
+    // Only programs that play against a referee can play each other
+    let challenge = get_challenge(payload.challenge_id)
+        .await?
+        .ok_or(Error::ChallengeNotFound)?;
+    if challenge.game.is_none() {
+        return Err(Error::InvalidChallenge.into());
+    }
+
+    let tournament_id = insert_tournament(NewTournament {
+        challenge_id: challenge.id,
+        name: payload.name.trim(),
+        format: payload.format,
+        // Zero until the tournament starts and the entries are known
+        rounds: match payload.format {
+            TournamentFormat::Swiss => payload.rounds.unwrap_or(0),
+            TournamentFormat::RoundRobin => 0,
+        },
+        created_by: Some(ctx.user_id()),
+    })
+    .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({ "tournament_id": tournament_id })),
+    ))
+}
+
+// Enters one of the user's uploaded programs, entering again replaces it
+pub async fn enter_tournament(
+    ctx: Ctx,
+    Path(tournament_id): Path<Uuid>,
+    Json(payload): Json<EntryPayload>,
+) -> Result<Json<Value>> {
+    let tournament = get_tournament(tournament_id)
+        .await?
+        .ok_or(Error::TournamentNotFound)?;
+    if tournament.state != TournamentState::Pending {
+        return Err(Error::TournamentAlreadyStarted.into());
+    }
+
+    let file = get_file_info(payload.file_id)
+        .await
+        .map_err(|_| Error::FileNotFound)?;
+    if file.owner_uuid != ctx.user_id() {
+        return Err(Error::FileNotFound.into());
+    }
+
+    let language = file
+        .file_type
+        .as_deref()
+        .and_then(Language::from_name)
+        .ok_or(Error::UnsupportedLanguage)?;
+    let challenge = get_challenge(tournament.challenge_id)
+        .await?
+        .ok_or(Error::ChallengeNotFound)?;
+    let allowed: Vec<Language> = challenge
+        .allowed_languages
+        .iter()
+        .flatten()
+        .filter_map(|name| Language::from_name(name))
+        .collect();
+    if !allowed.is_empty() && !allowed.contains(&language) {
+        return Err(Error::LanguageNotAllowed.into());
+    }
+
+    upsert_tournament_entry(NewTournamentEntry {
+        tournament_id,
+        user_uuid: ctx.user_id(),
+        file_id: file.id,
+    })
+    .await?;
+
+    Ok(Json(json!({
+        "status": "entered",
+        "tournament_id": tournament_id,
+        "file_id": file.id,
+    })))
+}
+
+// Closes the entries and plays the tournament in the background
+pub async fn begin_tournament(
+    State(state): State<AppState>,
+    _ctx: Ctx,
+    Path(tournament_id): Path<Uuid>,
+) -> Result<(StatusCode, Json<Value>)> {
+    let tournament = get_tournament(tournament_id)
+        .await?
+        .ok_or(Error::TournamentNotFound)?;
+    if tournament.state != TournamentState::Pending {
+        return Err(Error::TournamentAlreadyStarted.into());
+    }
+
+    let entries = get_tournament_entries(tournament_id).await?.len();
+    if entries < 2 {
+        return Err(Error::NotEnoughEntries.into());
+    }
+
+    let rounds = match tournament.format {
+        TournamentFormat::RoundRobin => round_robin_rounds(entries),
+        TournamentFormat::Swiss if tournament.rounds > 0 => tournament.rounds as usize,
+        TournamentFormat::Swiss => swiss_rounds(entries),
+    };
+    let rounds = i32::try_from(rounds).map_err(|_| Error::NotEnoughEntries)?;
+    start_tournament(tournament_id, rounds).await?;
+    state.tm.spawn_tournament(tournament_id);
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(json!({
+            "status": "running",
+            "tournament_id": tournament_id,
+            "entries": entries,
+            "rounds": rounds,
+        })),
+    ))
+}
+
+pub async fn get_match_details(_ctx: Ctx, Path(match_id): Path<Uuid>) -> Result<Json<Value>> {
+    let game = get_match(match_id).await?.ok_or(Error::MatchNotFound)?;
+    let mut json = match_json(&game);
+    json["transcript"] = game.transcript;
+    Ok(Json(json))
+}
+
+// Elo ratings from every tournament played on the challenge, best first
+pub async fn get_challenge_ratings(
+    _ctx: Ctx,
+    Path(challenge_id): Path<Uuid>,
+) -> Result<Json<Value>> {
+    get_challenge(challenge_id)
+        .await?
+        .ok_or(Error::ChallengeNotFound)?;
+    let ratings: Vec<Value> = get_ratings(challenge_id)
+        .await?
+        .iter()
+        .enumerate()
+        .map(|(rank, rating)| {
+            json!({
+                "rank": rank + 1,
+                "user_id": rating.user_uuid,
+                "rating": rating.rating.round(),
+                "games_played": rating.games_played,
+                "updated_at": rating.updated_at,
+            })
+        })
+        .collect();
+
+    Ok(Json(json!({
+        "challenge_id": challenge_id,
+        "ratings": ratings,
+    })))
+}
@@ -4,6 +4,7 @@ use serde_json::json;
 
 use super::root::FileInfo;
 use crate::ctx::Ctx;
+use crate::docker::languages::Language;
 use crate::error::Error;
 use crate::Result;
 
@@ -12,7 +13,19 @@ pub async fn upload(
     headers: axum::http::HeaderMap,
     mut multipart: Multipart,
 ) -> Result<Json<FileInfo>> {
-    if let Ok(Some(field)) = multipart.next_field().await {
+    let mut language = None;
+    let mut upload = None;
+
+    while let Ok(Some(field)) = multipart.next_field().await {
+        if field.name() == Some("language") {
+            let value = field.text().await.map_err(|e| {
+                error!("{:?}", e);
+                Error::InternalServerError
+            })?;
+            language = Some(Language::from_name(&value).ok_or(Error::UnsupportedLanguage)?);
+            continue;
+        }
+
         let name = field
             .file_name()
             .map(std::string::ToString::to_string)
@@ -21,22 +34,27 @@ pub async fn upload(
             error!("{:?}", e);
             Error::InternalServerError
         })?;
-        let file_id =
-            super::file_upload::upload(data.to_vec(), ctx.user_id(), name.clone()).await?;
-        let body = json!({
-            "status":"success",
-        });
-
-        let current_time = chrono::Utc::now().naive_utc();
-
-        let file_info = FileInfo {
-            file_id: file_id.to_string(),
-            file_name: name,
-            time_submitted: current_time,
-            result: None,
-        };
-        return Ok(axum::Json(file_info));
+        upload = Some((name, data));
     }
 
-    Err(Error::InternalServerError.into())
+    let (name, data) = upload.ok_or(Error::InternalServerError)?;
+
+    // Fall back to the file extension if no language was given
+    let language = language
+        .or_else(|| Language::from_filename(&name))
+        .ok_or(Error::UnsupportedLanguage)?;
+
+    let file_id =
+        super::file_upload::upload(data.to_vec(), ctx.user_id(), name.clone(), language).await?;
+
+    let current_time = chrono::Utc::now().naive_utc();
+
+    let file_info = FileInfo {
+        file_id: file_id.to_string(),
+        file_name: name,
+        time_submitted: current_time,
+        language: Some(language),
+        result: None,
+    };
+    Ok(axum::Json(file_info))
 }
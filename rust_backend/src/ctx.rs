@@ -1,14 +1,30 @@
 use uuid::Uuid;
 
+use crate::database::models::{Role, TokenScope};
+
 #[derive(Clone, Debug)]
 pub struct Ctx {
     user_id: Uuid,
+    role: Role,
+    // Set when the request was made with a personal access token
+    scopes: Option<Vec<TokenScope>>,
 }
 
 // Constructor.
 impl Ctx {
-    pub const fn new(user_id: Uuid) -> Self {
-        Self { user_id }
+    pub const fn new(user_id: Uuid, role: Role) -> Self {
+        Self {
+            user_id,
+            role,
+            scopes: None,
+        }
+    }
+
+    pub fn with_scopes(self, scopes: Vec<TokenScope>) -> Self {
+        Self {
+            scopes: Some(scopes),
+            ..self
+        }
     }
 }
 
@@ -17,4 +33,23 @@ impl Ctx {
     pub const fn user_id(&self) -> Uuid {
         self.user_id
     }
+
+    pub const fn role(&self) -> Role {
+        self.role
+    }
+
+    pub fn has_role(&self, required: Role) -> bool {
+        self.role.includes(required)
+    }
+
+    pub const fn is_personal_token(&self) -> bool {
+        self.scopes.is_some()
+    }
+
+    // Sessions may do anything the user can
+    pub fn allows(&self, scope: TokenScope) -> bool {
+        self.scopes
+            .as_ref()
+            .is_none_or(|scopes| scopes.contains(&scope))
+    }
 }
@@ -1,11 +1,21 @@
 use std::env;
 
-use crate::database::models::{NewSessionToken, NewUser, User};
+use crate::database::models::{
+    Challenge, ChallengeChanges, ChallengeTestCase, Contest, ContestChanges, ContestParticipant,
+    ContestSubmission, Job, JobScore, JobState, LeaderboardStanding, Match, NewChallenge,
+    NewChallengeTestCase, NewContest, NewContestChallenge, NewContestSubmission, NewJob, NewMatch,
+    NewPersonalAccessToken, NewSessionToken, NewSimulation, NewTeacherStudent, NewTournament,
+    NewTournamentEntry, NewUser, PersonalAccessToken, Rating, Role, Simulation, TokenScope,
+    Tournament, TournamentEntry, TournamentState, User,
+};
 
+use crate::api::auth::tokens::{
+    hash_token, token_id, verify_token, PERSONAL_TOKEN_PREFIX, SESSION_TOKEN_PREFIX,
+};
 use crate::database::{File, FileMetadata};
 use crate::Error;
 use crate::Result;
-use chrono::NaiveDateTime;
+use chrono::{NaiveDateTime, Utc};
 use diesel::pg::Pg;
 use diesel::prelude::*;
 
@@ -20,20 +30,13 @@ pub fn establish_connection() -> PgConnection {
     let connection_url = env::var("DB_URL").expect("DB_URL must be set");
 
     let mut conn_res = PgConnection::establish(&connection_url);
-    let mut conn = match conn_res {
+    match conn_res {
         Ok(conn) => conn,
         Err(err) => {
             error!("Error connecting to database: {}", err);
             panic!("Error connecting to database: {}", err);
         }
-    };
-    if cfg!(test) {
-        match conn.begin_test_transaction() {
-            Ok(_) => info!("Test transaction started"),
-            Err(err) => error!("Error starting test transaction: {}", err),
-        }
     }
-    conn
 }
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
@@ -128,8 +131,9 @@ pub async fn get_user_from_username(username_query: &str) -> Result<User> {
 
 #[derive(Clone)]
 pub struct UploadToken {
+    pub id: Uuid,
     pub user_uuid: Uuid,
-    pub token: String,
+    pub token_hash: String,
     pub expiration_date: NaiveDateTime,
 }
 
@@ -138,7 +142,8 @@ pub async fn upload_session_token(up_token: UploadToken) -> Result<()> {
     let mut conn = establish_connection();
 
     let new_token = NewSessionToken {
-        token: &up_token.token,
+        id: up_token.id,
+        token_hash: &up_token.token_hash,
         user_uuid: up_token.user_uuid,
         expiration_date: up_token.expiration_date,
     };
@@ -162,17 +167,35 @@ pub async fn get_user(user_id: Uuid) -> Result<User> {
 }
 
 // Get the user from the token, return a Result containing a Some(User) if the token is valid, None otherwise.
-pub async fn get_token_owner(token_str: &String) -> Result<Option<User>> {
-    use crate::schema::session_tokens::dsl::{session_tokens, token, user_uuid};
+// Expired tokens are an error, so the client can tell it has to log in again.
+pub async fn get_token_owner(token_str: &str) -> std::result::Result<Option<User>, Error> {
+    use crate::schema::session_tokens::dsl::{
+        expiration_date, id, session_tokens, token_hash, user_uuid,
+    };
+    let Some(session_id) = token_id(SESSION_TOKEN_PREFIX, token_str) else {
+        return Ok(None);
+    };
     let mut conn = establish_connection();
 
-    let result: Uuid = session_tokens
-        .filter(token.eq(token_str))
-        .select(user_uuid)
-        .first(&mut conn)
-        .map_err(|err| Error::DatabaseQueryFail)?;
+    let Some((owner, hash, expires_at)) = session_tokens
+        .filter(id.eq(session_id))
+        .select((user_uuid, token_hash, expiration_date))
+        .first::<(Uuid, String, NaiveDateTime)>(&mut conn)
+        .optional()
+        .map_err(|err| Error::DatabaseQueryFail)?
+    else {
+        return Ok(None);
+    };
+    if !verify_token(token_str, &hash) {
+        return Ok(None);
+    }
+    if expires_at <= Utc::now().naive_utc() {
+        return Err(Error::AuthFailTokenExpired);
+    }
 
-    let user = get_user(result).await?;
+    let user = get_user(owner)
+        .await
+        .map_err(|err| Error::DatabaseFailedToFindUser)?;
 
     if user.id == Uuid::nil() {
         return Ok(None);
@@ -181,6 +204,46 @@ pub async fn get_token_owner(token_str: &String) -> Result<Option<User>> {
     Ok(Some(user))
 }
 
+// Returns false if no session has the token
+pub async fn delete_session_token(token_str: &str) -> Result<bool> {
+    use crate::schema::session_tokens::dsl::{id, session_tokens, token_hash};
+    let Some(session_id) = token_id(SESSION_TOKEN_PREFIX, token_str) else {
+        return Ok(false);
+    };
+    let mut conn = establish_connection();
+
+    let deleted = diesel::delete(
+        session_tokens
+            .filter(id.eq(session_id))
+            .filter(token_hash.eq(hash_token(token_str))),
+    )
+    .execute(&mut conn)
+    .map_err(|err| Error::DatabaseQueryFail)?;
+
+    Ok(deleted > 0)
+}
+
+// Logs the user out everywhere, returns the number of sessions ended
+pub async fn delete_user_sessions(user_id: Uuid) -> Result<usize> {
+    use crate::schema::session_tokens::dsl::{session_tokens, user_uuid};
+    let mut conn = establish_connection();
+
+    Ok(diesel::delete(session_tokens.filter(user_uuid.eq(user_id)))
+        .execute(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+pub async fn delete_expired_sessions() -> Result<usize> {
+    use crate::schema::session_tokens::dsl::{expiration_date, session_tokens};
+    let mut conn = establish_connection();
+
+    Ok(
+        diesel::delete(session_tokens.filter(expiration_date.le(Utc::now().naive_utc())))
+            .execute(&mut conn)
+            .map_err(|err| Error::DatabaseQueryFail)?,
+    )
+}
+
 pub async fn get_files_from_user(user_id: Uuid) -> Result<Vec<Uuid>> {
     use crate::schema::files::dsl::{files, id, owner_uuid};
     let mut conn = establish_connection();
@@ -201,3 +264,901 @@ pub async fn get_file_from_id(file_id: Uuid) -> Result<File> {
         .first::<File>(&mut conn)
         .map_err(|err| Error::DatabaseQueryFail)?)
 }
+
+pub async fn insert_job(new_job: NewJob<'_>) -> Result<()> {
+    use crate::schema::jobs::dsl::jobs;
+    let mut conn = establish_connection();
+
+    diesel::insert_into(jobs)
+        .values(new_job)
+        .execute(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?;
+
+    Ok(())
+}
+
+pub async fn get_job(job_id: Uuid) -> Result<Option<Job>> {
+    use crate::schema::jobs::dsl::{id, jobs};
+    let mut conn = establish_connection();
+
+    Ok(jobs
+        .filter(id.eq(job_id))
+        .first::<Job>(&mut conn)
+        .optional()
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+pub async fn delete_job(job_id: Uuid) -> Result<()> {
+    use crate::schema::jobs::dsl::{id, jobs};
+    let mut conn = establish_connection();
+
+    diesel::delete(jobs.filter(id.eq(job_id)))
+        .execute(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?;
+
+    Ok(())
+}
+
+// Jobs that were queued or running when the backend stopped, oldest first
+pub async fn get_unfinished_jobs() -> Result<Vec<Job>> {
+    use crate::schema::jobs::dsl::{created_at, jobs, state};
+    let mut conn = establish_connection();
+
+    Ok(jobs
+        .filter(state.eq_any([JobState::Queued, JobState::Compiling, JobState::Running]))
+        .order(created_at.asc())
+        .load::<Job>(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+pub async fn set_job_state(job_id: Uuid, new_state: JobState) -> Result<()> {
+    use crate::schema::jobs::dsl::{id, jobs, state};
+    let mut conn = establish_connection();
+
+    diesel::update(jobs.filter(id.eq(job_id)))
+        .set(state.eq(new_state))
+        .execute(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?;
+
+    Ok(())
+}
+
+pub async fn start_job(job_id: Uuid) -> Result<()> {
+    use crate::schema::jobs::dsl::{id, jobs, started_at, state};
+    let mut conn = establish_connection();
+
+    diesel::update(jobs.filter(id.eq(job_id)))
+        .set((
+            state.eq(JobState::Compiling),
+            started_at.eq(Utc::now().naive_utc()),
+        ))
+        .execute(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?;
+
+    Ok(())
+}
+
+pub async fn finish_job(
+    job_id: Uuid,
+    new_state: JobState,
+    job_output: Option<serde_json::Value>,
+    job_score: Option<JobScore>,
+) -> Result<()> {
+    use crate::schema::jobs::dsl::{
+        cpu_time_ms, finished_at, id, jobs, output, peak_memory_kb, score, state,
+    };
+    let mut conn = establish_connection();
+
+    diesel::update(jobs.filter(id.eq(job_id)))
+        .set((
+            state.eq(new_state),
+            finished_at.eq(Utc::now().naive_utc()),
+            output.eq(job_output),
+            score.eq(job_score.map(|job_score| job_score.score)),
+            cpu_time_ms.eq(job_score.and_then(|job_score| job_score.cpu_time_ms)),
+            peak_memory_kb.eq(job_score.and_then(|job_score| job_score.peak_memory_kb)),
+        ))
+        .execute(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?;
+
+    Ok(())
+}
+
+pub async fn insert_simulation(new_simulation: NewSimulation) -> Result<i32> {
+    use crate::schema::simulations::dsl::{simulation_id, simulations};
+    let mut conn = establish_connection();
+
+    Ok(diesel::insert_into(simulations)
+        .values(new_simulation)
+        .returning(simulation_id)
+        .get_result(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+// Newest run first
+pub async fn get_file_simulations(file_id: Uuid) -> Result<Vec<Simulation>> {
+    use crate::schema::simulations::dsl::{ran_at, ran_file_id, simulations};
+    let mut conn = establish_connection();
+
+    Ok(simulations
+        .filter(ran_file_id.eq(file_id))
+        .order(ran_at.desc())
+        .load::<Simulation>(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+pub async fn get_latest_simulation(file_id: Uuid) -> Result<Option<Simulation>> {
+    use crate::schema::simulations::dsl::{ran_at, ran_file_id, simulations};
+    let mut conn = establish_connection();
+
+    Ok(simulations
+        .filter(ran_file_id.eq(file_id))
+        .order(ran_at.desc())
+        .first::<Simulation>(&mut conn)
+        .optional()
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+pub async fn insert_challenge(new_challenge: NewChallenge<'_>) -> Result<Uuid> {
+    use crate::schema::challenges::dsl::{challenges, id};
+    let mut conn = establish_connection();
+
+    Ok(diesel::insert_into(challenges)
+        .values(new_challenge)
+        .returning(id)
+        .get_result(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+pub async fn get_challenge(challenge_id: Uuid) -> Result<Option<Challenge>> {
+    use crate::schema::challenges::dsl::{challenges, id};
+    let mut conn = establish_connection();
+
+    Ok(challenges
+        .filter(id.eq(challenge_id))
+        .first::<Challenge>(&mut conn)
+        .optional()
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+// Newest first, unpublished challenges are only included for admins
+pub async fn get_challenges(include_unpublished: bool) -> Result<Vec<Challenge>> {
+    use crate::schema::challenges::dsl::{challenges, created_at, is_published};
+    let mut conn = establish_connection();
+
+    let mut query = challenges.order(created_at.desc()).into_boxed();
+    if !include_unpublished {
+        query = query.filter(is_published.eq(true));
+    }
+
+    Ok(query
+        .load::<Challenge>(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+// Returns false if the challenge does not exist
+pub async fn update_challenge(challenge_id: Uuid, changes: ChallengeChanges) -> Result<bool> {
+    use crate::schema::challenges::dsl::{challenges, id};
+    let mut conn = establish_connection();
+
+    let updated = diesel::update(challenges.filter(id.eq(challenge_id)))
+        .set(changes)
+        .execute(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?;
+
+    Ok(updated > 0)
+}
+
+// Test cases are deleted along with the challenge
+pub async fn delete_challenge(challenge_id: Uuid) -> Result<bool> {
+    use crate::schema::challenges::dsl::{challenges, id};
+    let mut conn = establish_connection();
+
+    let deleted = diesel::delete(challenges.filter(id.eq(challenge_id)))
+        .execute(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?;
+
+    Ok(deleted > 0)
+}
+
+pub async fn insert_test_case(new_test_case: NewChallengeTestCase<'_>) -> Result<Uuid> {
+    use crate::schema::challenge_test_cases::dsl::{challenge_test_cases, id};
+    let mut conn = establish_connection();
+
+    Ok(diesel::insert_into(challenge_test_cases)
+        .values(new_test_case)
+        .returning(id)
+        .get_result(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+// In the order they are run
+pub async fn get_test_cases(challenge: Uuid) -> Result<Vec<ChallengeTestCase>> {
+    use crate::schema::challenge_test_cases::dsl::{
+        challenge_id, challenge_test_cases, name, position,
+    };
+    let mut conn = establish_connection();
+
+    Ok(challenge_test_cases
+        .filter(challenge_id.eq(challenge))
+        .order((position.asc(), name.asc()))
+        .load::<ChallengeTestCase>(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+pub async fn delete_test_case(challenge: Uuid, test_case_id: Uuid) -> Result<bool> {
+    use crate::schema::challenge_test_cases::dsl::{challenge_id, challenge_test_cases, id};
+    let mut conn = establish_connection();
+
+    let deleted = diesel::delete(
+        challenge_test_cases.filter(id.eq(test_case_id).and(challenge_id.eq(challenge))),
+    )
+    .execute(&mut conn)
+    .map_err(|err| Error::DatabaseQueryFail)?;
+
+    Ok(deleted > 0)
+}
+
+pub async fn insert_tournament(new_tournament: NewTournament<'_>) -> Result<Uuid> {
+    use crate::schema::tournaments::dsl::{id, tournaments};
+    let mut conn = establish_connection();
+
+    Ok(diesel::insert_into(tournaments)
+        .values(new_tournament)
+        .returning(id)
+        .get_result(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+pub async fn get_tournament(tournament_id: Uuid) -> Result<Option<Tournament>> {
+    use crate::schema::tournaments::dsl::{id, tournaments};
+    let mut conn = establish_connection();
+
+    Ok(tournaments
+        .filter(id.eq(tournament_id))
+        .first::<Tournament>(&mut conn)
+        .optional()
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+// Newest first
+pub async fn get_tournaments() -> Result<Vec<Tournament>> {
+    use crate::schema::tournaments::dsl::{created_at, tournaments};
+    let mut conn = establish_connection();
+
+    Ok(tournaments
+        .order(created_at.desc())
+        .load::<Tournament>(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+// Tournaments that were running when the backend stopped
+pub async fn get_running_tournaments() -> Result<Vec<Tournament>> {
+    use crate::schema::tournaments::dsl::{state, tournaments};
+    let mut conn = establish_connection();
+
+    Ok(tournaments
+        .filter(state.eq(TournamentState::Running))
+        .load::<Tournament>(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+// The number of rounds is fixed once the tournament starts
+pub async fn start_tournament(tournament_id: Uuid, total_rounds: i32) -> Result<()> {
+    use crate::schema::tournaments::dsl::{id, rounds, started_at, state, tournaments};
+    let mut conn = establish_connection();
+
+    diesel::update(tournaments.filter(id.eq(tournament_id)))
+        .set((
+            state.eq(TournamentState::Running),
+            rounds.eq(total_rounds),
+            started_at.eq(Utc::now().naive_utc()),
+        ))
+        .execute(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?;
+
+    Ok(())
+}
+
+pub async fn finish_tournament(tournament_id: Uuid, new_state: TournamentState) -> Result<()> {
+    use crate::schema::tournaments::dsl::{finished_at, id, state, tournaments};
+    let mut conn = establish_connection();
+
+    diesel::update(tournaments.filter(id.eq(tournament_id)))
+        .set((state.eq(new_state), finished_at.eq(Utc::now().naive_utc())))
+        .execute(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?;
+
+    Ok(())
+}
+
+// Entering again replaces the submission
+pub async fn upsert_tournament_entry(entry: NewTournamentEntry) -> Result<()> {
+    use crate::schema::tournament_entries::dsl::{
+        file_id, tournament_entries, tournament_id, user_uuid,
+    };
+    let mut conn = establish_connection();
+
+    diesel::insert_into(tournament_entries)
+        .values(&entry)
+        .on_conflict((tournament_id, user_uuid))
+        .do_update()
+        .set(file_id.eq(entry.file_id))
+        .execute(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?;
+
+    Ok(())
+}
+
+// Standings, ties go to the earlier entry
+pub async fn get_tournament_entries(tournament: Uuid) -> Result<Vec<TournamentEntry>> {
+    use crate::schema::tournament_entries::dsl::{
+        created_at, points, tournament_entries, tournament_id,
+    };
+    let mut conn = establish_connection();
+
+    Ok(tournament_entries
+        .filter(tournament_id.eq(tournament))
+        .order((points.desc(), created_at.asc()))
+        .load::<TournamentEntry>(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+pub async fn get_matches(tournament: Uuid) -> Result<Vec<Match>> {
+    use crate::schema::matches::dsl::{matches, played_at, round, tournament_id};
+    let mut conn = establish_connection();
+
+    Ok(matches
+        .filter(tournament_id.eq(tournament))
+        .order((round.asc(), played_at.asc()))
+        .load::<Match>(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+pub async fn get_match(match_id: Uuid) -> Result<Option<Match>> {
+    use crate::schema::matches::dsl::{id, matches};
+    let mut conn = establish_connection();
+
+    Ok(matches
+        .filter(id.eq(match_id))
+        .first::<Match>(&mut conn)
+        .optional()
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+// Stores a played round at once, so an interrupted round is played again from the start
+pub async fn record_round(
+    tournament: Uuid,
+    round: i32,
+    new_matches: Vec<NewMatch>,
+    standings: &[(Uuid, f64)],
+    new_ratings: &[Rating],
+) -> Result<()> {
+    use crate::schema::{matches, ratings, tournament_entries, tournaments};
+    let mut conn = establish_connection();
+
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        diesel::insert_into(matches::table)
+            .values(&new_matches)
+            .execute(conn)?;
+
+        for (user, user_points) in standings {
+            diesel::update(
+                tournament_entries::table.filter(
+                    tournament_entries::tournament_id
+                        .eq(tournament)
+                        .and(tournament_entries::user_uuid.eq(user)),
+                ),
+            )
+            .set(tournament_entries::points.eq(user_points))
+            .execute(conn)?;
+        }
+
+        for rating in new_ratings {
+            diesel::insert_into(ratings::table)
+                .values(rating)
+                .on_conflict((ratings::user_uuid, ratings::challenge_id))
+                .do_update()
+                .set((
+                    ratings::rating.eq(rating.rating),
+                    ratings::games_played.eq(rating.games_played),
+                    ratings::updated_at.eq(rating.updated_at),
+                ))
+                .execute(conn)?;
+        }
+
+        diesel::update(tournaments::table.filter(tournaments::id.eq(tournament)))
+            .set(tournaments::played_rounds.eq(round))
+            .execute(conn)?;
+
+        Ok(())
+    })
+    .map_err(|err| Error::DatabaseQueryFail)?;
+
+    Ok(())
+}
+
+// Highest rating first
+pub async fn get_ratings(challenge: Uuid) -> Result<Vec<Rating>> {
+    use crate::schema::ratings::dsl::{challenge_id, rating, ratings};
+    let mut conn = establish_connection();
+
+    Ok(ratings
+        .filter(challenge_id.eq(challenge))
+        .order(rating.desc())
+        .load::<Rating>(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+// The best scored job of every user per challenge, submitted since $2.
+// Without a challenge in $1 every published challenge is included.
+// Contest submissions are left out, they would give away frozen results.
+// Ties go to the earlier and then the leaner submission.
+const BEST_RUNS: &str = "
+    WITH best AS (
+        SELECT DISTINCT ON (jobs.user_uuid, jobs.challenge_id)
+            jobs.user_uuid, jobs.score, jobs.cpu_time_ms, jobs.peak_memory_kb, jobs.created_at
+        FROM jobs
+        JOIN challenges ON challenges.id = jobs.challenge_id
+        WHERE jobs.score IS NOT NULL
+            AND (($1::UUID IS NULL AND challenges.is_published) OR jobs.challenge_id = $1)
+            AND NOT EXISTS (
+                SELECT 1 FROM contest_submissions WHERE contest_submissions.file_id = jobs.file_id
+            )
+            AND ($2::TIMESTAMP IS NULL OR jobs.created_at >= $2)
+        ORDER BY jobs.user_uuid, jobs.challenge_id, jobs.score DESC, jobs.created_at, jobs.cpu_time_ms
+    )";
+
+// One page of the best runs summed per user, best total first, and the number of users.
+// Unmeasured usage ranks below any measurement.
+pub async fn get_leaderboard(
+    challenge: Option<Uuid>,
+    since: Option<NaiveDateTime>,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<LeaderboardStanding>, i64)> {
+    use diesel::sql_types::{BigInt, Nullable, Timestamp};
+    let mut conn = establish_connection();
+
+    #[derive(QueryableByName)]
+    struct Total {
+        #[diesel(sql_type = BigInt)]
+        total: i64,
+    }
+
+    let standings = diesel::sql_query(format!(
+        "{BEST_RUNS}
+        SELECT best.user_uuid, users.username,
+            SUM(best.score)::INT8 AS score, COUNT(*) AS solved,
+            SUM(best.cpu_time_ms)::INT8 AS cpu_time_ms, MAX(best.peak_memory_kb) AS peak_memory_kb,
+            MAX(best.created_at) AS submitted_at
+        FROM best
+        JOIN users ON users.id = best.user_uuid
+        GROUP BY best.user_uuid, users.username
+        ORDER BY score DESC, submitted_at, cpu_time_ms, peak_memory_kb, best.user_uuid
+        LIMIT $3 OFFSET $4"
+    ))
+    .bind::<Nullable<diesel::sql_types::Uuid>, _>(challenge)
+    .bind::<Nullable<Timestamp>, _>(since)
+    .bind::<BigInt, _>(limit)
+    .bind::<BigInt, _>(offset)
+    .load::<LeaderboardStanding>(&mut conn)
+    .map_err(|err| Error::DatabaseQueryFail)?;
+
+    let total = diesel::sql_query(format!(
+        "{BEST_RUNS} SELECT COUNT(DISTINCT user_uuid) AS total FROM best"
+    ))
+    .bind::<Nullable<diesel::sql_types::Uuid>, _>(challenge)
+    .bind::<Nullable<Timestamp>, _>(since)
+    .get_result::<Total>(&mut conn)
+    .map_err(|err| Error::DatabaseQueryFail)?;
+
+    Ok((standings, total.total))
+}
+
+pub async fn insert_contest(new_contest: NewContest<'_>, challenge_ids: &[Uuid]) -> Result<Uuid> {
+    use crate::schema::{contest_challenges, contests};
+    let mut conn = establish_connection();
+
+    // The contest is not created if one of its challenges is missing
+    Ok(conn
+        .transaction::<_, diesel::result::Error, _>(|conn| {
+            let contest_id = diesel::insert_into(contests::table)
+                .values(new_contest)
+                .returning(contests::id)
+                .get_result(conn)?;
+            let challenges: Vec<NewContestChallenge> = challenge_ids
+                .iter()
+                .zip(0..)
+                .map(|(challenge_id, position)| NewContestChallenge {
+                    contest_id,
+                    challenge_id: *challenge_id,
+                    position,
+                })
+                .collect();
+            diesel::insert_into(contest_challenges::table)
+                .values(&challenges)
+                .execute(conn)?;
+            Ok(contest_id)
+        })
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+pub async fn get_contest(contest_id: Uuid) -> Result<Option<Contest>> {
+    use crate::schema::contests::dsl::{contests, id};
+    let mut conn = establish_connection();
+
+    Ok(contests
+        .filter(id.eq(contest_id))
+        .first::<Contest>(&mut conn)
+        .optional()
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+// Latest start first
+pub async fn get_contests() -> Result<Vec<Contest>> {
+    use crate::schema::contests::dsl::{contests, starts_at};
+    let mut conn = establish_connection();
+
+    Ok(contests
+        .order(starts_at.desc())
+        .load::<Contest>(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+// Returns false if the contest does not exist
+pub async fn update_contest(contest_id: Uuid, changes: ContestChanges) -> Result<bool> {
+    use crate::schema::contests::dsl::{contests, id};
+    let mut conn = establish_connection();
+
+    let updated = diesel::update(contests.filter(id.eq(contest_id)))
+        .set(changes)
+        .execute(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?;
+
+    Ok(updated > 0)
+}
+
+// Participants and submissions are deleted along with the contest
+pub async fn delete_contest(contest_id: Uuid) -> Result<bool> {
+    use crate::schema::contests::dsl::{contests, id};
+    let mut conn = establish_connection();
+
+    let deleted = diesel::delete(contests.filter(id.eq(contest_id)))
+        .execute(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?;
+
+    Ok(deleted > 0)
+}
+
+// Adding a challenge again only moves it
+pub async fn upsert_contest_challenge(challenge: NewContestChallenge) -> Result<()> {
+    use crate::schema::contest_challenges::dsl::{
+        challenge_id, contest_challenges, contest_id, position,
+    };
+    let mut conn = establish_connection();
+
+    diesel::insert_into(contest_challenges)
+        .values(&challenge)
+        .on_conflict((contest_id, challenge_id))
+        .do_update()
+        .set(position.eq(challenge.position))
+        .execute(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?;
+
+    Ok(())
+}
+
+pub async fn delete_contest_challenge(contest: Uuid, challenge: Uuid) -> Result<bool> {
+    use crate::schema::contest_challenges::dsl::{challenge_id, contest_challenges, contest_id};
+    let mut conn = establish_connection();
+
+    let deleted = diesel::delete(
+        contest_challenges
+            .filter(contest_id.eq(contest))
+            .filter(challenge_id.eq(challenge)),
+    )
+    .execute(&mut conn)
+    .map_err(|err| Error::DatabaseQueryFail)?;
+
+    Ok(deleted > 0)
+}
+
+// In the order they are shown in
+pub async fn get_contest_challenges(contest: Uuid) -> Result<Vec<Challenge>> {
+    use crate::schema::{challenges, contest_challenges};
+    let mut conn = establish_connection();
+
+    Ok(contest_challenges::table
+        .inner_join(challenges::table)
+        .filter(contest_challenges::contest_id.eq(contest))
+        .order((contest_challenges::position.asc(), challenges::name.asc()))
+        .select(challenges::all_columns)
+        .load::<Challenge>(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+// Registering twice keeps the first registration
+pub async fn insert_contest_participant(contest: Uuid, user: Uuid) -> Result<()> {
+    use crate::schema::contest_participants::dsl::{contest_id, contest_participants, user_uuid};
+    let mut conn = establish_connection();
+
+    diesel::insert_into(contest_participants)
+        .values((contest_id.eq(contest), user_uuid.eq(user)))
+        .on_conflict_do_nothing()
+        .execute(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?;
+
+    Ok(())
+}
+
+pub async fn get_contest_participant(
+    contest: Uuid,
+    user: Uuid,
+) -> Result<Option<ContestParticipant>> {
+    use crate::schema::contest_participants::dsl::{contest_id, contest_participants, user_uuid};
+    let mut conn = establish_connection();
+
+    Ok(contest_participants
+        .filter(contest_id.eq(contest))
+        .filter(user_uuid.eq(user))
+        .first::<ContestParticipant>(&mut conn)
+        .optional()
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+// User ids and usernames
+pub async fn get_contest_participants(contest: Uuid) -> Result<Vec<(Uuid, String)>> {
+    use crate::schema::{contest_participants, users};
+    let mut conn = establish_connection();
+
+    Ok(contest_participants::table
+        .inner_join(users::table)
+        .filter(contest_participants::contest_id.eq(contest))
+        .order(contest_participants::registered_at.asc())
+        .select((users::id, users::username))
+        .load::<(Uuid, String)>(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+pub async fn insert_contest_submission(submission: NewContestSubmission) -> Result<Uuid> {
+    use crate::schema::contest_submissions::dsl::{contest_submissions, id};
+    let mut conn = establish_connection();
+
+    Ok(diesel::insert_into(contest_submissions)
+        .values(submission)
+        .returning(id)
+        .get_result(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+// Removes a submission whose job could not be queued
+pub async fn delete_contest_submission(submission: Uuid) -> Result<bool> {
+    use crate::schema::contest_submissions::dsl::{contest_submissions, id};
+    let mut conn = establish_connection();
+
+    let deleted = diesel::delete(contest_submissions.filter(id.eq(submission)))
+        .execute(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?;
+
+    Ok(deleted > 0)
+}
+
+pub async fn is_contest_file(file: Uuid) -> Result<bool> {
+    use crate::schema::contest_submissions::dsl::{contest_submissions, file_id};
+    let mut conn = establish_connection();
+
+    Ok(diesel::select(diesel::dsl::exists(
+        contest_submissions.filter(file_id.eq(file)),
+    ))
+    .get_result(&mut conn)
+    .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+// Oldest first, each with the latest run of its file if it has been judged
+pub async fn get_contest_submissions(
+    contest: Uuid,
+) -> Result<Vec<(ContestSubmission, Option<Simulation>)>> {
+    use crate::schema::{contest_submissions, simulations};
+    let mut conn = establish_connection();
+
+    let mut submissions = contest_submissions::table
+        .left_join(simulations::table.on(simulations::ran_file_id.eq(contest_submissions::file_id)))
+        .filter(contest_submissions::contest_id.eq(contest))
+        .order((
+            contest_submissions::submitted_at.asc(),
+            contest_submissions::id.asc(),
+            simulations::ran_at.desc(),
+        ))
+        .load::<(ContestSubmission, Option<Simulation>)>(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?;
+    submissions.dedup_by_key(|(submission, _)| submission.id);
+
+    Ok(submissions)
+}
+
+pub async fn update_user_role(user: Uuid, new_role: Role) -> Result<bool> {
+    use crate::schema::users::dsl::{id, role, users};
+    let mut conn = establish_connection();
+
+    let updated = diesel::update(users.filter(id.eq(user)))
+        .set(role.eq(new_role))
+        .execute(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?;
+
+    Ok(updated > 0)
+}
+
+pub async fn insert_teacher_student(link: NewTeacherStudent) -> Result<()> {
+    use crate::schema::teacher_students::dsl::teacher_students;
+    let mut conn = establish_connection();
+
+    diesel::insert_into(teacher_students)
+        .values(link)
+        .on_conflict_do_nothing()
+        .execute(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?;
+
+    Ok(())
+}
+
+pub async fn delete_teacher_student(teacher: Uuid, student: Uuid) -> Result<bool> {
+    use crate::schema::teacher_students::dsl::{student_uuid, teacher_students, teacher_uuid};
+    let mut conn = establish_connection();
+
+    let deleted = diesel::delete(
+        teacher_students
+            .filter(teacher_uuid.eq(teacher))
+            .filter(student_uuid.eq(student)),
+    )
+    .execute(&mut conn)
+    .map_err(|err| Error::DatabaseQueryFail)?;
+
+    Ok(deleted > 0)
+}
+
+pub async fn is_teacher_of(teacher: Uuid, student: Uuid) -> Result<bool> {
+    use crate::schema::teacher_students::dsl::{student_uuid, teacher_students, teacher_uuid};
+    let mut conn = establish_connection();
+
+    Ok(diesel::select(diesel::dsl::exists(
+        teacher_students
+            .filter(teacher_uuid.eq(teacher))
+            .filter(student_uuid.eq(student)),
+    ))
+    .get_result::<bool>(&mut conn)
+    .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+// User ids and usernames, in the order they were added
+pub async fn get_students(teacher: Uuid) -> Result<Vec<(Uuid, String)>> {
+    use crate::schema::{teacher_students, users};
+    let mut conn = establish_connection();
+
+    Ok(teacher_students::table
+        .inner_join(users::table.on(users::id.eq(teacher_students::student_uuid)))
+        .filter(teacher_students::teacher_uuid.eq(teacher))
+        .order(teacher_students::added_at.asc())
+        .select((users::id, users::username))
+        .load::<(Uuid, String)>(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+pub async fn insert_personal_token(token: NewPersonalAccessToken<'_>) -> Result<()> {
+    use crate::schema::personal_access_tokens::dsl::personal_access_tokens;
+    let mut conn = establish_connection();
+
+    diesel::insert_into(personal_access_tokens)
+        .values(token)
+        .execute(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?;
+
+    Ok(())
+}
+
+// Newest first
+pub async fn get_personal_tokens(user: Uuid) -> Result<Vec<PersonalAccessToken>> {
+    use crate::schema::personal_access_tokens::dsl::{
+        created_at, personal_access_tokens, user_uuid,
+    };
+    let mut conn = establish_connection();
+
+    Ok(personal_access_tokens
+        .filter(user_uuid.eq(user))
+        .order(created_at.desc())
+        .load::<PersonalAccessToken>(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+pub async fn get_personal_token(token_id: Uuid, user: Uuid) -> Result<Option<PersonalAccessToken>> {
+    use crate::schema::personal_access_tokens::dsl::{id, personal_access_tokens, user_uuid};
+    let mut conn = establish_connection();
+
+    Ok(personal_access_tokens
+        .filter(id.eq(token_id))
+        .filter(user_uuid.eq(user))
+        .first::<PersonalAccessToken>(&mut conn)
+        .optional()
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+pub async fn delete_personal_token(token_id: Uuid, user: Uuid) -> Result<bool> {
+    use crate::schema::personal_access_tokens::dsl::{id, personal_access_tokens, user_uuid};
+    let mut conn = establish_connection();
+
+    let deleted = diesel::delete(
+        personal_access_tokens
+            .filter(id.eq(token_id))
+            .filter(user_uuid.eq(user)),
+    )
+    .execute(&mut conn)
+    .map_err(|err| Error::DatabaseQueryFail)?;
+
+    Ok(deleted > 0)
+}
+
+// The old token stops working the moment the new one is stored
+pub async fn rotate_personal_token(
+    old_id: Uuid,
+    replacement: NewPersonalAccessToken<'_>,
+) -> Result<bool> {
+    use crate::schema::personal_access_tokens::dsl::{id, personal_access_tokens, user_uuid};
+    let mut conn = establish_connection();
+
+    Ok(conn
+        .transaction::<bool, diesel::result::Error, _>(|conn| {
+            let deleted = diesel::delete(
+                personal_access_tokens
+                    .filter(id.eq(old_id))
+                    .filter(user_uuid.eq(replacement.user_uuid)),
+            )
+            .execute(conn)?;
+            if deleted == 0 {
+                return Ok(false);
+            }
+
+            diesel::insert_into(personal_access_tokens)
+                .values(&replacement)
+                .execute(conn)?;
+            Ok(true)
+        })
+        .map_err(|err| Error::DatabaseQueryFail)?)
+}
+
+// Like get_token_owner, but for personal access tokens. Also returns what the
+// token may be used for and records when it was last used.
+pub async fn get_personal_token_owner(
+    token_str: &str,
+) -> std::result::Result<Option<(User, Vec<TokenScope>)>, Error> {
+    use crate::schema::personal_access_tokens::dsl::{id, last_used_at, personal_access_tokens};
+    let Some(pat_id) = token_id(PERSONAL_TOKEN_PREFIX, token_str) else {
+        return Ok(None);
+    };
+    let mut conn = establish_connection();
+
+    let Some(token) = personal_access_tokens
+        .filter(id.eq(pat_id))
+        .first::<PersonalAccessToken>(&mut conn)
+        .optional()
+        .map_err(|err| Error::DatabaseQueryFail)?
+    else {
+        return Ok(None);
+    };
+    if !verify_token(token_str, &token.token_hash) {
+        return Ok(None);
+    }
+    let now = Utc::now().naive_utc();
+    if token.expires_at <= now {
+        return Err(Error::AuthFailTokenExpired);
+    }
+
+    diesel::update(personal_access_tokens.filter(id.eq(pat_id)))
+        .set(last_used_at.eq(now))
+        .execute(&mut conn)
+        .map_err(|err| Error::DatabaseQueryFail)?;
+
+    let user = get_user(token.user_uuid)
+        .await
+        .map_err(|_| Error::DatabaseFailedToFindUser)?;
+    Ok(Some((user, token.scopes)))
+}
@@ -1,8 +1,13 @@
-use crate::schema::{files, session_tokens, users};
+use crate::schema::{
+    challenge_test_cases, challenges, contest_challenges, contest_participants,
+    contest_submissions, contests, files, jobs, matches, personal_access_tokens, ratings,
+    session_tokens, simulations, teacher_students, tournament_entries, tournaments, users,
+};
 use chrono::NaiveDateTime;
 
-use diesel::Insertable;
-use diesel::{sql_types::Nullable, Queryable, Selectable};
+use diesel::pg::data_types::PgInterval;
+use diesel::{sql_types::Nullable, Queryable, QueryableByName, Selectable};
+use diesel::{AsChangeset, Insertable};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -23,7 +28,43 @@ pub struct User {
     pub created_at: Option<chrono::NaiveDateTime>,
     pub last_login_at: Option<chrono::NaiveDateTime>,
     pub login_count: Option<i32>,
-    pub is_admin: Option<bool>,
+    pub role: Role,
+}
+
+// Declared from least to most privileged, every role includes the ones before it
+#[derive(
+    diesel_derive_enum::DbEnum,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Deserialize,
+    Serialize,
+)]
+#[ExistingTypePath = "crate::schema::sql_types::UserRole"]
+#[DbValueStyle = "snake_case"]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Student,
+    // Sees the submissions of their students
+    Teacher,
+    Admin,
+}
+
+impl Role {
+    pub fn includes(self, required: Self) -> bool {
+        self >= required
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = teacher_students)]
+pub struct NewTeacherStudent {
+    pub teacher_uuid: Uuid,
+    pub student_uuid: Uuid,
 }
 #[derive(Queryable, Insertable, Debug, Deserialize, Serialize)]
 #[diesel(table_name = files)]
@@ -45,7 +86,8 @@ pub struct File {
 #[diesel(table_name = session_tokens)]
 pub struct SessionToken {
     pub id: Uuid,
-    pub token: String,
+    // Never the token itself, see api::auth::tokens
+    pub token_hash: String,
     pub user_uuid: Uuid,
     pub creation_date: NaiveDateTime,
     pub expiration_date: NaiveDateTime,
@@ -54,7 +96,8 @@ pub struct SessionToken {
 #[derive(Insertable)]
 #[diesel(table_name = session_tokens)]
 pub struct NewSessionToken<'a> {
-    pub token: &'a str,
+    pub id: Uuid,
+    pub token_hash: &'a str,
     pub user_uuid: Uuid,
     pub expiration_date: NaiveDateTime,
 }
@@ -62,7 +105,7 @@ pub struct NewSessionToken<'a> {
 #[derive(Insertable)]
 #[diesel(table_name = session_tokens)]
 pub struct Token<'a> {
-    pub token: &'a str,
+    pub token_hash: &'a str,
 }
 
 // Define the struct representing the model
@@ -79,3 +122,441 @@ pub struct FileMetadata {
     pub last_modified_at: NaiveDateTime,
     pub parent_id: Option<Uuid>,
 }
+
+#[derive(diesel_derive_enum::DbEnum, Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[ExistingTypePath = "crate::schema::sql_types::JobState"]
+#[DbValueStyle = "snake_case"]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Queued,
+    Compiling,
+    Running,
+    Finished,
+    Failed,
+    TimedOut,
+    Cancelled,
+}
+
+impl JobState {
+    pub const fn is_finished(self) -> bool {
+        matches!(
+            self,
+            Self::Finished | Self::Failed | Self::TimedOut | Self::Cancelled
+        )
+    }
+}
+
+#[derive(Queryable, Debug)]
+#[diesel(table_name = jobs)]
+pub struct Job {
+    pub id: Uuid,
+    pub user_uuid: Uuid,
+    pub file_id: Option<Uuid>,
+    pub language: String,
+    pub source: Vec<u8>,
+    pub state: JobState,
+    pub created_at: NaiveDateTime,
+    pub started_at: Option<NaiveDateTime>,
+    pub finished_at: Option<NaiveDateTime>,
+    pub output: Option<serde_json::Value>,
+    pub test_cases: Option<serde_json::Value>,
+    pub stdin: Vec<u8>,
+    pub challenge_id: Option<Uuid>,
+    pub limits: Option<serde_json::Value>,
+    pub checker: Option<serde_json::Value>,
+    pub game: Option<serde_json::Value>,
+    pub scoring: Option<serde_json::Value>,
+    pub score: Option<i32>,
+    pub cpu_time_ms: Option<i64>,
+    pub peak_memory_kb: Option<i64>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = jobs)]
+pub struct NewJob<'a> {
+    pub id: Uuid,
+    pub user_uuid: Uuid,
+    pub file_id: Option<Uuid>,
+    pub language: &'a str,
+    pub source: &'a [u8],
+    pub test_cases: Option<serde_json::Value>,
+    pub stdin: &'a [u8],
+    pub challenge_id: Option<Uuid>,
+    pub limits: Option<serde_json::Value>,
+    pub checker: Option<serde_json::Value>,
+    pub game: Option<serde_json::Value>,
+    pub scoring: Option<serde_json::Value>,
+}
+
+// Ranks a finished job on the leaderboards
+#[derive(Clone, Copy, Debug)]
+pub struct JobScore {
+    pub score: i32,
+    // Summed over the test cases
+    pub cpu_time_ms: Option<i64>,
+    // Highest of the test cases
+    pub peak_memory_kb: Option<i64>,
+}
+
+#[derive(diesel_derive_enum::DbEnum, Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[ExistingTypePath = "crate::schema::sql_types::SimulationResult"]
+#[DbValueStyle = "verbatim"]
+#[serde(rename_all = "snake_case")]
+pub enum SimulationResult {
+    Passed,
+    Failed,
+    // The run could not be completed, not the submission's fault
+    Error,
+}
+
+#[derive(Queryable, Debug)]
+#[diesel(table_name = simulations)]
+pub struct Simulation {
+    pub simulation_id: i32,
+    pub ran_at: NaiveDateTime,
+    pub ran_file_id: Uuid,
+    pub logs: Option<String>,
+    pub result: Option<SimulationResult>,
+    pub time_taken: Option<PgInterval>,
+    pub cpu_time: Option<PgInterval>,
+    // Kilobytes
+    pub max_memory_usage: Option<i32>,
+    // Only set for graded runs
+    pub score: Option<i32>,
+    pub score_breakdown: Option<serde_json::Value>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = simulations)]
+pub struct NewSimulation {
+    pub ran_at: NaiveDateTime,
+    pub ran_file_id: Uuid,
+    pub logs: Option<String>,
+    pub result: Option<SimulationResult>,
+    pub time_taken: Option<PgInterval>,
+    pub cpu_time: Option<PgInterval>,
+    pub max_memory_usage: Option<i32>,
+    pub score: Option<i32>,
+    pub score_breakdown: Option<serde_json::Value>,
+}
+
+#[derive(Queryable, Debug)]
+#[diesel(table_name = challenges)]
+pub struct Challenge {
+    pub id: Uuid,
+    pub name: String,
+    pub description: String,
+    // Language names, empty allows every supported language
+    pub allowed_languages: Vec<Option<String>>,
+    pub limits: Option<serde_json::Value>,
+    pub is_published: bool,
+    pub created_by: Option<Uuid>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    // Both set if the challenge is judged by a checker program
+    pub checker_language: Option<String>,
+    pub checker_source: Option<String>,
+    // Set for interactive challenges, which are played against a referee
+    pub game: Option<serde_json::Value>,
+    pub scoring: Option<serde_json::Value>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = challenges)]
+pub struct NewChallenge<'a> {
+    pub name: &'a str,
+    pub description: &'a str,
+    pub allowed_languages: Vec<Option<String>>,
+    pub limits: Option<serde_json::Value>,
+    pub is_published: bool,
+    pub created_by: Option<Uuid>,
+    pub scoring: Option<serde_json::Value>,
+}
+
+// Fields left as None are not changed
+#[derive(AsChangeset, Default)]
+#[diesel(table_name = challenges)]
+pub struct ChallengeChanges {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub allowed_languages: Option<Vec<Option<String>>>,
+    pub is_published: Option<bool>,
+    pub updated_at: Option<NaiveDateTime>,
+    // Some(None) clears the column
+    pub limits: Option<Option<serde_json::Value>>,
+    pub checker_language: Option<Option<String>>,
+    pub checker_source: Option<Option<String>>,
+    pub game: Option<Option<serde_json::Value>>,
+    pub scoring: Option<Option<serde_json::Value>>,
+}
+
+#[derive(Queryable, Debug)]
+#[diesel(table_name = challenge_test_cases)]
+pub struct ChallengeTestCase {
+    pub id: Uuid,
+    pub challenge_id: Uuid,
+    pub name: String,
+    pub input: String,
+    pub expected_output: String,
+    pub comparison: serde_json::Value,
+    pub is_sample: bool,
+    pub position: i32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = challenge_test_cases)]
+pub struct NewChallengeTestCase<'a> {
+    pub challenge_id: Uuid,
+    pub name: &'a str,
+    pub input: &'a str,
+    pub expected_output: &'a str,
+    pub comparison: serde_json::Value,
+    pub is_sample: bool,
+    pub position: i32,
+}
+
+#[derive(diesel_derive_enum::DbEnum, Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[ExistingTypePath = "crate::schema::sql_types::TournamentFormat"]
+#[DbValueStyle = "snake_case"]
+#[serde(rename_all = "snake_case")]
+pub enum TournamentFormat {
+    // Everyone plays everyone once
+    RoundRobin,
+    // Players with similar scores are paired for a fixed number of rounds
+    Swiss,
+}
+
+#[derive(diesel_derive_enum::DbEnum, Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[ExistingTypePath = "crate::schema::sql_types::TournamentState"]
+#[DbValueStyle = "snake_case"]
+#[serde(rename_all = "snake_case")]
+pub enum TournamentState {
+    Pending,
+    Running,
+    Finished,
+    Failed,
+}
+
+#[derive(Queryable, Debug)]
+#[diesel(table_name = tournaments)]
+pub struct Tournament {
+    pub id: Uuid,
+    pub challenge_id: Uuid,
+    pub name: String,
+    pub format: TournamentFormat,
+    pub rounds: i32,
+    pub played_rounds: i32,
+    pub state: TournamentState,
+    pub created_by: Option<Uuid>,
+    pub created_at: NaiveDateTime,
+    pub started_at: Option<NaiveDateTime>,
+    pub finished_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = tournaments)]
+pub struct NewTournament<'a> {
+    pub challenge_id: Uuid,
+    pub name: &'a str,
+    pub format: TournamentFormat,
+    pub rounds: i32,
+    pub created_by: Option<Uuid>,
+}
+
+#[derive(Queryable, Debug)]
+#[diesel(table_name = tournament_entries)]
+pub struct TournamentEntry {
+    pub tournament_id: Uuid,
+    pub user_uuid: Uuid,
+    pub file_id: Uuid,
+    pub points: f64,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = tournament_entries)]
+pub struct NewTournamentEntry {
+    pub tournament_id: Uuid,
+    pub user_uuid: Uuid,
+    pub file_id: Uuid,
+}
+
+#[derive(Queryable, Debug)]
+#[diesel(table_name = matches)]
+pub struct Match {
+    pub id: Uuid,
+    pub tournament_id: Uuid,
+    pub round: i32,
+    pub player_one: Uuid,
+    pub player_two: Option<Uuid>,
+    pub winner: Option<Uuid>,
+    pub reason: String,
+    pub transcript: serde_json::Value,
+    pub played_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = matches)]
+pub struct NewMatch {
+    pub tournament_id: Uuid,
+    pub round: i32,
+    pub player_one: Uuid,
+    pub player_two: Option<Uuid>,
+    pub winner: Option<Uuid>,
+    pub reason: String,
+    pub transcript: serde_json::Value,
+}
+
+#[derive(Queryable, Insertable, Debug, Clone)]
+#[diesel(table_name = ratings)]
+pub struct Rating {
+    pub user_uuid: Uuid,
+    pub challenge_id: Uuid,
+    pub rating: f64,
+    pub games_played: i32,
+    pub updated_at: NaiveDateTime,
+}
+
+// A user's best runs on a leaderboard, summed over the challenges it covers
+#[derive(QueryableByName, Debug, Clone, PartialEq, Eq)]
+pub struct LeaderboardStanding {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    pub user_uuid: Uuid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub username: String,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub score: i64,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub solved: i64,
+    #[diesel(sql_type = Nullable<diesel::sql_types::BigInt>)]
+    pub cpu_time_ms: Option<i64>,
+    #[diesel(sql_type = Nullable<diesel::sql_types::BigInt>)]
+    pub peak_memory_kb: Option<i64>,
+    // When the last of the best runs was submitted
+    #[diesel(sql_type = diesel::sql_types::Timestamp)]
+    pub submitted_at: NaiveDateTime,
+}
+
+#[derive(diesel_derive_enum::DbEnum, Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[ExistingTypePath = "crate::schema::sql_types::ContestScoring"]
+#[DbValueStyle = "snake_case"]
+#[serde(rename_all = "snake_case")]
+pub enum ContestScoring {
+    // Solved challenges, ties broken by penalty time
+    Icpc,
+    // Partial points per challenge, the best submission counts
+    Ioi,
+}
+
+#[derive(Queryable, Debug, Clone)]
+#[diesel(table_name = contests)]
+pub struct Contest {
+    pub id: Uuid,
+    pub name: String,
+    pub description: String,
+    pub scoring: ContestScoring,
+    pub starts_at: NaiveDateTime,
+    pub ends_at: NaiveDateTime,
+    // 0 keeps the scoreboard live until the end
+    pub freeze_minutes: i32,
+    pub created_by: Option<Uuid>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = contests)]
+pub struct NewContest<'a> {
+    pub name: &'a str,
+    pub description: &'a str,
+    pub scoring: ContestScoring,
+    pub starts_at: NaiveDateTime,
+    pub ends_at: NaiveDateTime,
+    pub freeze_minutes: i32,
+    pub created_by: Option<Uuid>,
+}
+
+// Fields left as None are not changed
+#[derive(AsChangeset, Default)]
+#[diesel(table_name = contests)]
+pub struct ContestChanges {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub scoring: Option<ContestScoring>,
+    pub starts_at: Option<NaiveDateTime>,
+    pub ends_at: Option<NaiveDateTime>,
+    pub freeze_minutes: Option<i32>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = contest_challenges)]
+pub struct NewContestChallenge {
+    pub contest_id: Uuid,
+    pub challenge_id: Uuid,
+    pub position: i32,
+}
+
+#[derive(Queryable, Insertable, Debug)]
+#[diesel(table_name = contest_participants)]
+pub struct ContestParticipant {
+    pub contest_id: Uuid,
+    pub user_uuid: Uuid,
+    pub registered_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Debug)]
+#[diesel(table_name = contest_submissions)]
+pub struct ContestSubmission {
+    pub id: Uuid,
+    pub contest_id: Uuid,
+    pub challenge_id: Uuid,
+    pub user_uuid: Uuid,
+    pub file_id: Uuid,
+    pub submitted_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = contest_submissions)]
+pub struct NewContestSubmission {
+    pub contest_id: Uuid,
+    pub challenge_id: Uuid,
+    pub user_uuid: Uuid,
+    pub file_id: Uuid,
+}
+
+#[derive(diesel_derive_enum::DbEnum, Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[ExistingTypePath = "crate::schema::sql_types::TokenScope"]
+#[DbValueStyle = "snake_case"]
+#[serde(rename_all = "snake_case")]
+pub enum TokenScope {
+    // Results, files, challenges and scoreboards
+    Read,
+    // Uploading, running and submitting code
+    Submit,
+}
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = personal_access_tokens)]
+pub struct PersonalAccessToken {
+    pub id: Uuid,
+    pub user_uuid: Uuid,
+    pub name: String,
+    // Never the token itself, see api::auth::tokens
+    pub token_hash: String,
+    pub scopes: Vec<TokenScope>,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub last_used_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = personal_access_tokens)]
+pub struct NewPersonalAccessToken<'a> {
+    pub id: Uuid,
+    pub user_uuid: Uuid,
+    pub name: &'a str,
+    pub token_hash: &'a str,
+    pub scopes: &'a [TokenScope],
+    // Set by the caller, so the lifetime is exactly what was asked for
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+}
@@ -1,21 +1,28 @@
 use bollard::exec::{self, CreateExecOptions};
 use bollard::image::{BuildImageOptions, CreateImageOptions};
-use bollard::service::{ExecConfig, Mount, MountVolumeOptions};
-use futures::TryStreamExt;
+use bollard::service::{ContainerState, ExecConfig, Mount, MountVolumeOptions};
+use futures::{StreamExt, TryStreamExt};
+use serde::Serialize;
 use std::collections::HashMap;
 use std::io::{Read, Write};
 use std::path::Path;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
 use std::{default, string};
-use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
-use tokio_stream::StreamExt;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
 
 use crate::docker::build_image::{self, get_image};
 use crate::docker::common::{extract_file_from_tar_archive, image_exists};
+use crate::docker::diagnostics::{parse_diagnostics, Diagnostic};
+use crate::docker::job::{JobEvent, OutputStream, SandboxJob, Stage};
+use crate::docker::languages::SANDBOX_DIR;
+use crate::docker::metrics::{parse_metrics, Metrics, METRICS_FILE};
 use crate::error::AppError;
 use crate::schema::files::id;
 use bollard::container::{
-    Config, CreateContainerOptions, LogOutput, LogsOptions, StartContainerOptions, Stats,
-    StopContainerOptions, WaitContainerOptions,
+    AttachContainerOptions, AttachContainerResults, Config, CreateContainerOptions,
+    KillContainerOptions, LogOutput, LogsOptions, RemoveContainerOptions, StartContainerOptions,
+    Stats, StopContainerOptions, WaitContainerOptions,
 };
 use bollard::container::{DownloadFromContainerOptions, UploadToContainerOptions};
 use bollard::Docker;
@@ -46,12 +53,23 @@ async fn start_container(docker: &Docker, container_id: &str) -> Result<()> {
     Ok(())
 }
 
-async fn create_container(docker: &Docker, preset: impl ContainerPreset + Send) -> Result<String> {
+async fn create_container(
+    docker: &Docker,
+    preset: impl ContainerPreset,
+    job: &SandboxJob,
+) -> Result<String> {
     info!("Creating container");
 
     let mut config = preset.container_config();
+    config.host_config = Some(preset.host_config());
+    config.labels = Some(job.labels());
+
+    let options = CreateContainerOptions {
+        name: job.container_name(&preset.info().name),
+        ..preset.create_options()
+    };
 
-    let container = docker.create_container(Some(preset.create_options()), config);
+    let container = docker.create_container(Some(options), config);
     let container_id = match container.await {
         Ok(o) => o.id,
         Err(e) => return Err(e.into()),
@@ -59,32 +77,47 @@ async fn create_container(docker: &Docker, preset: impl ContainerPreset + Send)
     Ok(container_id)
 }
 
-async fn remove_old_container(docker: &Docker, container_name: &str) -> Result<()> {
-    //Check if the container exists
-    let container_exists = docker.inspect_container(container_name, None).await;
+// Follows the logs until the container stops, forwarding them to the job's subscribers
+async fn get_logs(
+    docker: &Docker,
+    container_id: &str,
+    preset: impl ContainerPreset,
+    stage: Stage,
+    job: &SandboxJob,
+) -> Result<CapturedOutput> {
+    let options = preset.logs_options();
+    let limit = preset.limits().output_limit_bytes;
 
-    // Stop if running
-    let _ = docker.stop_container(container_name, None).await;
+    let mut logs = Box::pin(docker.logs(container_id, Some(options)));
+    let mut output = CapturedOutput::default();
 
-    //Remove it if it exists
-    if container_exists.is_ok() {
-        docker.remove_container(container_name, None).await?;
+    while let Some(log) = logs.try_next().await? {
+        let (stream, kind, message) = match log {
+            LogOutput::StdOut { message } | LogOutput::Console { message } => {
+                (&mut output.stdout, OutputStream::Stdout, message)
+            }
+            LogOutput::StdErr { message } => (&mut output.stderr, OutputStream::Stderr, message),
+            LogOutput::StdIn { .. } => continue,
+        };
+
+        // Keep reading so the other stream can still be filled
+        let remaining = limit.saturating_sub(stream.len());
+        if message.len() > remaining {
+            output.truncated = true;
+        }
+        let kept = &message[..message.len().min(remaining)];
+        stream.extend_from_slice(kept);
+
+        if !kept.is_empty() {
+            job.emit(JobEvent::Output {
+                stage,
+                stream: kind,
+                data: String::from_utf8_lossy(kept).into_owned(),
+            });
+        }
     }
-    Ok(())
-}
 
-async fn get_logs(docker: &Docker, preset: impl ContainerPreset) -> Result<Vec<LogOutput>> {
-    let container_name = preset.info().name;
-
-    let options = preset.logs_options();
-
-    // Get logs from stopped container
-    let logs = docker
-        .logs(&container_name, Some(options))
-        .try_collect::<Vec<_>>()
-        .await?;
-
-    Ok(logs)
+    Ok(output)
 }
 
 async fn copy_file_into_container(
@@ -134,14 +167,85 @@ async fn stop_container(docker: &Docker, container_id: &str) -> Result<()> {
     Ok(())
 }
 
+async fn kill_container(docker: &Docker, container_id: &str) -> Result<()> {
+    info!("Killing container {}", container_id);
+
+    let options = KillContainerOptions { signal: "SIGKILL" };
+
+    docker.kill_container(container_id, Some(options)).await?;
+    Ok(())
+}
+
+// Waits for the container to stop, killing it if the deadline passes first.
+// Returns the exit code, or None if the container had to be killed.
+async fn wait_for_exit(
+    docker: &Docker,
+    container_id: &str,
+    deadline: Duration,
+) -> Result<Option<i64>> {
+    let options = WaitContainerOptions {
+        condition: "not-running",
+    };
+    let mut wait = Box::pin(docker.wait_container(container_id, Some(options)));
+
+    match tokio::time::timeout(deadline, wait.next()).await {
+        Ok(Some(Ok(response))) => Ok(Some(response.status_code)),
+        // Bollard reports non-zero exit codes as errors
+        Ok(Some(Err(bollard::errors::Error::DockerContainerWaitError { code, .. }))) => {
+            Ok(Some(code))
+        }
+        // Nothing else stops a program that keeps running
+        Ok(Some(Err(e))) => {
+            if let Err(kill) = kill_container(docker, container_id).await {
+                warn!("Failed to kill container: {}", kill);
+            }
+            Err(e.into())
+        }
+        // The stream ended without a status, ask the container instead
+        Ok(None) => {
+            let state = docker.inspect_container(container_id, None).await?.state;
+            Ok(state.and_then(|s| s.exit_code))
+        }
+        Err(_) => {
+            warn!("Container {} passed its deadline", container_id);
+            // The container may have exited right after the deadline
+            if let Err(e) = kill_container(docker, container_id).await {
+                warn!("Failed to kill container: {}", e);
+            }
+            Ok(None)
+        }
+    }
+}
+
 async fn remove_container(docker: &Docker, container_id: &str) -> Result<()> {
     info!("Removing container");
-    // Stop and remove the container
-    docker.stop_container(container_id, None).await?;
-    docker.remove_container(container_id, None).await?;
+
+    // Force removes running containers, v removes the sandbox volume
+    let options = RemoveContainerOptions {
+        force: true,
+        v: true,
+        ..Default::default()
+    };
+
+    docker.remove_container(container_id, Some(options)).await?;
     Ok(())
 }
 
+// Removes the container however the work in it ended.
+// An error of the work is returned over one of the removal.
+async fn remove_after<T>(docker: &Docker, container_id: &str, result: Result<T>) -> Result<T> {
+    let removed = remove_container(docker, container_id).await;
+    match (result, removed) {
+        (Ok(value), removed) => removed.map(|()| value),
+        (Err(e), removed) => {
+            if let Err(removal) = removed {
+                warn!("Failed to remove container {}: {}", container_id, removal);
+            }
+            Err(e)
+        }
+    }
+}
+
 async fn exec_in_container(docker: &Docker, container_id: &str, command: Vec<&str>) -> Result<()> {
     let config = CreateExecOptions {
         cmd: Some(command),
@@ -168,13 +272,13 @@ async fn get_file_from_container(
     Ok(bytes)
 }
 
-pub async fn gcc_container(
+pub async fn compile_preset(
     source_file: File,
     preset: impl ContainerPreset + std::marker::Copy,
-) -> Result<File> {
+    job: &SandboxJob,
+) -> Result<CompileResult> {
     let docker = Docker::connect_with_local_defaults()?;
 
-    let container_name = preset.info().name;
     let image_name = preset.info().image;
 
     let image = match image_exists(&docker, &image_name).await? {
@@ -182,100 +286,142 @@ pub async fn gcc_container(
         false => get_image(preset).await?,
     };
 
-    remove_old_container(&docker, &container_name).await?;
+    let container_id = create_container(&docker, preset, job).await?;
+    let compiled = compile_in_container(&docker, &container_id, source_file, preset, job).await;
+    let (mut result, archive_bytes) = remove_after(&docker, &container_id, compiled).await?;
 
-    let container_id = create_container(&docker, preset).await?;
-
-    let destination_path: &Path = Path::new("/example.c");
+    // There is no artifact to fetch if the compiler failed
+    let Some(archive_bytes) = archive_bytes else {
+        return Ok(result);
+    };
+    let artifact_name = preset.info().output;
 
-    info!("Copying file into container");
+    let mut archive_file: File = File::from_std(tempfile()?);
+    archive_file.write_all(&archive_bytes).await?;
 
-    copy_file_into_container(&docker, &container_id, source_file, destination_path).await?;
+    // Extract the file from the archive
+    let buff = extract_file_from_tar_archive(archive_file, &artifact_name).await?;
 
-    start_container(&docker, &container_id).await?;
+    info!("File size: {}", buff.len());
 
-    info!("Waiting for container to finish");
+    if buff.is_empty() {
+        warn!("Compiler exited successfully but produced no artifact");
+        return Ok(result);
+    }
 
-    let wait_options = WaitContainerOptions {
-        condition: "not-running",
-    };
+    let mut file = File::from_std(tempfile()?);
+    file.write_all(&buff).await?;
+    file.seek(std::io::SeekFrom::Start(0)).await?;
 
-    let container_stats = get_container_stats(&docker, &container_id).await?;
+    result.success = true;
+    result.artifact = Some(file);
 
-    // Wait for one second
-    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    Ok(result)
+}
 
-    let _ = docker.wait_container(&container_id, Some(wait_options));
+// Compiles in the created container and downloads the artifact archive
+async fn compile_in_container(
+    docker: &Docker,
+    container_id: &str,
+    source_file: File,
+    preset: impl ContainerPreset + std::marker::Copy,
+    job: &SandboxJob,
+) -> Result<(CompileResult, Option<Vec<u8>>)> {
+    let source_name = preset.info().input;
+    let artifact_name = preset.info().output;
+    let destination_path = Path::new(SANDBOX_DIR).join(&source_name);
 
-    let container_logs = get_logs(&docker, preset).await?;
+    info!("Copying file into container");
 
-    // Print logs
-    info!(
-        "{:?}",
-        container_logs
-            .iter()
-            .map(|log| log.to_string())
-            .collect::<Vec<String>>()
-    );
+    copy_file_into_container(docker, container_id, source_file, &destination_path).await?;
 
-    let archive_bytes = get_file_from_container(&docker, &container_id, "/example.o").await?;
+    start_container(docker, container_id).await?;
 
-    stop_container(&docker, &container_id).await?;
+    info!("Waiting for container to finish");
 
-    if archive_bytes.is_empty() {
-        panic!("Binary file is empty");
-    }
+    // The log stream ends once the container stops or is killed
+    let (exit_code, container_logs) = tokio::join!(
+        wait_for_exit(docker, container_id, preset.timeout()),
+        get_logs(docker, container_id, preset, Stage::Compile, job),
+    );
+    let exit_code = exit_code?;
+    let container_logs = container_logs?;
+    let state = docker.inspect_container(container_id, None).await?.state;
 
-    let output = ContainerOutput {
-        logs: container_logs,
-        id: container_id,
-        exit_code: 0,
-        metrics: None,
+    // Print logs
+    info!("{}", container_logs.stderr_lossy());
+
+    // Compilers write diagnostics to stderr, but some use stdout
+    let mut compiler_output = container_logs.stderr_lossy();
+    compiler_output.push_str(&container_logs.stdout_lossy());
+
+    let result = CompileResult {
+        success: false,
+        exit_code,
+        outcome: if exit_code.is_some() {
+            RunOutcome::from_state(state.as_ref())
+        } else {
+            RunOutcome::TimedOut
+        },
+        diagnostics: parse_diagnostics(&compiler_output),
+        output: compiler_output,
+        artifact: None,
     };
 
-    let mut archive_file: File = File::create("archive.tar").await?;
-    archive_file.write_all(&archive_bytes).await?;
-    archive_file.seek(std::io::SeekFrom::Start(0)).await?;
-
-    let mut archive_file: File = File::from_std(tempfile()?);
-    let mut buff = Vec::new();
-    // load file from disk
-    let mut file = File::open("archive.tar").await?;
-    file.read_to_end(&mut buff).await?;
-    archive_file.write_all(&buff).await?;
-
-    // Extract the file from the archive
-    let buff = extract_file_from_tar_archive(archive_file, "example.o").await?;
-
-    info!("File size: {}", buff.len());
+    if exit_code != Some(0) {
+        return Ok((result, None));
+    }
 
-    let mut file = File::from_std(tempfile()?);
-    file.write_all(&buff).await?;
-    file.seek(std::io::SeekFrom::Start(0)).await?;
+    let artifact_path = Path::new(SANDBOX_DIR).join(&artifact_name);
+    let artifact_path = artifact_path
+        .to_str()
+        .ok_or(crate::Error::InternalServerError)?;
+    let archive_bytes = get_file_from_container(docker, container_id, artifact_path).await?;
 
-    Ok(file)
+    Ok((result, Some(archive_bytes)))
 }
 
-pub async fn send_stdin_to_container(
+async fn attach_stdin(
     docker: &Docker,
     container_id: &str,
-    stdin: &str,
-) -> Result<()> {
-    let config = CreateExecOptions {
-        cmd: Some(vec![stdin.to_string()]),
+) -> Result<Pin<Box<dyn AsyncWrite + Send>>> {
+    let options = AttachContainerOptions::<String> {
+        stdin: Some(true),
+        stream: Some(true),
         ..Default::default()
     };
+    let attached = docker.attach_container(container_id, Some(options)).await?;
+    Ok(attached.input)
+}
 
-    Ok(())
+// Writes the input and closes stdin, so programs reading until EOF terminate
+async fn write_stdin(mut stdin: Pin<Box<dyn AsyncWrite + Send>>, input: &[u8]) {
+    // A program that exits without reading all of its input is not an error
+    if let Err(e) = stdin.write_all(input).await {
+        debug!("Stopped writing stdin: {}", e);
+    }
+    let _ = stdin.shutdown().await;
 }
 
 pub async fn run_preset(
     file: File,
     preset: impl ContainerPreset + std::marker::Copy,
+    stdin: &[u8],
+    job: &SandboxJob,
+) -> Result<ContainerOutput> {
+    run_preset_with_files(file, &[], preset, stdin, job).await
+}
+
+// Like run_preset, with extra files placed in the sandbox next to the program
+pub async fn run_preset_with_files(
+    file: File,
+    files: &[(&str, &[u8])],
+    preset: impl ContainerPreset + std::marker::Copy,
+    stdin: &[u8],
+    job: &SandboxJob,
 ) -> Result<ContainerOutput> {
     let docker = Docker::connect_with_local_defaults()?;
 
-    let container_name = preset.info().name;
     let image_name = preset.info().image;
 
     // Check if the image exists
@@ -284,61 +430,266 @@ pub async fn run_preset(
         false => get_image(preset).await?,
     };
 
-    // Remove the old container
-    remove_old_container(&docker, &container_name).await?;
-
     // Create a new container
-    let container_id = create_container(&docker, preset).await?;
+    let container_id = create_container(&docker, preset, job).await?;
+    let ran = run_in_container(&docker, &container_id, file, files, preset, stdin, job).await;
+
+    // Remove the container along with its sandbox volume
+    remove_after(&docker, &container_id, ran).await
+}
 
+// Runs the program in the created container and collects its output
+async fn run_in_container(
+    docker: &Docker,
+    container_id: &str,
+    file: File,
+    files: &[(&str, &[u8])],
+    preset: impl ContainerPreset + std::marker::Copy,
+    stdin: &[u8],
+    job: &SandboxJob,
+) -> Result<ContainerOutput> {
     // Copy the file into the container
-    let destination_path: &Path = Path::new("/program.o");
-    copy_file_into_container(&docker, &container_id, file, destination_path).await?;
+    let destination_path = Path::new(SANDBOX_DIR).join(preset.info().input);
+    copy_file_into_container(docker, container_id, file, &destination_path).await?;
+
+    for (name, contents) in files {
+        let mut extra_file = File::from_std(tempfile()?);
+        extra_file.write_all(contents).await?;
+        let destination_path = Path::new(SANDBOX_DIR).join(name);
+        copy_file_into_container(docker, container_id, extra_file, &destination_path).await?;
+    }
+
+    // Attach before starting so no input is lost
+    let stdin_stream = attach_stdin(docker, container_id).await?;
 
     // Start the container
-    start_container(&docker, &container_id).await?;
-
-    info!("Container started");
-
-    loop {
-        // Check if the container is running
-        let d = docker.inspect_container(&container_id, None).await?;
-
-        match d.state {
-            Some(state) => {
-                if state.running.is_some() {
-                    // Print container stats
-                    let stats = get_container_stats(&docker, &container_id).await?;
-                    info!("{:?}", stats.cpu_stats);
-                } else {
-                    break;
-                }
-            }
-            None => {
-                break;
-            }
-        }
-    }
+    start_container(docker, container_id).await?;
+    let started_at = Instant::now();
 
     info!("Waiting for container to finish");
 
-    // Wait for the container to finish
-    let container_stats = get_container_stats(&docker, &container_id).await?;
-    let container_logs = get_logs(&docker, preset).await?;
-    //let metrics = get_metrics(&docker, &container_id).await?;
-
-    // Stop the container
-    stop_container(&docker, &container_id).await?;
+    // Logs written before a timeout are still returned.
+    // Input is written concurrently, the program may not read it all.
+    let (exit_code, container_logs, ()) = tokio::join!(
+        wait_for_exit(docker, container_id, preset.timeout()),
+        get_logs(docker, container_id, preset, Stage::Run, job),
+        write_stdin(stdin_stream, stdin),
+    );
+    let duration = started_at.elapsed();
+    let exit_code = exit_code?;
+    let container_logs = container_logs?;
+
+    let state = docker.inspect_container(container_id, None).await?.state;
+    // Missing metrics do not change the verdict, but earn no resource points
+    let metrics = get_metrics(docker, container_id, duration)
+        .await
+        .unwrap_or_else(|e| {
+            warn!(
+                "Failed to read metrics of container {}: {}",
+                container_id, e
+            );
+            None
+        });
 
     let output = ContainerOutput {
-        logs: container_logs,
-        id: container_id,
-        exit_code: 0,
-        metrics: None,
+        output: container_logs,
+        id: container_id.to_string(),
+        // A killed container has no wait status, its exit code is in the state
+        exit_code: exit_code
+            .or_else(|| state.as_ref().and_then(|s| s.exit_code))
+            .unwrap_or(-1),
+        outcome: if exit_code.is_some() {
+            RunOutcome::from_state(state.as_ref())
+        } else {
+            RunOutcome::TimedOut
+        },
+        duration,
+        metrics,
     };
 
     Ok(output)
 }
 
+// What the next line of an interactive program turned out to be
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReadLine {
+    Line(String),
+    // Nothing was written before the timeout
+    TimedOut,
+    // The program closed its output, usually because it exited
+    Closed,
+}
+
+// A program that is talked to line by line while it runs, e.g. a game player.
+// Stdout is read by the caller, stderr is kept for the result.
+pub struct InteractiveContainer {
+    docker: Docker,
+    id: String,
+    input: Pin<Box<dyn AsyncWrite + Send>>,
+    output: Pin<
+        Box<
+            dyn futures::Stream<Item = std::result::Result<LogOutput, bollard::errors::Error>>
+                + Send,
+        >,
+    >,
+    // Stdout read past the last complete line
+    pending: Vec<u8>,
+    stderr: Vec<u8>,
+    truncated: bool,
+    limit: usize,
+    started_at: Instant,
+}
+
+impl InteractiveContainer {
+    pub async fn start(
+        file: File,
+        preset: impl ContainerPreset + std::marker::Copy,
+        job: &SandboxJob,
+    ) -> Result<Self> {
+        let docker = Docker::connect_with_local_defaults()?;
+
+        let image_name = preset.info().image;
+        if !image_exists(&docker, &image_name).await? {
+            get_image(preset).await?;
+        }
+
+        let container_id = create_container(&docker, preset, job).await?;
+        let destination_path = Path::new(SANDBOX_DIR).join(preset.info().input);
+
+        // The container is not owned by anyone until it is returned
+        let attached = match Self::attach(&docker, &container_id, file, &destination_path).await {
+            Ok(attached) => attached,
+            Err(e) => return remove_after(&docker, &container_id, Err(e)).await,
+        };
+
+        Ok(Self {
+            docker,
+            id: container_id,
+            input: attached.input,
+            output: attached.output,
+            pending: Vec::new(),
+            stderr: Vec::new(),
+            truncated: false,
+            limit: preset.limits().output_limit_bytes,
+            started_at: Instant::now(),
+        })
+    }
+
+    // Copies the program in, then attaches before starting so no output is lost
+    async fn attach(
+        docker: &Docker,
+        container_id: &str,
+        file: File,
+        destination_path: &Path,
+    ) -> Result<AttachContainerResults> {
+        copy_file_into_container(docker, container_id, file, destination_path).await?;
+
+        let options = AttachContainerOptions::<String> {
+            stdin: Some(true),
+            stdout: Some(true),
+            stderr: Some(true),
+            stream: Some(true),
+            ..Default::default()
+        };
+        let attached = docker.attach_container(container_id, Some(options)).await?;
+
+        start_container(docker, container_id).await?;
+        Ok(attached)
+    }
+
+    pub async fn send_line(&mut self, line: &str) -> Result<()> {
+        self.input.write_all(line.as_bytes()).await?;
+        self.input.write_all(b"\n").await?;
+        self.input.flush().await?;
+        Ok(())
+    }
+
+    // Lines longer than the output limit are cut off there
+    pub async fn read_line(&mut self, timeout: Duration) -> Result<ReadLine> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            if let Some(end) = self.pending.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = self.pending.drain(..=end).collect();
+                return Ok(ReadLine::Line(lossy_line(&line)));
+            }
+            if self.pending.len() >= self.limit {
+                self.truncated = true;
+                let line = std::mem::take(&mut self.pending);
+                return Ok(ReadLine::Line(lossy_line(&line)));
+            }
+
+            match tokio::time::timeout_at(deadline, self.output.next()).await {
+                Err(_) => return Ok(ReadLine::TimedOut),
+                // A last line without a newline still counts
+                Ok(None) if !self.pending.is_empty() => {
+                    let line = std::mem::take(&mut self.pending);
+                    return Ok(ReadLine::Line(lossy_line(&line)));
+                }
+                Ok(None) => return Ok(ReadLine::Closed),
+                Ok(Some(Err(e))) => return Err(e.into()),
+                Ok(Some(Ok(LogOutput::StdOut { message } | LogOutput::Console { message }))) => {
+                    self.pending.extend_from_slice(&message);
+                }
+                Ok(Some(Ok(LogOutput::StdErr { message }))) => {
+                    let remaining = self.limit.saturating_sub(self.stderr.len());
+                    if message.len() > remaining {
+                        self.truncated = true;
+                    }
+                    self.stderr
+                        .extend_from_slice(&message[..message.len().min(remaining)]);
+                }
+                Ok(Some(Ok(LogOutput::StdIn { .. }))) => {}
+            }
+        }
+    }
+
+    // Closes stdin and gives the program a moment to exit before it is killed
+    pub async fn stop(mut self, grace: Duration) -> Result<ContainerOutput> {
+        let _ = self.input.shutdown().await;
+
+        // The container is removed even if it could not be waited for
+        let stopped = async {
+            let exit_code = wait_for_exit(&self.docker, &self.id, grace).await?;
+            let duration = self.started_at.elapsed();
+
+            let state = self.docker.inspect_container(&self.id, None).await?.state;
+            let metrics = get_metrics(&self.docker, &self.id, duration)
+                .await
+                .unwrap_or_else(|e| {
+                    warn!("Failed to read metrics of container {}: {}", self.id, e);
+                    None
+                });
+            Ok::<_, AppError>((exit_code, duration, state, metrics))
+        }
+        .await;
+
+        let (exit_code, duration, state, metrics) =
+            remove_after(&self.docker, &self.id, stopped).await?;
+
+        Ok(ContainerOutput {
+            output: CapturedOutput {
+                stdout: self.pending,
+                stderr: self.stderr,
+                truncated: self.truncated,
+            },
+            exit_code: exit_code
+                .or_else(|| state.as_ref().and_then(|s| s.exit_code))
+                .unwrap_or(-1),
+            // Killing a program that is done playing is not a timeout
+            outcome: RunOutcome::from_state(state.as_ref()),
+            id: self.id,
+            duration,
+            metrics,
+        })
+    }
+}
+
+fn lossy_line(line: &[u8]) -> String {
+    String::from_utf8_lossy(line)
+        .trim_end_matches(['\n', '\r'])
+        .to_string()
+}
+
 pub async fn get_container_stats(docker: &Docker, container_id: &str) -> Result<Stats> {
     let options = Some(StatsOptions {
         stream: false,
@@ -357,54 +708,104 @@ pub async fn get_container_stats(docker: &Docker, container_id: &str) -> Result<
     }
 }
 
-pub async fn get_metrics(docker: &Docker, container_id: &str) -> Result<Metrics> {
-    let options = Some(StatsOptions {
-        stream: false,
-        one_shot: true,
-    });
+// Reads the cgroup accounting the run wrapper left in the sandbox.
+// None if the program was killed before the wrapper could write it,
+// or if the file is not what the wrapper writes.
+pub async fn get_metrics(
+    docker: &Docker,
+    container_id: &str,
+    wall_time: Duration,
+) -> Result<Option<Metrics>> {
+    let metrics_path = Path::new(SANDBOX_DIR).join(METRICS_FILE);
+    let metrics_path = metrics_path
+        .to_str()
+        .ok_or(crate::Error::InternalServerError)?;
+
+    let Ok(archive_bytes) = get_file_from_container(docker, container_id, metrics_path).await
+    else {
+        return Ok(None);
+    };
 
-    let stats = docker
-        .stats(container_id, options)
-        .try_collect::<Vec<_>>()
-        .await?;
+    let mut archive_file: File = File::from_std(tempfile()?);
+    archive_file.write_all(&archive_bytes).await?;
+    let contents = extract_file_from_tar_archive(archive_file, METRICS_FILE).await?;
 
-    let mut cpu_user = 0.0;
-    let mut cpu_system = 0.0;
-    let mut memory = 0.0;
+    Ok(parse_metrics(
+        &String::from_utf8_lossy(&contents),
+        wall_time,
+    ))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunOutcome {
+    Exited,
+    // Killed by the kernel for exceeding the memory limit
+    MemoryLimitExceeded,
+    // Killed for running past the wall-clock deadline
+    TimedOut,
+}
 
-    info!("{:?}", stats);
+impl RunOutcome {
+    fn from_state(state: Option<&ContainerState>) -> Self {
+        match state.and_then(|s| s.oom_killed) {
+            Some(true) => Self::MemoryLimitExceeded,
+            _ => Self::Exited,
+        }
+    }
+}
 
-    for stat in stats {
-        let cpu_stats = stat.cpu_stats;
-        let memory_stats = stat.memory_stats;
+// Stdout and stderr of a container, each capped at the preset output limit
+#[derive(Debug, Default)]
+pub struct CapturedOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub truncated: bool,
+}
 
-        let cpu_usage = cpu_stats.cpu_usage.total_usage as f64;
-        let system_cpu_usage: f64 = cpu_stats.system_cpu_usage.expect("Not found") as f64;
-        let memory_usage = memory_stats.usage.expect("Not found") as f64;
+impl CapturedOutput {
+    pub fn stdout_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.stdout).into_owned()
+    }
 
-        cpu_user = cpu_usage / system_cpu_usage * 100.0;
-        cpu_system = (cpu_usage / system_cpu_usage) * 100.0;
-        memory = memory_usage / memory_stats.limit.expect("Not found") as f64 * 100.0;
+    pub fn stderr_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.stderr).into_owned()
     }
+}
 
-    Ok(Metrics {
-        cpu_user,
-        cpu_system,
-        memory,
-    })
+#[derive(Debug, Serialize)]
+pub struct CompileResult {
+    pub success: bool,
+    // None if the compiler was killed
+    pub exit_code: Option<i64>,
+    pub outcome: RunOutcome,
+    pub diagnostics: Vec<Diagnostic>,
+    pub output: String,
+    #[serde(skip)]
+    pub artifact: Option<File>,
 }
 
-#[derive(Debug)]
-pub struct Metrics {
-    pub cpu_user: f64,
-    pub cpu_system: f64,
-    pub memory: f64,
+impl CompileResult {
+    // Interpreted languages run the source directly
+    pub const fn skipped(source: File) -> Self {
+        Self {
+            success: true,
+            exit_code: None,
+            outcome: RunOutcome::Exited,
+            diagnostics: Vec::new(),
+            output: String::new(),
+            artifact: Some(source),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct ContainerOutput {
-    pub logs: Vec<LogOutput>,
+    pub output: CapturedOutput,
     pub id: String,
     pub exit_code: i64,
+    pub outcome: RunOutcome,
+    // Wall time from start until the container stopped or was killed
+    pub duration: Duration,
     pub metrics: Option<Metrics>,
 }
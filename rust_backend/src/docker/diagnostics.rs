@@ -0,0 +1,76 @@
+use lazy_regex::regex_captures;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Severity {
+    fn parse(severity: &str) -> Self {
+        match severity {
+            "warning" => Self::Warning,
+            "note" | "help" => Self::Note,
+            _ => Self::Error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: u32,
+    pub column: Option<u32>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+// Parses compiler output into diagnostics.
+// Understands the gcc/g++/javac/go style `file:line:col: severity: message`
+// and the rustc style where the location follows on a ` --> file:line:col` line.
+pub fn parse_diagnostics(stderr: &str) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    // rustc prints the message before the location
+    let mut pending: Option<(Severity, String)> = None;
+
+    for line in stderr.lines() {
+        if let Some((_, severity, message)) =
+            regex_captures!(r"^(error|warning|note|help)(?:\[\w+\])?: (.*)$", line)
+        {
+            pending = Some((Severity::parse(severity), message.to_string()));
+            continue;
+        }
+
+        if let Some((_, file, line, column)) = regex_captures!(r"^\s*--> (.+):(\d+):(\d+)$", line) {
+            if let Some((severity, message)) = pending.take() {
+                diagnostics.push(Diagnostic {
+                    file: file.to_string(),
+                    line: line.parse().unwrap_or_default(),
+                    column: column.parse().ok(),
+                    severity,
+                    message,
+                });
+            }
+            continue;
+        }
+
+        if let Some((_, file, line, column, severity, message)) = regex_captures!(
+            r"^(?:\./)?([^\s:][^:]*):(\d+):(?:(\d+):)? (?:(fatal error|error|warning|note): )?(.*)$",
+            line
+        ) {
+            diagnostics.push(Diagnostic {
+                file: file.to_string(),
+                line: line.parse().unwrap_or_default(),
+                column: column.parse().ok(),
+                // go vet and the go compiler only print errors, without a severity
+                severity: Severity::parse(severity),
+                message: message.to_string(),
+            });
+        }
+    }
+
+    diagnostics
+}
@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+
+use bollard::container::{ListContainersOptions, RemoveContainerOptions};
+use bollard::Docker;
+use maplit::hashmap;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::database::models::JobState;
+use crate::Result;
+
+// Every container created by the backend carries this label
+pub const MANAGED_LABEL: &str = "gymnasiearbete.managed";
+pub const JOB_LABEL: &str = "gymnasiearbete.job_id";
+pub const USER_LABEL: &str = "gymnasiearbete.user_id";
+pub const FILE_LABEL: &str = "gymnasiearbete.file_id";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    Compile,
+    Run,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+// Sent to everyone watching a job while it is queued or running
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JobEvent {
+    State {
+        state: JobState,
+    },
+    // Output as it is written, only the part within the output limit is sent
+    Output {
+        stage: Stage,
+        stream: OutputStream,
+        data: String,
+    },
+    // Always the last event of a job
+    Finished {
+        state: JobState,
+    },
+}
+
+impl JobEvent {
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::State { .. } => "state",
+            Self::Output { .. } => "output",
+            Self::Finished { .. } => "finished",
+        }
+    }
+}
+
+// Identifies the containers belonging to one compile and run of a submission
+#[derive(Clone, Debug)]
+pub struct SandboxJob {
+    pub id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub file_id: Option<Uuid>,
+    pub events: Option<broadcast::Sender<JobEvent>>,
+}
+
+impl SandboxJob {
+    pub fn new(user_id: Option<Uuid>, file_id: Option<Uuid>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            user_id,
+            file_id,
+            events: None,
+        }
+    }
+
+    // Nobody may be listening, in which case the event is dropped
+    pub fn emit(&self, event: JobEvent) {
+        if let Some(events) = &self.events {
+            let _ = events.send(event);
+        }
+    }
+
+    // The same job without subscribers, for containers whose output
+    // must not be streamed, e.g. checkers that read hidden test data
+    pub fn silent(&self) -> Self {
+        Self {
+            events: None,
+            ..self.clone()
+        }
+    }
+
+    // Jobs that are not started on behalf of a user, e.g. self tests
+    pub fn anonymous() -> Self {
+        Self::new(None, None)
+    }
+
+    // Unique per job so concurrent runs of the same preset never collide
+    pub fn container_name(&self, preset_name: &str) -> String {
+        format!("{}-{}", preset_name, self.id)
+    }
+
+    pub fn labels(&self) -> HashMap<String, String> {
+        let mut labels = hashmap! {
+            MANAGED_LABEL.to_string() => "true".to_string(),
+            JOB_LABEL.to_string() => self.id.to_string(),
+        };
+        if let Some(user_id) = self.user_id {
+            labels.insert(USER_LABEL.to_string(), user_id.to_string());
+        }
+        if let Some(file_id) = self.file_id {
+            labels.insert(FILE_LABEL.to_string(), file_id.to_string());
+        }
+        labels
+    }
+}
+
+// Removes containers left behind by a previous run of the backend.
+// Every run removes its own container, even when it fails, so only a
+// backend that stopped mid-run leaves any. Only called at startup, when
+// no job can be running yet.
+pub async fn remove_orphaned_containers() -> Result<usize> {
+    remove_labeled_containers(MANAGED_LABEL.to_string()).await
+}
+
+// Removes the containers of a cancelled job
+pub async fn remove_job_containers(job_id: Uuid) -> Result<usize> {
+    remove_labeled_containers(format!("{JOB_LABEL}={job_id}")).await
+}
+
+async fn remove_labeled_containers(label: String) -> Result<usize> {
+    let docker = Docker::connect_with_local_defaults()?;
+
+    let options = ListContainersOptions {
+        all: true,
+        filters: hashmap! { "label".to_string() => vec![label] },
+        ..Default::default()
+    };
+    let containers = docker.list_containers(Some(options)).await?;
+
+    let mut removed = 0;
+    for container in containers {
+        let Some(container_id) = container.id else {
+            continue;
+        };
+
+        let options = RemoveContainerOptions {
+            force: true,
+            v: true,
+            ..Default::default()
+        };
+        match docker.remove_container(&container_id, Some(options)).await {
+            Ok(()) => removed += 1,
+            Err(e) => warn!("Failed to remove container {}: {}", container_id, e),
+        }
+    }
+
+    Ok(removed)
+}
@@ -0,0 +1,128 @@
+use serde::{Deserialize, Serialize};
+
+use crate::utils::get_extension_from_filename;
+
+// Directory inside the sandbox where sources and artifacts are placed
+pub const SANDBOX_DIR: &str = "/sandbox";
+
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, strum_macros::AsRefStr,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Language {
+    #[default]
+    C,
+    Cpp,
+    Rust,
+    Python,
+    Java,
+    Go,
+}
+
+pub struct LanguageInfo {
+    pub image: &'static str,
+    pub tag: &'static str,
+    pub source_file: &'static str,
+    pub artifact_file: &'static str,
+    // None for interpreted languages, the source is run directly
+    pub compile_command: Option<&'static str>,
+    pub run_command: &'static str,
+}
+
+const C: LanguageInfo = LanguageInfo {
+    image: "gcc",
+    tag: "latest",
+    source_file: "main.c",
+    artifact_file: "main",
+    compile_command: Some("gcc -O2 main.c -o main -lm"),
+    run_command: "chmod +x main && ./main",
+};
+
+const CPP: LanguageInfo = LanguageInfo {
+    image: "gcc",
+    tag: "latest",
+    source_file: "main.cpp",
+    artifact_file: "main",
+    compile_command: Some("g++ -O2 main.cpp -o main"),
+    run_command: "chmod +x main && ./main",
+};
+
+const RUST: LanguageInfo = LanguageInfo {
+    image: "rust",
+    tag: "latest",
+    source_file: "main.rs",
+    artifact_file: "main",
+    compile_command: Some("rustc -O main.rs -o main"),
+    run_command: "chmod +x main && ./main",
+};
+
+const PYTHON: LanguageInfo = LanguageInfo {
+    image: "python",
+    tag: "3.12-slim",
+    source_file: "main.py",
+    artifact_file: "main.py",
+    compile_command: None,
+    run_command: "python3 main.py",
+};
+
+const JAVA: LanguageInfo = LanguageInfo {
+    image: "eclipse-temurin",
+    tag: "21",
+    source_file: "Main.java",
+    artifact_file: "Main.class",
+    compile_command: Some("javac Main.java"),
+    run_command: "java Main",
+};
+
+const GO: LanguageInfo = LanguageInfo {
+    image: "golang",
+    tag: "1.22",
+    source_file: "main.go",
+    artifact_file: "main",
+    compile_command: Some("go build -o main main.go"),
+    run_command: "chmod +x main && ./main",
+};
+
+impl Language {
+    pub const ALL: [Self; 6] = [
+        Self::C,
+        Self::Cpp,
+        Self::Rust,
+        Self::Python,
+        Self::Java,
+        Self::Go,
+    ];
+
+    pub const fn info(self) -> &'static LanguageInfo {
+        match self {
+            Self::C => &C,
+            Self::Cpp => &CPP,
+            Self::Rust => &RUST,
+            Self::Python => &PYTHON,
+            Self::Java => &JAVA,
+            Self::Go => &GO,
+        }
+    }
+
+    pub const fn is_compiled(self) -> bool {
+        self.info().compile_command.is_some()
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim().to_lowercase();
+        Self::ALL.into_iter().find(|l| l.as_ref() == name)
+    }
+
+    pub fn from_filename(filename: &str) -> Option<Self> {
+        match get_extension_from_filename(filename)? {
+            "c" => Some(Self::C),
+            "cpp" | "cc" | "cxx" => Some(Self::Cpp),
+            "rs" => Some(Self::Rust),
+            "py" => Some(Self::Python),
+            "java" => Some(Self::Java),
+            "go" => Some(Self::Go),
+            _ => None,
+        }
+    }
+}
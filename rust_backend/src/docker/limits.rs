@@ -0,0 +1,151 @@
+use bollard::service::{
+    HostConfig, Mount, MountTypeEnum, MountVolumeOptions, MountVolumeOptionsDriverConfig,
+    ResourcesUlimits,
+};
+use maplit::hashmap;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use super::languages::SANDBOX_DIR;
+
+const MEGABYTE: i64 = 1024 * 1024;
+
+// Length of a CPU scheduling period in microseconds
+const CPU_PERIOD: i64 = 100_000;
+
+// Resource limits applied to a sandbox container.
+// Missing fields fall back to the runner defaults when deserialized.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ResourceLimits {
+    pub memory_bytes: i64,
+    // Memory plus swap, equal to memory_bytes disables swap
+    pub memory_swap_bytes: i64,
+    // Microseconds of CPU time per 100ms period, 100_000 is one core
+    pub cpu_quota: i64,
+    pub pids_limit: i64,
+    pub open_files: i64,
+    pub file_size_bytes: i64,
+    pub tmpfs_size_bytes: i64,
+    // Size of the working directory, the only other writable place
+    pub sandbox_size_bytes: i64,
+    // Wall-clock deadline, the container is killed once it passes
+    pub wall_time_ms: u64,
+    // Bytes kept per output stream, the rest is dropped
+    pub output_limit_bytes: usize,
+}
+
+pub const RUNNER_LIMITS: ResourceLimits = ResourceLimits {
+    memory_bytes: 256 * MEGABYTE,
+    memory_swap_bytes: 256 * MEGABYTE,
+    cpu_quota: CPU_PERIOD,
+    pids_limit: 64,
+    open_files: 64,
+    file_size_bytes: 16 * MEGABYTE,
+    tmpfs_size_bytes: 16 * MEGABYTE,
+    sandbox_size_bytes: 64 * MEGABYTE,
+    wall_time_ms: 10_000,
+    output_limit_bytes: 1024 * 1024,
+};
+
+// Compilers need more headroom than the programs they build
+pub const COMPILER_LIMITS: ResourceLimits = ResourceLimits {
+    memory_bytes: 1024 * MEGABYTE,
+    memory_swap_bytes: 1024 * MEGABYTE,
+    cpu_quota: 2 * CPU_PERIOD,
+    pids_limit: 256,
+    open_files: 1024,
+    file_size_bytes: 256 * MEGABYTE,
+    tmpfs_size_bytes: 512 * MEGABYTE,
+    sandbox_size_bytes: 512 * MEGABYTE,
+    wall_time_ms: 60_000,
+    output_limit_bytes: 64 * 1024,
+};
+
+impl Default for ResourceLimits {
+    fn default() -> Self {
+        RUNNER_LIMITS
+    }
+}
+
+impl ResourceLimits {
+    pub const fn wall_time(&self) -> Duration {
+        Duration::from_millis(self.wall_time_ms)
+    }
+
+    // The most CPU time a program can use before its wall time runs out,
+    // the quota is per period so a quota of two cores doubles it
+    pub fn cpu_time(&self) -> Duration {
+        let millis = i128::from(self.wall_time_ms) * i128::from(self.cpu_quota.max(0))
+            / i128::from(CPU_PERIOD);
+        Duration::from_millis(u64::try_from(millis).unwrap_or(u64::MAX))
+    }
+
+    // Docker refuses less than 6MB of memory and a quota under 1ms,
+    // everything else only has to be positive
+    pub const fn is_valid(&self) -> bool {
+        self.memory_bytes >= 6 * MEGABYTE
+            && self.cpu_quota >= 1000
+            && self.pids_limit > 0
+            && self.open_files > 0
+            && self.file_size_bytes > 0
+            && self.tmpfs_size_bytes > 0
+            && self.sandbox_size_bytes > 0
+            && self.wall_time_ms > 0
+            && self.output_limit_bytes > 0
+    }
+
+    pub fn host_config(&self) -> HostConfig {
+        HostConfig {
+            memory: Some(self.memory_bytes),
+            memory_swap: Some(self.memory_swap_bytes.max(self.memory_bytes)),
+            cpu_period: Some(CPU_PERIOD),
+            cpu_quota: Some(self.cpu_quota),
+            pids_limit: Some(self.pids_limit),
+            ulimits: Some(vec![
+                ulimit("nofile", self.open_files),
+                ulimit("fsize", self.file_size_bytes),
+                ulimit("core", 0),
+            ]),
+            readonly_rootfs: Some(true),
+            tmpfs: Some(hashmap! {
+                "/tmp".to_string() => format!("rw,nosuid,nodev,size={}", self.tmpfs_size_bytes),
+            }),
+            mounts: Some(vec![self.sandbox_mount()]),
+            network_mode: Some("none".to_string()),
+            cap_drop: Some(vec!["ALL".to_string()]),
+            security_opt: Some(vec!["no-new-privileges".to_string()]),
+            ..Default::default()
+        }
+    }
+
+    // Files are copied in before the container starts, which a tmpfs mount
+    // does not allow, so the working directory is a volume backed by one.
+    // It is anonymous and removed together with the container.
+    fn sandbox_mount(&self) -> Mount {
+        Mount {
+            target: Some(SANDBOX_DIR.to_string()),
+            typ: Some(MountTypeEnum::VOLUME),
+            volume_options: Some(MountVolumeOptions {
+                driver_config: Some(MountVolumeOptionsDriverConfig {
+                    name: Some("local".to_string()),
+                    options: Some(hashmap! {
+                        "type".to_string() => "tmpfs".to_string(),
+                        "device".to_string() => "tmpfs".to_string(),
+                        "o".to_string() => format!("size={}", self.sandbox_size_bytes),
+                    }),
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+fn ulimit(name: &str, value: i64) -> ResourcesUlimits {
+    ResourcesUlimits {
+        name: Some(name.to_string()),
+        soft: Some(value),
+        hard: Some(value),
+    }
+}
@@ -0,0 +1,82 @@
+use std::time::Duration;
+
+use super::languages::SANDBOX_DIR;
+
+// Written by the run wrapper once the program has exited
+pub const METRICS_FILE: &str = ".metrics";
+
+// cgroup v1 reports cpu time in USER_HZ ticks
+const MICROS_PER_TICK: u64 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metrics {
+    pub cpu_user: Duration,
+    pub cpu_system: Duration,
+    pub wall_time: Duration,
+    pub peak_memory_bytes: u64,
+}
+
+impl Metrics {
+    pub fn cpu_time(&self) -> Duration {
+        self.cpu_user + self.cpu_system
+    }
+
+    pub const fn peak_memory_kb(&self) -> u64 {
+        self.peak_memory_bytes / 1024
+    }
+}
+
+// Runs the command and then copies the container's cgroup accounting into
+// the sandbox, keeping the program's exit code. The container has its own
+// cgroup namespace, so the counters cover exactly this run.
+// The program can write to the sandbox too, so the wrapper (PID 1) first kills
+// whatever it left running and removes anything already at the metrics path.
+// If the file still cannot be written, the run has no metrics.
+pub fn with_metrics(command: &str) -> String {
+    let path = format!("{SANDBOX_DIR}/{METRICS_FILE}");
+    format!(
+        "{command}\n\
+         status=$?\n\
+         kill -9 -1 2>/dev/null\n\
+         chmod u+w {SANDBOX_DIR} 2>/dev/null; chmod -R u+w {path} 2>/dev/null\n\
+         rm -rf {path}\n\
+         {{ cat /sys/fs/cgroup/cpu.stat /sys/fs/cgroup/cpuacct/cpuacct.stat; \
+         echo \"memory_peak $(cat /sys/fs/cgroup/memory.peak /sys/fs/cgroup/memory/memory.max_usage_in_bytes)\"; \
+         }} 2>/dev/null > {path}\n\
+         exit $status"
+    )
+}
+
+// Parses the file written by `with_metrics`, understands both cgroup v2
+// (`user_usec`, `memory.peak`) and cgroup v1 (`user` ticks, `max_usage_in_bytes`).
+// Missing, unparsable or repeated counters give None rather than a guess.
+pub fn parse_metrics(contents: &str, wall_time: Duration) -> Option<Metrics> {
+    let mut cpu_user = None;
+    let mut cpu_system = None;
+    let mut peak_memory_bytes = None;
+
+    for line in contents.lines() {
+        let Some((key, value)) = line.split_once(' ') else {
+            continue;
+        };
+        let (counter, unit) = match key {
+            "user_usec" => (&mut cpu_user, 1),
+            "system_usec" => (&mut cpu_system, 1),
+            "user" => (&mut cpu_user, MICROS_PER_TICK),
+            "system" => (&mut cpu_system, MICROS_PER_TICK),
+            "memory_peak" => (&mut peak_memory_bytes, 1),
+            _ => continue,
+        };
+        let value = value.trim().parse::<u64>().ok()?.checked_mul(unit)?;
+        if counter.replace(value).is_some() {
+            return None;
+        }
+    }
+
+    Some(Metrics {
+        cpu_user: Duration::from_micros(cpu_user?),
+        cpu_system: Duration::from_micros(cpu_system?),
+        wall_time,
+        peak_memory_bytes: peak_memory_bytes?,
+    })
+}
@@ -1,4 +1,9 @@
 pub mod api;
 pub mod build_image;
 pub mod common;
+pub mod diagnostics;
+pub mod job;
+pub mod languages;
+pub mod limits;
+pub mod metrics;
 pub mod profiles;
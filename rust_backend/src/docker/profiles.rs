@@ -8,6 +8,11 @@ use bollard::{
 use derived::Constdef;
 use futures::future::Lazy;
 use std::default;
+use std::time::Duration;
+
+use super::languages::{Language, SANDBOX_DIR};
+use super::limits::{ResourceLimits, COMPILER_LIMITS, RUNNER_LIMITS};
+use super::metrics::with_metrics;
 
 pub struct ContainerInfo {
     pub name: String,
@@ -20,14 +25,14 @@ pub struct ContainerInfo {
 
 pub trait ContainerPreset: Send + Sync {
     fn info(&self) -> ContainerInfo;
-    fn start_stdin(&self) -> &str {
-        ""
+    fn limits(&self) -> ResourceLimits {
+        ResourceLimits::default()
     }
     fn host_config(&self) -> HostConfig {
-        HostConfig {
-            auto_remove: Some(true),
-            ..Default::default()
-        }
+        self.limits().host_config()
+    }
+    fn timeout(&self) -> Duration {
+        self.limits().wall_time()
     }
     fn container_config(&self) -> Config<String> {
         Config {
@@ -37,8 +42,9 @@ pub trait ContainerPreset: Send + Sync {
         }
     }
     fn logs_options(&self) -> LogsOptions<String> {
+        // Logs are read while the container runs so they can be streamed
         LogsOptions {
-            follow: false,
+            follow: true,
             stdout: true,
             stderr: true,
             ..Default::default()
@@ -67,32 +73,123 @@ pub trait ContainerPreset: Send + Sync {
 }
 
 pub const HELLO_WORLD_PRESET: HelloWorldPreset = HelloWorldPreset;
-pub const COMPILER_PRESET: CompilerPreset = CompilerPreset;
-pub const CODE_RUNNER_PRESET: CodeRunnerPreset = CodeRunnerPreset;
+pub const COMPILER_PRESET: CompilerPreset = CompilerPreset::new(Language::C);
+pub const CODE_RUNNER_PRESET: CodeRunnerPreset = CodeRunnerPreset::new(Language::C);
+
+// Returns the compile and run presets for a language.
+// Interpreted languages have no compile stage.
+pub const fn language_presets(language: Language) -> (Option<CompilerPreset>, CodeRunnerPreset) {
+    let compiler = if language.is_compiled() {
+        Some(CompilerPreset::new(language))
+    } else {
+        None
+    };
+    (compiler, CodeRunnerPreset::new(language))
+}
 
 #[derive(Clone, Copy)]
-pub struct CodeRunnerPreset;
+pub struct CodeRunnerPreset {
+    pub language: Language,
+    pub limits: ResourceLimits,
+    // Prefix of the container name, programs running side by side need their own
+    pub role: &'static str,
+}
+
+impl CodeRunnerPreset {
+    pub const fn new(language: Language) -> Self {
+        Self {
+            language,
+            limits: RUNNER_LIMITS,
+            role: "code-runner",
+        }
+    }
+
+    pub const fn with_role(mut self, role: &'static str) -> Self {
+        self.role = role;
+        self
+    }
+
+    pub const fn with_limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+}
+
 impl ContainerPreset for CodeRunnerPreset {
     fn info(&self) -> ContainerInfo {
+        let language = self.language.info();
         ContainerInfo {
-            name: "code-runner".to_string(),
-            image: "linuxkit/kernel-perf".to_string(),
-            tag: "latest".to_string(),
+            name: format!("{}-{}", self.role, self.language.as_ref()),
+            image: language.image.to_string(),
+            tag: language.tag.to_string(),
             remote: true,
-            input: "program.o".to_string(),
-            output: todo!(),
+            input: language.artifact_file.to_string(),
+            output: String::new(),
         }
     }
+    fn limits(&self) -> ResourceLimits {
+        self.limits
+    }
     fn container_config(&self) -> Config<String> {
         Config {
-            image: Some(self.info().image),
-            // Command we want to run: first chmod +x the file, then run it and output the result to stdout, the program is named program.o
-            entrypoint: construct_command(&format!(
-                "sh -c 'chmod +x {} && ./{}'",
-                self.info().input,
-                self.info().input
-            )),
-            ..Default::default()
+            entrypoint: construct_shell_command(&with_metrics(self.language.info().run_command)),
+            // Stdin is attached before start and closed once the input is written
+            open_stdin: Some(true),
+            stdin_once: Some(true),
+            attach_stdin: Some(true),
+            ..sandbox_config(self.info().image)
+        }
+    }
+}
+
+// Files a checker is given, passed in this order as its arguments
+pub const CHECKER_INPUT_FILE: &str = "input.txt";
+pub const CHECKER_EXPECTED_FILE: &str = "expected.txt";
+pub const CHECKER_OUTPUT_FILE: &str = "output.txt";
+
+// Runs a checker program with the test input, the expected output and the
+// contestant's output. Its stdin is empty and its usage is not measured.
+#[derive(Clone, Copy)]
+pub struct CheckerPreset {
+    pub language: Language,
+    pub limits: ResourceLimits,
+}
+
+impl CheckerPreset {
+    pub const fn new(language: Language) -> Self {
+        Self {
+            language,
+            limits: RUNNER_LIMITS,
+        }
+    }
+}
+
+impl ContainerPreset for CheckerPreset {
+    fn info(&self) -> ContainerInfo {
+        let language = self.language.info();
+        ContainerInfo {
+            name: format!("checker-{}", self.language.as_ref()),
+            image: language.image.to_string(),
+            tag: language.tag.to_string(),
+            remote: true,
+            input: language.artifact_file.to_string(),
+            output: String::new(),
+        }
+    }
+    fn limits(&self) -> ResourceLimits {
+        self.limits
+    }
+    fn container_config(&self) -> Config<String> {
+        let command = format!(
+            "{} {CHECKER_INPUT_FILE} {CHECKER_EXPECTED_FILE} {CHECKER_OUTPUT_FILE}",
+            self.language.info().run_command
+        );
+        Config {
+            entrypoint: construct_shell_command(&command),
+            open_stdin: Some(true),
+            stdin_once: Some(true),
+            attach_stdin: Some(true),
+            ..sandbox_config(self.info().image)
         }
     }
 }
@@ -106,8 +203,8 @@ impl ContainerPreset for HelloWorldPreset {
             image: "hello-world".to_string(),
             tag: "latest".to_string(),
             remote: true,
-            input: todo!(),
-            output: todo!(),
+            input: String::new(),
+            output: String::new(),
         }
     }
     fn container_config(&self) -> Config<String> {
@@ -119,37 +216,66 @@ impl ContainerPreset for HelloWorldPreset {
     }
 }
 
-const GCC_COMMAND: &str = "gcc";
-const INPUT_FILE: &str = "example.c";
-const OUTPUT_FILE: &str = "example.o";
+#[derive(Clone, Copy)]
+pub struct CompilerPreset {
+    pub language: Language,
+    pub limits: ResourceLimits,
+}
+
+impl CompilerPreset {
+    pub const fn new(language: Language) -> Self {
+        Self {
+            language,
+            limits: COMPILER_LIMITS,
+        }
+    }
 
-fn construct_gcc_command(base: &str, input: &str, output: &str) -> String {
-    format!("{} {} -o {}", base, input, output)
+    pub const fn with_limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = limits;
+        self
+    }
 }
 
-#[derive(Clone, Copy)]
-pub struct CompilerPreset;
 impl ContainerPreset for CompilerPreset {
+    fn limits(&self) -> ResourceLimits {
+        self.limits
+    }
     fn container_config(&self) -> Config<String> {
         Config {
-            image: Some(self.info().image),
-            cmd: construct_command(&construct_gcc_command(GCC_COMMAND, INPUT_FILE, OUTPUT_FILE)),
-            ..Default::default()
+            cmd: self
+                .language
+                .info()
+                .compile_command
+                .and_then(construct_shell_command),
+            ..sandbox_config(self.info().image)
         }
     }
 
     fn info(&self) -> ContainerInfo {
+        let language = self.language.info();
         ContainerInfo {
-            name: "gcc".to_string(),
-            image: "gcc".to_string(),
-            tag: "latest".to_string(),
+            name: format!("compiler-{}", self.language.as_ref()),
+            image: language.image.to_string(),
+            tag: language.tag.to_string(),
             remote: true,
-            input: INPUT_FILE.to_string(),
-            output: OUTPUT_FILE.to_string(),
+            input: language.source_file.to_string(),
+            output: language.artifact_file.to_string(),
         }
     }
 }
 
+// Container config shared by the compile and run stages
+fn sandbox_config(image: String) -> Config<String> {
+    Config {
+        image: Some(image),
+        // The root filesystem is read-only, the sandbox directory is mounted by the limits
+        working_dir: Some(SANDBOX_DIR.to_string()),
+        // Compilers write caches to $HOME, which must be on the tmpfs
+        env: Some(vec!["HOME=/tmp".to_string()]),
+        ..Default::default()
+    }
+}
+
 // Slice the input string into a vector of strings
 fn construct_command(input: &str) -> Option<Vec<String>> {
     let mut command = input
@@ -158,3 +284,8 @@ fn construct_command(input: &str) -> Option<Vec<String>> {
         .collect::<Vec<String>>();
     Some(command)
 }
+
+// Run the input through a shell so that operators like && work
+fn construct_shell_command(input: &str) -> Option<Vec<String>> {
+    Some(vec!["sh".to_string(), "-c".to_string(), input.to_string()])
+}
@@ -16,6 +16,7 @@ pub enum Error {
     UserNotFound,
     WrongPassword,
     FileNotFound,
+    Forbidden,
 
     // -- Database errors.
     DatabaseConnectionFail,
@@ -29,10 +30,47 @@ pub enum Error {
     AuthFailTokenWrongFormat,
     AuthFailCtxNotInRequestExt,
     AuthFailInvalidToken,
+    MissingTokenScope,
 
     InternalServerError,
     FailedToCalculateScore,
 
+    // -- Submission errors.
+    UnsupportedLanguage,
+    CompilationFailed,
+    InputTooLarge,
+
+    // -- Challenge errors.
+    ChallengeNotFound,
+    TestCaseNotFound,
+    InvalidChallenge,
+    LanguageNotAllowed,
+
+    // -- Tournament errors.
+    TournamentNotFound,
+    MatchNotFound,
+    TournamentAlreadyStarted,
+    NotEnoughEntries,
+
+    // -- Role errors.
+    InvalidRole,
+
+    // -- Personal access token errors.
+    PersonalTokenNotFound,
+    InvalidPersonalToken,
+
+    // -- Contest errors.
+    ContestNotFound,
+    ContestNotRunning,
+    NotRegistered,
+    InvalidContest,
+    ContestSubmissionLocked,
+
+    // -- Queue errors.
+    QueueFull,
+    JobNotFound,
+    JobAlreadyFinished,
+
     // -- Model errors.
     TicketDeleteFailIdNotFound { id: u64 },
 }
@@ -49,8 +87,10 @@ impl IntoResponse for Error {
     fn into_response(self) -> Response {
         println!("->> {:<12} - {self:?}", "INTO_RES");
 
-        // Create a placeholder Axum response.
-        let mut response = StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        // Errors returned by middlewares and extractors reach the client as is
+        let (status, client_error) = self.client_status_and_error();
+        let mut response =
+            (status, Json(json!({ "error": client_error.as_ref() }))).into_response();
 
         // Insert the Error into the response.
         response.extensions_mut().insert(self);
@@ -69,7 +109,59 @@ impl Error {
             // -- Auth.
             Self::AuthFailNoAuthTokenCookie
             | Self::AuthFailTokenWrongFormat
-            | Self::AuthFailCtxNotInRequestExt => (StatusCode::FORBIDDEN, ClientError::NO_AUTH),
+            | Self::AuthFailCtxNotInRequestExt
+            | Self::AuthFailTokenExpired
+            | Self::AuthFailTokenNotFound
+            | Self::AuthFailInvalidToken => (StatusCode::UNAUTHORIZED, ClientError::NO_AUTH),
+            Self::MissingTokenScope => (StatusCode::FORBIDDEN, ClientError::INSUFFICIENT_SCOPE),
+
+            Self::FileNotFound => (StatusCode::NOT_FOUND, ClientError::FILE_NOT_FOUND),
+            Self::Forbidden => (StatusCode::FORBIDDEN, ClientError::FORBIDDEN),
+            Self::UserNotFound => (StatusCode::NOT_FOUND, ClientError::USER_NOT_FOUND),
+            Self::InvalidRole => (StatusCode::BAD_REQUEST, ClientError::INVALID_PARAMS),
+
+            // -- Personal access tokens.
+            Self::PersonalTokenNotFound => (StatusCode::NOT_FOUND, ClientError::TOKEN_NOT_FOUND),
+            Self::InvalidPersonalToken => (StatusCode::BAD_REQUEST, ClientError::INVALID_PARAMS),
+
+            // -- Submission.
+            Self::UnsupportedLanguage => (StatusCode::BAD_REQUEST, ClientError::INVALID_PARAMS),
+            Self::InputTooLarge => (StatusCode::PAYLOAD_TOO_LARGE, ClientError::INVALID_PARAMS),
+            Self::CompilationFailed => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                ClientError::COMPILATION_FAILED,
+            ),
+
+            // -- Queue.
+            Self::QueueFull => (StatusCode::SERVICE_UNAVAILABLE, ClientError::QUEUE_FULL),
+            Self::JobNotFound => (StatusCode::NOT_FOUND, ClientError::JOB_NOT_FOUND),
+            Self::JobAlreadyFinished => (StatusCode::CONFLICT, ClientError::JOB_ALREADY_FINISHED),
+
+            // -- Challenge.
+            Self::ChallengeNotFound => (StatusCode::NOT_FOUND, ClientError::CHALLENGE_NOT_FOUND),
+            Self::TestCaseNotFound => (StatusCode::NOT_FOUND, ClientError::TEST_CASE_NOT_FOUND),
+            Self::InvalidChallenge => (StatusCode::BAD_REQUEST, ClientError::INVALID_PARAMS),
+            Self::LanguageNotAllowed => {
+                (StatusCode::BAD_REQUEST, ClientError::LANGUAGE_NOT_ALLOWED)
+            }
+
+            // -- Tournament.
+            Self::TournamentNotFound => (StatusCode::NOT_FOUND, ClientError::TOURNAMENT_NOT_FOUND),
+            Self::MatchNotFound => (StatusCode::NOT_FOUND, ClientError::MATCH_NOT_FOUND),
+            Self::TournamentAlreadyStarted => (
+                StatusCode::CONFLICT,
+                ClientError::TOURNAMENT_ALREADY_STARTED,
+            ),
+            Self::NotEnoughEntries => (StatusCode::BAD_REQUEST, ClientError::INVALID_PARAMS),
+
+            // -- Contest.
+            Self::ContestNotFound => (StatusCode::NOT_FOUND, ClientError::CONTEST_NOT_FOUND),
+            Self::ContestNotRunning => (StatusCode::FORBIDDEN, ClientError::CONTEST_NOT_RUNNING),
+            Self::NotRegistered => (StatusCode::FORBIDDEN, ClientError::NOT_REGISTERED),
+            Self::InvalidContest => (StatusCode::BAD_REQUEST, ClientError::INVALID_PARAMS),
+            Self::ContestSubmissionLocked => {
+                (StatusCode::CONFLICT, ClientError::CONTEST_SUBMISSION_LOCKED)
+            }
 
             // -- Model.
             Self::TicketDeleteFailIdNotFound { .. } => {
@@ -129,12 +221,32 @@ where
 #[derive(Debug, strum_macros::AsRefStr)]
 #[allow(non_camel_case_types)]
 #[allow(clippy::module_name_repetitions)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum ClientError {
     LOGIN_FAIL,
     NO_AUTH,
     INVALID_PARAMS,
     SERVICE_ERROR,
     INVALID_FILE,
+    FILE_NOT_FOUND,
+    USER_NOT_FOUND,
+    COMPILATION_FAILED,
+    QUEUE_FULL,
+    JOB_NOT_FOUND,
+    JOB_ALREADY_FINISHED,
+    FORBIDDEN,
+    CHALLENGE_NOT_FOUND,
+    TEST_CASE_NOT_FOUND,
+    LANGUAGE_NOT_ALLOWED,
+    TOURNAMENT_NOT_FOUND,
+    MATCH_NOT_FOUND,
+    TOURNAMENT_ALREADY_STARTED,
+    CONTEST_NOT_FOUND,
+    CONTEST_NOT_RUNNING,
+    NOT_REGISTERED,
+    CONTEST_SUBMISSION_LOCKED,
+    INSUFFICIENT_SCOPE,
+    TOKEN_NOT_FOUND,
 }
 
 // Clienterror implements apperror
@@ -4,25 +4,51 @@ extern crate log;
 use self::error::{Error, Result};
 use tokio::time::Duration;
 
+use crate::api::authentication::{
+    mw_require_auth, mw_require_role, mw_require_scope, mw_require_session,
+};
+use crate::api::challenges::{
+    add_test_case, create_challenge, edit_challenge, get_challenge_details, list_challenges,
+    list_test_cases, remove_challenge, remove_checker, remove_game, remove_test_case, set_checker,
+    set_game, submit_solution,
+};
+use crate::api::contests::{
+    add_contest_challenge, create_contest, edit_contest, get_contest_details,
+    get_contest_scoreboard, list_contests, register_for_contest, remove_contest,
+    remove_contest_challenge, submit_contest_solution,
+};
 use crate::api::create_account::register_account;
-use crate::api::get_files::get_user_files;
+use crate::api::get_files::{get_file_runs, get_student_files, get_user_files};
 use crate::api::get_user_data::get_user_info;
+use crate::api::jobs::{cancel_job, get_job_status, stream_job_events};
+use crate::api::leaderboards::{get_challenge_leaderboard, get_global_leaderboard};
 use crate::api::log_in::login_route;
+use crate::api::log_out::{logout_all_route, logout_route};
+use crate::api::personal_tokens::{
+    create_personal_token, list_personal_tokens, revoke_personal_token, rotate_personal_token_route,
+};
+use crate::api::queue::get_queue;
+use crate::api::roles::{add_student, list_students, remove_student, set_user_role};
 use crate::api::root::{get_server_status, root};
 use crate::api::run_code::{build_and_run, run_hello_world_test};
+use crate::api::tournaments::{
+    begin_tournament, create_tournament, enter_tournament, get_challenge_ratings,
+    get_match_details, get_tournament_details, list_tournaments,
+};
 use crate::api::upload_file::upload;
 
 use axum::extract::{Path, Query};
 use axum::http::{Method, Uri};
 use axum::response::{Html, IntoResponse, Response};
-use axum::routing::{get, get_service, post};
+use axum::routing::{delete, get, get_service, post, put};
 use axum::{middleware, Json, Router};
 
 use ctx::Ctx;
+use database::models::{Role, TokenScope};
 
 use serde_json::{json, Value};
 use std::sync::{Arc, Mutex};
-use tasks::TaskManager;
+use tasks::{start_session_purge, TaskManager, WorkerConfig, SESSION_PURGE_INTERVAL};
 use tokio::net::TcpListener;
 use tower::ServiceBuilder;
 use tower_cookies::CookieManagerLayer;
@@ -43,7 +69,7 @@ mod utils;
 
 #[derive(Clone)]
 pub struct AppState {
-    tm: Arc<Mutex<TaskManager>>,
+    tm: Arc<TaskManager>,
 }
 
 pub fn check_docker_socket() -> bool {
@@ -58,7 +84,13 @@ async fn startup_checks() -> Result<()> {
     warn!("Warning! Running on Windows. Docker will be unavailable!");
 
     #[cfg(unix)]
-    if !check_docker_socket() {
+    if check_docker_socket() {
+        debug!("Removing orphaned sandbox containers");
+        match docker::job::remove_orphaned_containers().await {
+            Ok(removed) => info!("Removed {} orphaned containers", removed),
+            Err(e) => warn!("Failed to remove orphaned containers: {}", e),
+        }
+    } else {
         warn!("Warning! Docker socket does not exist!");
     }
 
@@ -67,6 +99,154 @@ async fn startup_checks() -> Result<()> {
     Ok(())
 }
 
+// Reachable without logging in
+fn public_routes() -> Router<AppState> {
+    Router::new()
+        // `GET /` goes to `root`
+        .route("/", get(root))
+        .route("/register", post(register_account))
+        .route("/login", post(login_route))
+        .route("/info", get(get_server_status))
+}
+
+// Everything else, anonymous requests are rejected before the handler runs
+fn protected_routes() -> Router<AppState> {
+    // Personal access tokens need the read scope here
+    let read_routes = Router::new()
+        .route("/profile", get(get_user_info))
+        .route("/files", get(get_user_files))
+        .route("/files/:file_id/runs", get(get_file_runs))
+        .route("/queue", get(get_queue))
+        .route("/jobs/:job_id", get(get_job_status))
+        .route("/jobs/:job_id/events", get(stream_job_events))
+        .route("/challenges", get(list_challenges))
+        .route("/challenges/:challenge_id", get(get_challenge_details))
+        .route(
+            "/challenges/:challenge_id/leaderboard",
+            get(get_challenge_leaderboard),
+        )
+        .route("/leaderboard", get(get_global_leaderboard))
+        .route(
+            "/challenges/:challenge_id/ratings",
+            get(get_challenge_ratings),
+        )
+        .route("/tournaments", get(list_tournaments))
+        .route("/tournaments/:tournament_id", get(get_tournament_details))
+        .route("/matches/:match_id", get(get_match_details))
+        .route("/contests", get(list_contests))
+        .route("/contests/:contest_id", get(get_contest_details))
+        .route(
+            "/contests/:contest_id/scoreboard",
+            get(get_contest_scoreboard),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            TokenScope::Read,
+            mw_require_scope,
+        ));
+
+    // And the submit scope here
+    let submit_routes = Router::new()
+        .route("/upload", post(upload))
+        .route("/build", post(build_and_run))
+        .route("/jobs/:job_id", delete(cancel_job))
+        .route("/challenges/:challenge_id/submit", post(submit_solution))
+        .route(
+            "/tournaments/:tournament_id/entries",
+            post(enter_tournament),
+        )
+        .route(
+            "/contests/:contest_id/challenges/:challenge_id/submit",
+            post(submit_contest_solution),
+        )
+        .route("/contests/:contest_id/register", post(register_for_contest))
+        .route_layer(middleware::from_fn_with_state(
+            TokenScope::Submit,
+            mw_require_scope,
+        ));
+
+    // The role is checked after the login, methods on the same paths that any
+    // user may use are routed above
+    let admin_routes = Router::new()
+        .route("/challenges", post(create_challenge))
+        .route(
+            "/challenges/:challenge_id",
+            put(edit_challenge).delete(remove_challenge),
+        )
+        .route(
+            "/challenges/:challenge_id/tests",
+            get(list_test_cases).post(add_test_case),
+        )
+        .route(
+            "/challenges/:challenge_id/tests/:test_case_id",
+            delete(remove_test_case),
+        )
+        .route(
+            "/challenges/:challenge_id/checker",
+            put(set_checker).delete(remove_checker),
+        )
+        .route(
+            "/challenges/:challenge_id/game",
+            put(set_game).delete(remove_game),
+        )
+        .route("/tournaments", post(create_tournament))
+        .route("/tournaments/:tournament_id/start", post(begin_tournament))
+        .route("/contests", post(create_contest))
+        .route(
+            "/contests/:contest_id",
+            put(edit_contest).delete(remove_contest),
+        )
+        .route(
+            "/contests/:contest_id/challenges/:challenge_id",
+            put(add_contest_challenge).delete(remove_contest_challenge),
+        )
+        .route("/users/:user_id/role", put(set_user_role))
+        .route(
+            "/teachers/:teacher_id/students/:student_id",
+            put(add_student).delete(remove_student),
+        )
+        .route_layer(middleware::from_fn_with_state(Role::Admin, mw_require_role));
+
+    let teacher_routes = Router::new()
+        .route("/students", get(list_students))
+        .route("/students/:student_id/files", get(get_student_files))
+        .route_layer(middleware::from_fn_with_state(
+            Role::Teacher,
+            mw_require_role,
+        ));
+
+    // Personal access tokens cannot manage the account
+    let session_routes = Router::new()
+        .route("/logout", post(logout_route))
+        .route("/logout/all", post(logout_all_route))
+        .route(
+            "/tokens",
+            get(list_personal_tokens).post(create_personal_token),
+        )
+        .route("/tokens/:token_id", delete(revoke_personal_token))
+        .route(
+            "/tokens/:token_id/rotate",
+            post(rotate_personal_token_route),
+        )
+        .merge(admin_routes)
+        .merge(teacher_routes)
+        .route_layer(middleware::from_fn(mw_require_session));
+
+    Router::new()
+        .merge(read_routes)
+        .merge(submit_routes)
+        .merge(session_routes)
+        .route_layer(middleware::from_fn(mw_require_auth))
+}
+
+fn app(state: AppState) -> Router {
+    Router::new()
+        .merge(public_routes())
+        .merge(protected_routes())
+        .layer(middleware::from_fn(api::authentication::mw_ctx_resolver))
+        .layer(CookieManagerLayer::new())
+        .with_state(state)
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Starts the logger
@@ -75,26 +255,23 @@ async fn main() -> Result<()> {
     // Run checks
     startup_checks().await?;
 
-    let task_manager = Arc::new(Mutex::new(TaskManager { tasks: Vec::new() }));
-    task_manager.lock().unwrap().start_runner();
+    let task_manager = Arc::new(TaskManager::new(WorkerConfig::from_env()));
+    // Containers of interrupted jobs were removed above, so they can start over
+    match task_manager.restore().await {
+        Ok(restored) => info!("Requeued {} unfinished jobs", restored),
+        Err(e) => warn!("Failed to requeue unfinished jobs: {}", e),
+    }
+    task_manager.start_workers();
+    match task_manager.restore_tournaments().await {
+        Ok(restored) => info!("Resumed {} running tournaments", restored),
+        Err(e) => warn!("Failed to resume running tournaments: {}", e),
+    }
+    start_session_purge(SESSION_PURGE_INTERVAL);
     let state = AppState { tm: task_manager };
 
     info!("Starting axum router");
 
-    // build our application with a route
-    let app = Router::new()
-        // `GET /` goes to `root`
-        .route("/", get(root))
-        .route("/upload", post(upload))
-        .route("/register", post(register_account))
-        .route("/login", post(login_route))
-        .route("/profile", get(get_user_info))
-        .route("/files", get(get_user_files))
-        .route("/info", get(get_server_status))
-        .route("/build", post(build_and_run))
-        .layer(middleware::from_fn(api::authentication::mw_ctx_resolver))
-        .layer(CookieManagerLayer::new())
-        .with_state(state);
+    let app = app(state);
 
     // Setup a TcpListener
     let listener = TcpListener::bind("127.0.0.1:3000")
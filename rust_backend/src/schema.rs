@@ -1,9 +1,112 @@
 // @generated automatically by Diesel CLI.
 
 pub mod sql_types {
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "contest_scoring"))]
+    pub struct ContestScoring;
+
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "job_state"))]
+    pub struct JobState;
+
     #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "simulation_result"))]
     pub struct SimulationResult;
+
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "token_scope"))]
+    pub struct TokenScope;
+
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "tournament_format"))]
+    pub struct TournamentFormat;
+
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "tournament_state"))]
+    pub struct TournamentState;
+
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "user_role"))]
+    pub struct UserRole;
+}
+
+diesel::table! {
+    challenge_test_cases (id) {
+        id -> Uuid,
+        challenge_id -> Uuid,
+        #[max_length = 255]
+        name -> Varchar,
+        input -> Text,
+        expected_output -> Text,
+        comparison -> Jsonb,
+        is_sample -> Bool,
+        position -> Int4,
+    }
+}
+
+diesel::table! {
+    challenges (id) {
+        id -> Uuid,
+        #[max_length = 255]
+        name -> Varchar,
+        description -> Text,
+        allowed_languages -> Array<Nullable<Varchar>>,
+        limits -> Nullable<Jsonb>,
+        is_published -> Bool,
+        created_by -> Nullable<Uuid>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        #[max_length = 32]
+        checker_language -> Nullable<Varchar>,
+        checker_source -> Nullable<Text>,
+        game -> Nullable<Jsonb>,
+        scoring -> Nullable<Jsonb>,
+    }
+}
+
+diesel::table! {
+    contest_challenges (contest_id, challenge_id) {
+        contest_id -> Uuid,
+        challenge_id -> Uuid,
+        position -> Int4,
+    }
+}
+
+diesel::table! {
+    contest_participants (contest_id, user_uuid) {
+        contest_id -> Uuid,
+        user_uuid -> Uuid,
+        registered_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    contest_submissions (id) {
+        id -> Uuid,
+        contest_id -> Uuid,
+        challenge_id -> Uuid,
+        user_uuid -> Uuid,
+        file_id -> Uuid,
+        submitted_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::ContestScoring;
+
+    contests (id) {
+        id -> Uuid,
+        #[max_length = 255]
+        name -> Varchar,
+        description -> Text,
+        scoring -> ContestScoring,
+        starts_at -> Timestamp,
+        ends_at -> Timestamp,
+        freeze_minutes -> Int4,
+        created_by -> Nullable<Uuid>,
+        created_at -> Timestamp,
+    }
 }
 
 diesel::table! {
@@ -25,10 +128,81 @@ diesel::table! {
 }
 
 diesel::table! {
-    session_tokens (id) {
+    use diesel::sql_types::*;
+    use super::sql_types::JobState;
+
+    jobs (id) {
+        id -> Uuid,
+        user_uuid -> Uuid,
+        file_id -> Nullable<Uuid>,
+        #[max_length = 32]
+        language -> Varchar,
+        source -> Bytea,
+        state -> JobState,
+        created_at -> Timestamp,
+        started_at -> Nullable<Timestamp>,
+        finished_at -> Nullable<Timestamp>,
+        output -> Nullable<Jsonb>,
+        test_cases -> Nullable<Jsonb>,
+        stdin -> Bytea,
+        challenge_id -> Nullable<Uuid>,
+        limits -> Nullable<Jsonb>,
+        checker -> Nullable<Jsonb>,
+        game -> Nullable<Jsonb>,
+        scoring -> Nullable<Jsonb>,
+        score -> Nullable<Int4>,
+        cpu_time_ms -> Nullable<Int8>,
+        peak_memory_kb -> Nullable<Int8>,
+    }
+}
+
+diesel::table! {
+    matches (id) {
+        id -> Uuid,
+        tournament_id -> Uuid,
+        round -> Int4,
+        player_one -> Uuid,
+        player_two -> Nullable<Uuid>,
+        winner -> Nullable<Uuid>,
+        reason -> Text,
+        transcript -> Jsonb,
+        played_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::TokenScope;
+
+    personal_access_tokens (id) {
         id -> Uuid,
+        user_uuid -> Uuid,
         #[max_length = 255]
-        token -> Varchar,
+        name -> Varchar,
+        #[max_length = 64]
+        token_hash -> Varchar,
+        scopes -> Array<TokenScope>,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
+        last_used_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    ratings (user_uuid, challenge_id) {
+        user_uuid -> Uuid,
+        challenge_id -> Uuid,
+        rating -> Float8,
+        games_played -> Int4,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    session_tokens (id) {
+        id -> Uuid,
+        #[max_length = 64]
+        token_hash -> Varchar,
         user_uuid -> Uuid,
         creation_date -> Timestamp,
         expiration_date -> Timestamp,
@@ -48,10 +222,54 @@ diesel::table! {
         time_taken -> Nullable<Interval>,
         cpu_time -> Nullable<Interval>,
         max_memory_usage -> Nullable<Int4>,
+        score -> Nullable<Int4>,
+        score_breakdown -> Nullable<Jsonb>,
+    }
+}
+
+diesel::table! {
+    teacher_students (teacher_uuid, student_uuid) {
+        teacher_uuid -> Uuid,
+        student_uuid -> Uuid,
+        added_at -> Timestamp,
     }
 }
 
 diesel::table! {
+    tournament_entries (tournament_id, user_uuid) {
+        tournament_id -> Uuid,
+        user_uuid -> Uuid,
+        file_id -> Uuid,
+        points -> Float8,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::TournamentFormat;
+    use super::sql_types::TournamentState;
+
+    tournaments (id) {
+        id -> Uuid,
+        challenge_id -> Uuid,
+        #[max_length = 255]
+        name -> Varchar,
+        format -> TournamentFormat,
+        rounds -> Int4,
+        played_rounds -> Int4,
+        state -> TournamentState,
+        created_by -> Nullable<Uuid>,
+        created_at -> Timestamp,
+        started_at -> Nullable<Timestamp>,
+        finished_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::UserRole;
+
     users (id) {
         id -> Uuid,
         #[max_length = 255]
@@ -61,12 +279,53 @@ diesel::table! {
         created_at -> Nullable<Timestamptz>,
         last_login_at -> Nullable<Timestamptz>,
         login_count -> Nullable<Int4>,
-        is_admin -> Nullable<Bool>,
+        role -> UserRole,
     }
 }
 
+diesel::joinable!(challenge_test_cases -> challenges (challenge_id));
+diesel::joinable!(challenges -> users (created_by));
+diesel::joinable!(contest_challenges -> challenges (challenge_id));
+diesel::joinable!(contest_challenges -> contests (contest_id));
+diesel::joinable!(contest_participants -> contests (contest_id));
+diesel::joinable!(contest_participants -> users (user_uuid));
+diesel::joinable!(contest_submissions -> challenges (challenge_id));
+diesel::joinable!(contest_submissions -> contests (contest_id));
+diesel::joinable!(contest_submissions -> files (file_id));
+diesel::joinable!(contest_submissions -> users (user_uuid));
+diesel::joinable!(contests -> users (created_by));
 diesel::joinable!(files -> users (owner_uuid));
+diesel::joinable!(jobs -> challenges (challenge_id));
+diesel::joinable!(jobs -> files (file_id));
+diesel::joinable!(jobs -> users (user_uuid));
+diesel::joinable!(matches -> tournaments (tournament_id));
+diesel::joinable!(personal_access_tokens -> users (user_uuid));
+diesel::joinable!(ratings -> challenges (challenge_id));
+diesel::joinable!(ratings -> users (user_uuid));
 diesel::joinable!(session_tokens -> users (user_uuid));
 diesel::joinable!(simulations -> files (ran_file_id));
+diesel::joinable!(tournament_entries -> files (file_id));
+diesel::joinable!(tournament_entries -> tournaments (tournament_id));
+diesel::joinable!(tournament_entries -> users (user_uuid));
+diesel::joinable!(tournaments -> challenges (challenge_id));
+diesel::joinable!(tournaments -> users (created_by));
 
-diesel::allow_tables_to_appear_in_same_query!(files, session_tokens, simulations, users,);
+diesel::allow_tables_to_appear_in_same_query!(
+    challenge_test_cases,
+    challenges,
+    contest_challenges,
+    contest_participants,
+    contest_submissions,
+    contests,
+    files,
+    jobs,
+    matches,
+    personal_access_tokens,
+    ratings,
+    session_tokens,
+    simulations,
+    teacher_students,
+    tournament_entries,
+    tournaments,
+    users,
+);
@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+
+use chrono::{Duration, NaiveDateTime};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::database::models::{ContestScoring, ContestSubmission, Simulation, SimulationResult};
+use crate::simulation::history::is_compile_failure;
+
+// Minutes added for every rejected submission before the accepted one
+pub const PENALTY_MINUTES: i64 = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AttemptResult {
+    // Not judged yet, or hidden by the freeze
+    Pending,
+    // Points are between 0 and 100
+    Judged { accepted: bool, points: f64 },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Attempt {
+    pub user_id: Uuid,
+    pub challenge_id: Uuid,
+    pub submitted_at: NaiveDateTime,
+    pub result: AttemptResult,
+}
+
+impl Attempt {
+    // Runs that failed through no fault of the submitter do not count, and
+    // neither do programs that did not compile, as is usual for ICPC
+    pub fn from_submission(
+        submission: &ContestSubmission,
+        simulation: Option<&Simulation>,
+    ) -> Option<Self> {
+        let result = match simulation {
+            None => AttemptResult::Pending,
+            Some(simulation) if is_compile_failure(simulation) => return None,
+            Some(simulation) => {
+                let accepted = match simulation.result? {
+                    SimulationResult::Passed => true,
+                    SimulationResult::Failed => false,
+                    SimulationResult::Error => return None,
+                };
+                let correctness = simulation
+                    .score_breakdown
+                    .as_ref()
+                    .and_then(|breakdown| breakdown["correctness"].as_f64());
+                AttemptResult::Judged {
+                    accepted,
+                    points: correctness.map_or(if accepted { 100.0 } else { 0.0 }, |correctness| {
+                        correctness * 100.0
+                    }),
+                }
+            }
+        };
+
+        Some(Self {
+            user_id: submission.user_uuid,
+            challenge_id: submission.challenge_id,
+            submitted_at: submission.submitted_at,
+            result,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ChallengeResult {
+    pub challenge_id: Uuid,
+    // Judged submissions, for ICPC up to the accepted one
+    pub attempts: u32,
+    // Waiting for a verdict or submitted during the freeze
+    pub pending: u32,
+    pub solved: bool,
+    // Minutes from the start of the contest
+    pub solved_at: Option<i64>,
+    // Best of the attempts
+    pub points: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ScoreboardRow {
+    pub rank: usize,
+    pub user_id: Uuid,
+    pub username: String,
+    pub solved: usize,
+    // Minutes, only counted for ICPC
+    pub penalty: i64,
+    pub points: f64,
+    pub challenges: Vec<ChallengeResult>,
+}
+
+// The scoreboard stops changing here, None if it stays live
+pub fn freeze_time(
+    starts_at: NaiveDateTime,
+    ends_at: NaiveDateTime,
+    freeze_minutes: i32,
+) -> Option<NaiveDateTime> {
+    (freeze_minutes > 0)
+        .then(|| (ends_at - Duration::minutes(i64::from(freeze_minutes))).max(starts_at))
+}
+
+fn compare_rows(
+    scoring: ContestScoring,
+    a: &ScoreboardRow,
+    b: &ScoreboardRow,
+) -> std::cmp::Ordering {
+    match scoring {
+        ContestScoring::Icpc => b.solved.cmp(&a.solved).then(a.penalty.cmp(&b.penalty)),
+        ContestScoring::Ioi => b.points.total_cmp(&a.points),
+    }
+}
+
+// Every participant gets a row, attempts by others or on other challenges are
+// ignored. Attempts made at or after `frozen_at` only show up as pending.
+pub fn scoreboard(
+    scoring: ContestScoring,
+    starts_at: NaiveDateTime,
+    frozen_at: Option<NaiveDateTime>,
+    challenges: &[Uuid],
+    participants: &[(Uuid, String)],
+    attempts: &[Attempt],
+) -> Vec<ScoreboardRow> {
+    let columns: HashMap<Uuid, usize> = challenges
+        .iter()
+        .enumerate()
+        .map(|(i, challenge_id)| (*challenge_id, i))
+        .collect();
+    let mut rows: Vec<ScoreboardRow> = participants
+        .iter()
+        .map(|(user_id, username)| ScoreboardRow {
+            rank: 0,
+            user_id: *user_id,
+            username: username.clone(),
+            solved: 0,
+            penalty: 0,
+            points: 0.0,
+            challenges: challenges
+                .iter()
+                .map(|challenge_id| ChallengeResult {
+                    challenge_id: *challenge_id,
+                    ..ChallengeResult::default()
+                })
+                .collect(),
+        })
+        .collect();
+    let row_of: HashMap<Uuid, usize> = rows
+        .iter()
+        .enumerate()
+        .map(|(i, row)| (row.user_id, i))
+        .collect();
+
+    let mut attempts: Vec<&Attempt> = attempts.iter().collect();
+    attempts.sort_by_key(|attempt| attempt.submitted_at);
+    for attempt in attempts {
+        let (Some(&row), Some(&column)) = (
+            row_of.get(&attempt.user_id),
+            columns.get(&attempt.challenge_id),
+        ) else {
+            continue;
+        };
+        let result = &mut rows[row].challenges[column];
+        // Nothing after the first accepted submission counts for ICPC
+        if scoring == ContestScoring::Icpc && result.solved {
+            continue;
+        }
+
+        let frozen = frozen_at.is_some_and(|frozen_at| attempt.submitted_at >= frozen_at);
+        match attempt.result {
+            AttemptResult::Judged { accepted, points } if !frozen => {
+                result.attempts += 1;
+                result.points = result.points.max(points.clamp(0.0, 100.0));
+                if accepted && !result.solved {
+                    result.solved = true;
+                    result.solved_at = Some((attempt.submitted_at - starts_at).num_minutes());
+                }
+            }
+            _ => result.pending += 1,
+        }
+    }
+
+    for row in &mut rows {
+        row.solved = row.challenges.iter().filter(|result| result.solved).count();
+        row.points = row.challenges.iter().map(|result| result.points).sum();
+        if scoring == ContestScoring::Icpc {
+            row.penalty = row
+                .challenges
+                .iter()
+                .filter_map(|result| {
+                    let solved_at = result.solved_at?;
+                    Some(solved_at + PENALTY_MINUTES * i64::from(result.attempts - 1))
+                })
+                .sum();
+        }
+    }
+
+    rows.sort_by(|a, b| compare_rows(scoring, a, b).then_with(|| a.username.cmp(&b.username)));
+    // Ties share a rank
+    for i in 0..rows.len() {
+        rows[i].rank = if i > 0 && compare_rows(scoring, &rows[i - 1], &rows[i]).is_eq() {
+            rows[i - 1].rank
+        } else {
+            i + 1
+        };
+    }
+
+    rows
+}
@@ -1,7 +0,0 @@
-pub struct Game {
-    id: i32,
-    name: String,
-    description: String,
-    updated_at: String,
-    created_at: String,
-}
@@ -0,0 +1,419 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+
+use crate::api::run_code::{build_file, run_file};
+use crate::database::models::SimulationResult;
+use crate::docker::api::{run_preset_with_files, ContainerOutput, RunOutcome};
+use crate::docker::job::SandboxJob;
+use crate::docker::languages::Language;
+use crate::docker::limits::ResourceLimits;
+use crate::docker::profiles::{
+    CheckerPreset, CHECKER_EXPECTED_FILE, CHECKER_INPUT_FILE, CHECKER_OUTPUT_FILE,
+};
+use crate::utils::temp_file;
+
+// Relative tolerance used when a float comparison does not give one
+const DEFAULT_FLOAT_TOLERANCE: f64 = 1e-6;
+
+// Checker exit codes, any other code means the checker itself failed
+const CHECKER_ACCEPTED: i64 = 0;
+const CHECKER_WRONG_ANSWER: i64 = 1;
+// The first line of stdout is the score between 0 and 1
+const CHECKER_PARTIAL: i64 = 2;
+
+// How the program output is compared to the expected output
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum Comparison {
+    // Byte for byte, except for trailing newlines
+    #[default]
+    Exact,
+    // Compares the whitespace separated tokens
+    IgnoreWhitespace,
+    // Numeric tokens may differ by the tolerance, absolute or relative to the expected value
+    Float {
+        #[serde(default = "default_float_tolerance")]
+        tolerance: f64,
+    },
+    // The expected output is a pattern that must match the whole output
+    Regex,
+    // The same lines in any order
+    UnorderedLines,
+}
+
+const fn default_float_tolerance() -> f64 {
+    DEFAULT_FLOAT_TOLERANCE
+}
+
+impl Comparison {
+    // Returns a description of the first difference if the output is wrong
+    pub fn check(&self, expected: &str, actual: &str) -> Result<(), String> {
+        match self {
+            Self::Exact => compare_lines(
+                &expected.trim_end_matches('\n').lines().collect::<Vec<_>>(),
+                &actual.trim_end_matches('\n').lines().collect::<Vec<_>>(),
+            ),
+            Self::IgnoreWhitespace => compare_tokens(expected, actual, |e, a| e == a),
+            // Identical tokens match even if they are not finite, like `nan` or `inf`
+            Self::Float { tolerance } => compare_tokens(expected, actual, |e, a| {
+                e == a
+                    || match (e.parse::<f64>(), a.parse::<f64>()) {
+                        (Ok(e), Ok(a)) => (e - a).abs() <= tolerance * e.abs().max(1.0),
+                        _ => false,
+                    }
+            }),
+            Self::Regex => {
+                let pattern = Regex::new(&format!("^(?:{})$", expected.trim()))
+                    .map_err(|e| format!("invalid expected pattern: {e}"))?;
+                if pattern.is_match(actual.trim()) {
+                    Ok(())
+                } else {
+                    Err(format!("output does not match `{}`", expected.trim()))
+                }
+            }
+            Self::UnorderedLines => compare_lines(&sorted_lines(expected), &sorted_lines(actual)),
+        }
+    }
+}
+
+fn sorted_lines(text: &str) -> Vec<&str> {
+    let mut lines: Vec<&str> = text
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty())
+        .collect();
+    lines.sort_unstable();
+    lines
+}
+
+fn compare_lines(expected: &[&str], actual: &[&str]) -> Result<(), String> {
+    for (number, (e, a)) in expected.iter().zip(actual).enumerate() {
+        if e != a {
+            return Err(format!(
+                "line {}: expected `{}`, got `{}`",
+                number + 1,
+                e,
+                a
+            ));
+        }
+    }
+    match expected.len().cmp(&actual.len()) {
+        std::cmp::Ordering::Equal => Ok(()),
+        std::cmp::Ordering::Greater => Err(format!(
+            "line {}: expected `{}`, got end of output",
+            actual.len() + 1,
+            expected[actual.len()]
+        )),
+        std::cmp::Ordering::Less => Err(format!(
+            "line {}: expected end of output, got `{}`",
+            expected.len() + 1,
+            actual[expected.len()]
+        )),
+    }
+}
+
+fn compare_tokens(
+    expected: &str,
+    actual: &str,
+    equal: impl Fn(&str, &str) -> bool,
+) -> Result<(), String> {
+    let mut expected = expected.split_whitespace();
+    let mut actual = actual.split_whitespace();
+    let mut number = 1;
+    loop {
+        match (expected.next(), actual.next()) {
+            (None, None) => return Ok(()),
+            (Some(e), Some(a)) if equal(e, a) => {}
+            (Some(e), Some(a)) => {
+                return Err(format!("token {number}: expected `{e}`, got `{a}`"));
+            }
+            (Some(e), None) => {
+                return Err(format!("token {number}: expected `{e}`, got end of output"));
+            }
+            (None, Some(a)) => {
+                return Err(format!("token {number}: expected end of output, got `{a}`"));
+            }
+        }
+        number += 1;
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TestCase {
+    pub name: String,
+    #[serde(default)]
+    pub input: String,
+    pub expected_output: String,
+    #[serde(default)]
+    pub comparison: Comparison,
+    // Hidden cases only report their verdict and resource usage
+    #[serde(default)]
+    pub hidden: bool,
+}
+
+// A program written by the challenge author that judges the output,
+// for challenges where many different outputs are correct
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Checker {
+    pub language: Language,
+    pub source: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Accepted,
+    PartiallyAccepted,
+    WrongAnswer,
+    TimeLimitExceeded,
+    MemoryLimitExceeded,
+    RuntimeError,
+    CompilationError,
+    // The checker crashed or reported nonsense, not the submitter's fault
+    CheckerError,
+}
+
+impl Verdict {
+    pub const fn simulation_result(self) -> SimulationResult {
+        match self {
+            Self::Accepted => SimulationResult::Passed,
+            Self::CheckerError => SimulationResult::Error,
+            _ => SimulationResult::Failed,
+        }
+    }
+}
+
+// How the output of a program that exited normally was judged
+#[derive(Debug, Clone, PartialEq)]
+pub struct Judgement {
+    pub verdict: Verdict,
+    // Between 0 and 1, only partially accepted cases are in between
+    pub score: f64,
+    pub message: Option<String>,
+}
+
+impl Judgement {
+    fn new(verdict: Verdict, message: Option<String>) -> Self {
+        let score = if verdict == Verdict::Accepted {
+            1.0
+        } else {
+            0.0
+        };
+        Self {
+            verdict,
+            score,
+            message,
+        }
+    }
+
+    pub fn compare(case: &TestCase, stdout: &str) -> Self {
+        match case.comparison.check(&case.expected_output, stdout) {
+            Ok(()) => Self::new(Verdict::Accepted, None),
+            Err(diff) => Self::new(Verdict::WrongAnswer, Some(diff)),
+        }
+    }
+
+    // Reads the verdict from the checker's exit code, its output is the message
+    pub fn from_checker(run: &ContainerOutput) -> Self {
+        let stdout = run.output.stdout_lossy();
+        let message = |text: &str| Some(text.trim().to_string()).filter(|m| !m.is_empty());
+
+        if run.outcome != RunOutcome::Exited {
+            return Self::new(
+                Verdict::CheckerError,
+                Some(format!("checker stopped: {:?}", run.outcome)),
+            );
+        }
+
+        match run.exit_code {
+            CHECKER_ACCEPTED => Self::new(Verdict::Accepted, message(&stdout)),
+            CHECKER_WRONG_ANSWER => Self::new(Verdict::WrongAnswer, message(&stdout)),
+            CHECKER_PARTIAL => {
+                let (score, rest) = stdout.split_once('\n').unwrap_or((&stdout, ""));
+                match score.trim().parse::<f64>() {
+                    Ok(score) if (0.0..=1.0).contains(&score) => Self {
+                        verdict: Verdict::PartiallyAccepted,
+                        score,
+                        message: message(rest),
+                    },
+                    _ => Self::new(
+                        Verdict::CheckerError,
+                        Some(format!("invalid partial score `{}`", score.trim())),
+                    ),
+                }
+            }
+            code => Self::new(
+                Verdict::CheckerError,
+                Some(format!(
+                    "checker exited with code {code}: {}",
+                    run.output.stderr_lossy().trim()
+                )),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CaseResult {
+    pub name: String,
+    pub verdict: Verdict,
+    pub score: f64,
+    // The first difference or the checker's message, None for hidden cases
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+    pub exit_code: i64,
+    pub time_ms: u128,
+    // None if the program was killed before its usage could be read
+    pub cpu_time_ms: Option<u128>,
+    pub peak_memory_kb: Option<u64>,
+    // None for hidden cases, the output could give away the expected output
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stdout: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stderr: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GradingReport {
+    // Accepted only if every case is
+    pub verdict: Verdict,
+    pub passed: usize,
+    pub total: usize,
+    // Sum of the case scores, at most total
+    pub score: f64,
+    pub cases: Vec<CaseResult>,
+}
+
+impl GradingReport {
+    pub fn new(cases: Vec<CaseResult>) -> Self {
+        let verdict = cases
+            .iter()
+            .map(|case| case.verdict)
+            .find(|verdict| *verdict != Verdict::Accepted)
+            .unwrap_or(Verdict::Accepted);
+        Self {
+            verdict,
+            passed: cases
+                .iter()
+                .filter(|case| case.verdict == Verdict::Accepted)
+                .count(),
+            total: cases.len(),
+            score: cases.iter().map(|case| case.score).sum(),
+            cases,
+        }
+    }
+}
+
+// Without a judgement the output is compared to the expected output
+pub fn grade_case(
+    case: &TestCase,
+    run: &ContainerOutput,
+    judgement: Option<Judgement>,
+) -> CaseResult {
+    let stdout = run.output.stdout_lossy();
+
+    let judgement = match run.outcome {
+        RunOutcome::TimedOut => Judgement::new(Verdict::TimeLimitExceeded, None),
+        RunOutcome::MemoryLimitExceeded => Judgement::new(Verdict::MemoryLimitExceeded, None),
+        RunOutcome::Exited if run.exit_code != 0 => Judgement::new(Verdict::RuntimeError, None),
+        RunOutcome::Exited => judgement.unwrap_or_else(|| Judgement::compare(case, &stdout)),
+    };
+
+    let visible = !case.hidden;
+    CaseResult {
+        name: case.name.clone(),
+        verdict: judgement.verdict,
+        score: judgement.score,
+        diff: judgement.message.filter(|_| visible),
+        exit_code: run.exit_code,
+        time_ms: run.duration.as_millis(),
+        cpu_time_ms: run.metrics.map(|m| m.cpu_time().as_millis()),
+        peak_memory_kb: run.metrics.map(|m| m.peak_memory_kb()),
+        stdout: visible.then_some(stdout),
+        stderr: visible.then(|| run.output.stderr_lossy()),
+    }
+}
+
+// Compiles the checker, failing if it does not compile
+pub async fn build_checker(checker: &Checker, job: &SandboxJob) -> anyhow::Result<Vec<u8>> {
+    let source = temp_file(checker.source.as_bytes()).await?;
+    let compile = build_file(source, checker.language, job).await?;
+    let Some(mut artifact) = compile.artifact else {
+        anyhow::bail!("checker failed to compile: {}", compile.output);
+    };
+
+    let mut program = Vec::new();
+    artifact.read_to_end(&mut program).await?;
+    Ok(program)
+}
+
+// The checker runs in its own sandbox, apart from the submission
+async fn run_checker(
+    checker: &[u8],
+    language: Language,
+    case: &TestCase,
+    output: &[u8],
+    job: &SandboxJob,
+) -> anyhow::Result<Judgement> {
+    let files: [(&str, &[u8]); 3] = [
+        (CHECKER_INPUT_FILE, case.input.as_bytes()),
+        (CHECKER_EXPECTED_FILE, case.expected_output.as_bytes()),
+        (CHECKER_OUTPUT_FILE, output),
+    ];
+    let program = temp_file(checker).await?;
+    let run = run_preset_with_files(program, &files, CheckerPreset::new(language), &[], job)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to run checker: {}", e))?;
+    Ok(Judgement::from_checker(&run))
+}
+
+// Runs the artifact once per test case, every case gets a fresh container
+pub async fn run_test_cases(
+    mut artifact: File,
+    language: Language,
+    cases: &[TestCase],
+    limits: Option<ResourceLimits>,
+    checker: Option<&Checker>,
+    job: &SandboxJob,
+) -> anyhow::Result<GradingReport> {
+    let mut program = Vec::new();
+    artifact.read_to_end(&mut program).await?;
+
+    // Neither the checker nor the graded runs are streamed, both could
+    // reveal hidden test data
+    let silent = job.silent();
+    let checker = match checker {
+        Some(checker) => Some((build_checker(checker, &silent).await?, checker.language)),
+        None => None,
+    };
+
+    let mut results = Vec::with_capacity(cases.len());
+    for case in cases {
+        let file = temp_file(&program).await?;
+        let run = run_file(file, language, limits, case.input.as_bytes(), &silent).await?;
+
+        // Only output of a program that exited normally is worth checking
+        let judgement = match &checker {
+            Some((checker, checker_language))
+                if run.outcome == RunOutcome::Exited && run.exit_code == 0 =>
+            {
+                Some(
+                    run_checker(
+                        checker,
+                        *checker_language,
+                        case,
+                        &run.output.stdout,
+                        &silent,
+                    )
+                    .await?,
+                )
+            }
+            _ => None,
+        };
+        results.push(grade_case(case, &run, judgement));
+    }
+
+    Ok(GradingReport::new(results))
+}
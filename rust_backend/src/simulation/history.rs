@@ -0,0 +1,227 @@
+use chrono::{Duration as ChronoDuration, NaiveDateTime};
+use diesel::pg::data_types::PgInterval;
+use serde::Serialize;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::api::root::FileResult;
+use crate::database::models::{JobScore, NewSimulation, Simulation, SimulationResult};
+use crate::docker::api::RunOutcome;
+use crate::docker::metrics::Metrics;
+use crate::simulation::grading::GradingReport;
+use crate::simulation::scoring::ScoreBreakdown;
+use crate::simulation::sim::{GameReport, Winner};
+use crate::tasks::JobOutput;
+
+// One recorded run of a file, as returned by the API
+#[derive(Debug, Serialize)]
+pub struct RunInfo {
+    pub simulation_id: i32,
+    pub ran_at: NaiveDateTime,
+    pub result: Option<SimulationResult>,
+    pub logs: Option<String>,
+    pub time_taken_ms: Option<i64>,
+    pub cpu_time_ms: Option<i64>,
+    pub max_memory_usage_kb: Option<i32>,
+    pub score: Option<i32>,
+    pub score_breakdown: Option<serde_json::Value>,
+}
+
+impl From<Simulation> for RunInfo {
+    fn from(simulation: Simulation) -> Self {
+        Self {
+            simulation_id: simulation.simulation_id,
+            ran_at: simulation.ran_at,
+            result: simulation.result,
+            logs: simulation.logs,
+            time_taken_ms: simulation.time_taken.as_ref().map(interval_to_millis),
+            cpu_time_ms: simulation.cpu_time.as_ref().map(interval_to_millis),
+            max_memory_usage_kb: simulation.max_memory_usage,
+            score: simulation.score,
+            score_breakdown: simulation.score_breakdown,
+        }
+    }
+}
+
+impl From<Simulation> for FileResult {
+    fn from(simulation: Simulation) -> Self {
+        let time_taken = simulation.time_taken.as_ref().map_or(0, interval_to_millis);
+        Self {
+            time_started: simulation.ran_at,
+            time_finished: simulation.ran_at + ChronoDuration::milliseconds(time_taken),
+            output: simulation.logs.unwrap_or_default(),
+            success: simulation.result == Some(SimulationResult::Passed),
+        }
+    }
+}
+
+pub fn interval_from_duration(duration: Duration) -> PgInterval {
+    PgInterval::from_microseconds(i64::try_from(duration.as_micros()).unwrap_or(i64::MAX))
+}
+
+// Postgres keeps days and months apart from the microseconds
+pub fn interval_to_millis(interval: &PgInterval) -> i64 {
+    let days = i64::from(interval.months) * 30 + i64::from(interval.days);
+    (days * 86_400_000_000 + interval.microseconds) / 1000
+}
+
+// The cpu_time and max_memory_usage columns
+pub fn metrics_columns(metrics: Option<&Metrics>) -> (Option<PgInterval>, Option<i32>) {
+    let Some(metrics) = metrics else {
+        return (None, None);
+    };
+    (
+        Some(interval_from_duration(metrics.cpu_time())),
+        Some(i32::try_from(metrics.peak_memory_kb()).unwrap_or(i32::MAX)),
+    )
+}
+
+// The row recorded for a job that ran to completion
+pub fn job_simulation(file_id: Uuid, ran_at: NaiveDateTime, output: &JobOutput) -> NewSimulation {
+    if let Some(grading) = &output.grading {
+        return graded_simulation(file_id, ran_at, grading, output.score.as_ref());
+    }
+    if let Some(game) = &output.game {
+        return game_simulation(file_id, ran_at, game);
+    }
+
+    // Only a program that did not compile is recorded without a run time
+    let Some(run) = &output.run else {
+        return NewSimulation {
+            ran_at,
+            ran_file_id: file_id,
+            logs: Some(output.compile.output.clone()),
+            result: Some(SimulationResult::Failed),
+            time_taken: None,
+            cpu_time: None,
+            max_memory_usage: None,
+            score: None,
+            score_breakdown: None,
+        };
+    };
+
+    let passed = run.outcome == RunOutcome::Exited && run.exit_code == 0;
+    let (cpu_time, max_memory_usage) = metrics_columns(run.metrics.as_ref());
+    let mut logs = run.output.stdout_lossy();
+    logs.push_str(&run.output.stderr_lossy());
+
+    NewSimulation {
+        ran_at,
+        ran_file_id: file_id,
+        logs: Some(logs),
+        result: Some(if passed {
+            SimulationResult::Passed
+        } else {
+            SimulationResult::Failed
+        }),
+        time_taken: Some(interval_from_duration(run.duration)),
+        cpu_time,
+        max_memory_usage,
+        score: None,
+        score_breakdown: None,
+    }
+}
+
+pub fn is_compile_failure(simulation: &Simulation) -> bool {
+    simulation.result == Some(SimulationResult::Failed) && simulation.time_taken.is_none()
+}
+
+// The leaderboard columns of a scored job, measured like its run
+pub fn job_score(output: &JobOutput) -> Option<JobScore> {
+    let (score, grading) = (output.score.as_ref()?, output.grading.as_ref()?);
+    let cpu_time: Option<u128> = grading.cases.iter().map(|case| case.cpu_time_ms).sum();
+    Some(JobScore {
+        score: i32::try_from(score.score).unwrap_or(i32::MAX),
+        cpu_time_ms: cpu_time.map(|ms| i64::try_from(ms).unwrap_or(i64::MAX)),
+        peak_memory_kb: grading
+            .cases
+            .iter()
+            .filter_map(|case| case.peak_memory_kb)
+            .max()
+            .map(|kb| i64::try_from(kb).unwrap_or(i64::MAX)),
+    })
+}
+
+// Times are summed over the cases, memory is the highest peak of any case
+fn graded_simulation(
+    file_id: Uuid,
+    ran_at: NaiveDateTime,
+    grading: &GradingReport,
+    score: Option<&ScoreBreakdown>,
+) -> NewSimulation {
+    let logs = grading
+        .cases
+        .iter()
+        .map(|case| {
+            case.diff.as_ref().map_or_else(
+                || format!("{}: {:?}", case.name, case.verdict),
+                |diff| format!("{}: {:?} ({})", case.name, case.verdict, diff),
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let time_taken: u128 = grading.cases.iter().map(|case| case.time_ms).sum();
+    let cpu_time: Option<u128> = grading.cases.iter().map(|case| case.cpu_time_ms).sum();
+    let peak_memory = grading
+        .cases
+        .iter()
+        .filter_map(|case| case.peak_memory_kb)
+        .max();
+
+    let millis = |ms: u128| Duration::from_millis(u64::try_from(ms).unwrap_or(u64::MAX));
+    NewSimulation {
+        ran_at,
+        ran_file_id: file_id,
+        logs: Some(logs),
+        result: Some(grading.verdict.simulation_result()),
+        time_taken: Some(interval_from_duration(millis(time_taken))),
+        cpu_time: cpu_time.map(|ms| interval_from_duration(millis(ms))),
+        max_memory_usage: peak_memory.map(|kb| i32::try_from(kb).unwrap_or(i32::MAX)),
+        score: score.map(|score| i32::try_from(score.score).unwrap_or(i32::MAX)),
+        score_breakdown: score.and_then(|score| serde_json::to_value(score).ok()),
+    }
+}
+
+// Only a win passes, the transcript is kept as the logs
+fn game_simulation(file_id: Uuid, ran_at: NaiveDateTime, game: &GameReport) -> NewSimulation {
+    let millis = |ms: u128| Duration::from_millis(u64::try_from(ms).unwrap_or(u64::MAX));
+    NewSimulation {
+        ran_at,
+        ran_file_id: file_id,
+        logs: Some(game.transcript()),
+        result: Some(if game.winner == Winner::Player {
+            SimulationResult::Passed
+        } else {
+            SimulationResult::Failed
+        }),
+        time_taken: Some(interval_from_duration(millis(game.time_ms))),
+        cpu_time: game
+            .cpu_time_ms
+            .map(|ms| interval_from_duration(millis(ms))),
+        max_memory_usage: game
+            .peak_memory_kb
+            .map(|kb| i32::try_from(kb).unwrap_or(i32::MAX)),
+        score: None,
+        score_breakdown: None,
+    }
+}
+
+// The row recorded for a job the backend failed to run
+pub const fn error_simulation(
+    file_id: Uuid,
+    ran_at: NaiveDateTime,
+    error: String,
+) -> NewSimulation {
+    NewSimulation {
+        ran_at,
+        ran_file_id: file_id,
+        logs: Some(error),
+        result: Some(SimulationResult::Error),
+        time_taken: None,
+        cpu_time: None,
+        max_memory_usage: None,
+        score: None,
+        score_breakdown: None,
+    }
+}
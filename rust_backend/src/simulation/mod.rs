@@ -1,3 +1,6 @@
-pub mod games;
+pub mod contest;
+pub mod grading;
+pub mod history;
 pub mod scoring;
 pub mod sim;
+pub mod tournament;
@@ -1,41 +1,195 @@
-use crate::docker::api::Metrics;
+use serde::{Deserialize, Serialize};
+
+use crate::docker::limits::ResourceLimits;
+use crate::simulation::grading::GradingReport;
 use crate::Error;
-use anyhow;
-use easing;
 
-pub async fn calculate_score() -> Result<u32, anyhow::Error> {
-    let cpu_time: f64 = 0.2;
-    let cpu_time_max: f64 = 1.0;
+// Curve applied to the weighted sum before it is mapped to 1-100
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Easing {
+    Linear,
+    // High scores come easily
+    #[default]
+    QuadOut,
+    // High scores are rare
+    QuadIn,
+    CubicIn,
+}
 
-    let cpu_score = cpu_score(cpu_time, cpu_time_max).expect("Failed");
-    let memory_score: f64 = 0.1;
+impl Easing {
+    pub fn apply(self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => t,
+            Self::QuadOut => (1.0 - t).mul_add(-(1.0 - t), 1.0),
+            Self::QuadIn => t * t,
+            Self::CubicIn => t * t * t,
+        }
+    }
+}
 
-    // Define weights for CPU and memory scores
-    let cpu_weight = 0.7;
-    let memory_weight = 0.3;
+// Set per challenge, missing fields use the defaults
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ScoringConfig {
+    pub correctness_weight: f64,
+    pub cpu_weight: f64,
+    pub memory_weight: f64,
+    pub size_weight: f64,
+    pub easing: Easing,
+    // Sources this long or longer earn no size points
+    pub max_code_bytes: usize,
+}
 
-    // Apply weights to the scores
-    let weighted_cpu = cpu_score * cpu_weight;
-    let weighted_memory = memory_score * memory_weight;
+impl Default for ScoringConfig {
+    fn default() -> Self {
+        Self {
+            correctness_weight: 0.6,
+            cpu_weight: 0.2,
+            memory_weight: 0.1,
+            size_weight: 0.1,
+            easing: Easing::default(),
+            max_code_bytes: 64 * 1024,
+        }
+    }
+}
 
-    // Combine weighted scores
-    let combined_score = weighted_cpu + weighted_memory;
+impl ScoringConfig {
+    // Weights may not be negative and at least one has to count
+    pub fn is_valid(&self) -> bool {
+        let weights = [
+            self.correctness_weight,
+            self.cpu_weight,
+            self.memory_weight,
+            self.size_weight,
+        ];
+        weights
+            .iter()
+            .all(|weight| weight.is_finite() && *weight >= 0.0)
+            && weights.iter().sum::<f64>() > 0.0
+            && self.max_code_bytes > 0
+    }
+}
 
-    // Apply easing function to make higher values rarer
-    let eased_score = ease_out(combined_score);
+// Usage of one test case, None if it could not be measured
+#[derive(Clone, Copy, Debug)]
+pub struct CaseUsage {
+    // 0 to 1, the case's share of the correctness points
+    pub score: f64,
+    pub cpu_time_ms: Option<u128>,
+    pub peak_memory_kb: Option<u64>,
+}
 
-    // Map the eased score to the range [1, 100]
-    let final_score = eased_score.mul_add(99.0, 1.0).round() as u32;
+#[derive(Clone, Debug)]
+pub struct ScoreInput {
+    pub cases: Vec<CaseUsage>,
+    // CPU time the limits allow, not the wall-clock limit
+    pub cpu_limit_ms: u128,
+    pub memory_limit_kb: u64,
+    pub code_bytes: usize,
+}
 
-    Ok(final_score)
+impl ScoreInput {
+    pub fn from_report(report: &GradingReport, limits: &ResourceLimits, code_bytes: usize) -> Self {
+        Self {
+            cases: report
+                .cases
+                .iter()
+                .map(|case| CaseUsage {
+                    score: case.score,
+                    cpu_time_ms: case.cpu_time_ms,
+                    peak_memory_kb: case.peak_memory_kb,
+                })
+                .collect(),
+            cpu_limit_ms: limits.cpu_time().as_millis(),
+            memory_limit_kb: u64::try_from(limits.memory_bytes / 1024).unwrap_or(0),
+            code_bytes,
+        }
+    }
 }
 
-fn cpu_score(time: f64, max: f64) -> Option<f64> {
-    let steps = 100;
-    easing::quad_out(time, max, steps).last()
+// Every component is between 0 and 1, higher is better
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ScoreBreakdown {
+    pub correctness: f64,
+    pub cpu: f64,
+    pub memory: f64,
+    pub size: f64,
+    // Weighted mean of the components, before easing
+    pub combined: f64,
+    pub eased: f64,
+    pub score: u32,
+    pub config: ScoringConfig,
 }
 
-// Easing function (simple quadratic ease out)
-fn ease_out(t: f64) -> f64 {
-    (1.0 - t).mul_add(-(1.0 - t), 1.0)
+// Unused share of the limit, nothing if unmeasured or over it
+fn headroom(used: Option<f64>, limit: f64) -> f64 {
+    match used {
+        Some(used) if limit > 0.0 => (1.0 - used / limit).clamp(0.0, 1.0),
+        _ => 0.0,
+    }
+}
+
+// Mean over the cases, each weighed by the points it earned
+fn earned_mean(cases: &[CaseUsage], component: impl Fn(&CaseUsage) -> f64) -> f64 {
+    cases
+        .iter()
+        .map(|case| case.score.clamp(0.0, 1.0) * component(case))
+        .sum::<f64>()
+        / cases.len() as f64
+}
+
+// Resources only count for the points a case earned,
+// so a fast wrong answer does not score
+pub fn calculate_score(
+    input: &ScoreInput,
+    config: &ScoringConfig,
+) -> Result<ScoreBreakdown, Error> {
+    if input.cases.is_empty() || !config.is_valid() {
+        return Err(Error::FailedToCalculateScore);
+    }
+
+    let correctness = earned_mean(&input.cases, |_| 1.0);
+    let cpu = earned_mean(&input.cases, |case| {
+        headroom(
+            case.cpu_time_ms.map(|ms| ms as f64),
+            input.cpu_limit_ms as f64,
+        )
+    });
+    let memory = earned_mean(&input.cases, |case| {
+        headroom(
+            case.peak_memory_kb.map(|kb| kb as f64),
+            input.memory_limit_kb as f64,
+        )
+    });
+    let size = correctness * headroom(Some(input.code_bytes as f64), config.max_code_bytes as f64);
+
+    let components = [
+        (config.correctness_weight, correctness),
+        (config.cpu_weight, cpu),
+        (config.memory_weight, memory),
+        (config.size_weight, size),
+    ];
+    let weights: f64 = components.iter().map(|(weight, _)| weight).sum();
+    let combined = components
+        .iter()
+        .map(|(weight, component)| weight * component)
+        .sum::<f64>()
+        / weights;
+
+    let eased = config.easing.apply(combined);
+    // Map the eased score to the range [1, 100]
+    let score = eased.mul_add(99.0, 1.0).round() as u32;
+
+    Ok(ScoreBreakdown {
+        correctness,
+        cpu,
+        memory,
+        size,
+        combined,
+        eased,
+        score,
+        config: *config,
+    })
 }
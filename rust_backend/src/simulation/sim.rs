@@ -1,79 +1,751 @@
-use std::{io::Write, path::Path};
+use std::time::{Duration, Instant};
 
-use tempfile::tempfile;
-use tokio::{
-    fs::File,
-    io::{AsyncReadExt, AsyncWriteExt},
-};
+use serde::{Deserialize, Serialize};
+use tokio::{fs::File, io::AsyncReadExt};
 
-use crate::api::run_code::{self, build_file, run_file};
+use crate::api::run_code::build_file;
+use crate::docker::api::{ContainerOutput, InteractiveContainer, ReadLine};
+use crate::docker::job::SandboxJob;
+use crate::docker::languages::Language;
+use crate::docker::limits::ResourceLimits;
+use crate::docker::profiles::CodeRunnerPreset;
+use crate::utils::temp_file;
 
-pub struct PingPong {
-    pub submitted_code: File,
-    pub result: Option<String>,
-    pub correct_answer: String,
+// Time a program gets to exit on its own once the game is over
+const EXIT_GRACE: Duration = Duration::from_secs(1);
+
+// Referee programs are trusted, but must not hang a worker forever
+const REFEREE_TIMEOUT: Duration = Duration::from_secs(10);
+
+const DEFAULT_MOVE_TIMEOUT_MS: u64 = 1000;
+const DEFAULT_MAX_MOVES: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Side {
+    Player,
+    Referee,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Winner {
+    Player,
+    Referee,
+    Draw,
+}
+
+// What the referee does after a move of the player
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Turn {
+    // Sent to the player, who has to answer within the move timeout
+    Reply(String),
+    Over { winner: Winner, reason: String },
 }
 
+// The referee of an interactive game, it sees every move of the player
 pub trait GameLogic {
-    async fn start(self) -> Result<(), anyhow::Error>;
-    async fn setup(&self);
-    async fn verify(&self) -> bool;
-    async fn run(&mut self) -> Result<Option<String>, anyhow::Error>;
+    // The first message to the player, the player opens if None
+    async fn start(&mut self) -> Result<Option<String>, anyhow::Error>;
+    async fn play(&mut self, player_move: &str) -> Result<Turn, anyhow::Error>;
+}
+
+// The reference game: the referee serves `ping` and the player has to
+// return every ball with `pong`
+pub struct PingPong {
+    pub rounds: usize,
+    returned: usize,
 }
 
 impl PingPong {
-    pub async fn new(file: File) -> Self {
+    pub const fn new(rounds: usize) -> Self {
         Self {
-            // Load example.c from disk
-            submitted_code: file,
-            correct_answer: "pong".to_string(),
-            result: None,
+            rounds,
+            returned: 0,
         }
     }
 }
 
 impl GameLogic for PingPong {
-    async fn start(mut self) -> Result<(), anyhow::Error> {
-        info!("Starting game");
-        self.setup().await;
-        self.result = self.run().await?;
-        let won = self.verify().await;
-        info!("Game won: {}", won);
-        Ok(())
-    }
-    async fn setup(&self) {
-        info!("Setting up game")
-    }
-    async fn verify(&self) -> bool {
-        info!("Verifying game");
-        self.result
-            .as_ref()
-            .map_or(false, |result| result == &self.correct_answer)
-    }
-    async fn run(&mut self) -> Result<Option<String>, anyhow::Error> {
-        info!("Running game");
-        let mut code_file: File = File::from_std(tempfile()?);
-        // Write the content of the file to the temporary file
-        let mut content = Vec::new();
-        self.submitted_code.read_to_end(&mut content).await?;
-        code_file.write_all(&content).await?;
-
-        let artifact = build_file(code_file).await?;
-
-        let output = run_file(artifact).await?;
-
-        let logs: Option<String> = match output.logs.last() {
-            Some(ref logs) => Some(logs.to_string()),
-            None => return Ok(None),
+    async fn start(&mut self) -> Result<Option<String>, anyhow::Error> {
+        Ok(Some("ping".to_string()))
+    }
+
+    async fn play(&mut self, player_move: &str) -> Result<Turn, anyhow::Error> {
+        if player_move.trim() != "pong" {
+            return Ok(Turn::Over {
+                winner: Winner::Referee,
+                reason: format!("expected `pong`, got `{}`", player_move.trim()),
+            });
+        }
+
+        self.returned += 1;
+        if self.returned >= self.rounds {
+            return Ok(Turn::Over {
+                winner: Winner::Player,
+                reason: format!("returned all {} balls", self.rounds),
+            });
+        }
+        Ok(Turn::Reply("ping".to_string()))
+    }
+}
+
+// What the referee does after a move in a match between two programs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MatchTurn {
+    // Sent to the player in the seat, who has to answer next
+    Send {
+        seat: usize,
+        data: String,
+    },
+    // No winner is a draw
+    Over {
+        winner: Option<usize>,
+        reason: String,
+    },
+}
+
+// The referee of a match between two programs, seated as 0 and 1.
+// Exactly one player is to move at a time, the referee decides who.
+pub trait MatchLogic {
+    // The first message and the seat it is sent to
+    async fn start_match(&mut self) -> Result<(usize, String), anyhow::Error>;
+    async fn play_match(
+        &mut self,
+        seat: usize,
+        player_move: &str,
+    ) -> Result<MatchTurn, anyhow::Error>;
+}
+
+// The players return the ball to each other, the first to miss loses.
+// The rally is a draw once both players returned `rounds` balls.
+impl MatchLogic for PingPong {
+    async fn start_match(&mut self) -> Result<(usize, String), anyhow::Error> {
+        Ok((0, "ping".to_string()))
+    }
+
+    async fn play_match(
+        &mut self,
+        seat: usize,
+        player_move: &str,
+    ) -> Result<MatchTurn, anyhow::Error> {
+        if player_move.trim() != "pong" {
+            return Ok(MatchTurn::Over {
+                winner: Some(1 - seat),
+                reason: format!(
+                    "player {} missed, expected `pong`, got `{}`",
+                    seat + 1,
+                    player_move.trim()
+                ),
+            });
+        }
+
+        self.returned += 1;
+        if self.returned >= self.rounds * 2 {
+            return Ok(MatchTurn::Over {
+                winner: None,
+                reason: format!("both players returned all {} balls", self.rounds),
+            });
+        }
+        Ok(MatchTurn::Send {
+            seat: 1 - seat,
+            data: "ping".to_string(),
+        })
+    }
+}
+
+// A referee written by the challenge author, running in its own sandbox.
+// Every move of the player is written to its stdin as one line, it answers
+// each with one line on stdout:
+//   `move <text>`                           sends the text to the player
+//   `winner <player|referee|draw> <reason>` ends the game
+// Its first line is either a move or `wait` if the player opens.
+//
+// In a match between two programs it is first sent `players 2`, moves are
+// sent as `<seat> <text>` with seats 1 and 2, and it answers with
+//   `move <seat> <text>`                  sends the text to that player
+//   `winner <seat|draw> <reason>`         ends the match
+pub struct RefereeProgram {
+    container: InteractiveContainer,
+}
+
+impl RefereeProgram {
+    pub async fn start(
+        program: File,
+        language: Language,
+        job: &SandboxJob,
+    ) -> anyhow::Result<Self> {
+        let preset = CodeRunnerPreset::new(language).with_role("referee");
+        let container = InteractiveContainer::start(program, preset, job)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to start referee: {}", e))?;
+        Ok(Self { container })
+    }
+
+    pub async fn stop(self) -> anyhow::Result<ContainerOutput> {
+        self.container
+            .stop(EXIT_GRACE)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to stop referee: {}", e))
+    }
+
+    async fn send(&mut self, line: &str) -> anyhow::Result<()> {
+        self.container
+            .send_line(line)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to write to referee: {}", e))
+    }
+
+    async fn next_command(&mut self) -> anyhow::Result<String> {
+        let line = self
+            .container
+            .read_line(REFEREE_TIMEOUT)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to read from referee: {}", e))?;
+        match line {
+            ReadLine::Line(line) => Ok(line),
+            ReadLine::TimedOut => anyhow::bail!("referee did not answer in time"),
+            ReadLine::Closed => anyhow::bail!("referee exited during the game"),
+        }
+    }
+}
+
+pub fn parse_referee_command(line: &str) -> anyhow::Result<Turn> {
+    let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+    match command {
+        "move" => Ok(Turn::Reply(rest.to_string())),
+        "winner" => {
+            let (winner, reason) = rest.split_once(' ').unwrap_or((rest, ""));
+            let winner = match winner {
+                "player" => Winner::Player,
+                "referee" => Winner::Referee,
+                "draw" => Winner::Draw,
+                _ => anyhow::bail!("referee named an unknown winner `{}`", winner),
+            };
+            Ok(Turn::Over {
+                winner,
+                reason: reason.trim().to_string(),
+            })
+        }
+        _ => anyhow::bail!("referee sent an unknown command `{}`", line),
+    }
+}
+
+pub fn parse_match_command(line: &str) -> anyhow::Result<MatchTurn> {
+    let seat = |seat: &str| match seat {
+        "1" => Ok(0),
+        "2" => Ok(1),
+        _ => Err(anyhow::anyhow!("referee named an unknown seat `{}`", seat)),
+    };
+
+    let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+    let (target, text) = rest.split_once(' ').unwrap_or((rest, ""));
+    match command {
+        "move" => Ok(MatchTurn::Send {
+            seat: seat(target)?,
+            data: text.to_string(),
+        }),
+        "winner" => Ok(MatchTurn::Over {
+            winner: match target {
+                "draw" => None,
+                target => Some(seat(target)?),
+            },
+            reason: text.trim().to_string(),
+        }),
+        _ => anyhow::bail!("referee sent an unknown command `{}`", line),
+    }
+}
+
+impl MatchLogic for RefereeProgram {
+    async fn start_match(&mut self) -> Result<(usize, String), anyhow::Error> {
+        self.send("players 2").await?;
+        let line = self.next_command().await?;
+        match parse_match_command(&line)? {
+            MatchTurn::Send { seat, data } => Ok((seat, data)),
+            MatchTurn::Over { .. } => anyhow::bail!("referee ended the match before it started"),
+        }
+    }
+
+    async fn play_match(
+        &mut self,
+        seat: usize,
+        player_move: &str,
+    ) -> Result<MatchTurn, anyhow::Error> {
+        self.send(&format!("{} {}", seat + 1, player_move)).await?;
+        let line = self.next_command().await?;
+        parse_match_command(&line)
+    }
+}
+
+impl GameLogic for RefereeProgram {
+    async fn start(&mut self) -> Result<Option<String>, anyhow::Error> {
+        let line = self.next_command().await?;
+        if line.trim() == "wait" {
+            return Ok(None);
+        }
+        match parse_referee_command(&line)? {
+            Turn::Reply(opening) => Ok(Some(opening)),
+            Turn::Over { .. } => anyhow::bail!("referee ended the game before it started"),
+        }
+    }
+
+    async fn play(&mut self, player_move: &str) -> Result<Turn, anyhow::Error> {
+        self.send(player_move).await?;
+        let line = self.next_command().await?;
+        parse_referee_command(&line)
+    }
+}
+
+// Referees are either built in or a program uploaded with the challenge
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Referee {
+    PingPong { rounds: usize },
+    Program { language: Language, source: String },
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GameSettings {
+    pub referee: Referee,
+    // Time the player gets for each move
+    #[serde(default = "default_move_timeout_ms")]
+    pub move_timeout_ms: u64,
+    // The game is a draw once the player has made this many moves
+    #[serde(default = "default_max_moves")]
+    pub max_moves: usize,
+}
+
+const fn default_move_timeout_ms() -> u64 {
+    DEFAULT_MOVE_TIMEOUT_MS
+}
+
+const fn default_max_moves() -> usize {
+    DEFAULT_MAX_MOVES
+}
+
+// One line of the game, in the order they were sent
+#[derive(Debug, Clone, Serialize)]
+pub struct Move {
+    pub side: Side,
+    // The player who made or was sent the move, only set in matches
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seat: Option<usize>,
+    pub data: String,
+    // Time the player took to answer, zero for the referee
+    pub elapsed_ms: u128,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GameReport {
+    pub winner: Winner,
+    pub reason: String,
+    // The transcript, enough to replay the game
+    pub moves: Vec<Move>,
+    pub stderr: String,
+    pub time_ms: u128,
+    pub cpu_time_ms: Option<u128>,
+    pub peak_memory_kb: Option<u64>,
+}
+
+impl GameReport {
+    // The transcript as text, one move per line
+    pub fn transcript(&self) -> String {
+        let mut lines: Vec<String> = self
+            .moves
+            .iter()
+            .map(|m| match m.side {
+                Side::Player => format!("> {}", m.data),
+                Side::Referee => format!("< {}", m.data),
+            })
+            .collect();
+        lines.push(format!("winner: {:?} ({})", self.winner, self.reason));
+        lines.join("\n")
+    }
+}
+
+// Relays moves between the referee and the player until the referee ends the game.
+// A player that is too slow, exits or stops reading loses, referee errors fail the game.
+pub async fn play_game(
+    game: &mut impl GameLogic,
+    player: &mut InteractiveContainer,
+    settings: &GameSettings,
+    moves: &mut Vec<Move>,
+) -> anyhow::Result<(Winner, String)> {
+    let move_timeout = Duration::from_millis(settings.move_timeout_ms);
+    let referee_move = |data: String| Move {
+        side: Side::Referee,
+        seat: None,
+        data,
+        elapsed_ms: 0,
+    };
+
+    if let Some(opening) = game.start().await? {
+        let sent = player.send_line(&opening).await;
+        moves.push(referee_move(opening));
+        if sent.is_err() {
+            return Ok((Winner::Referee, "the program stopped reading".to_string()));
+        }
+    }
+
+    let mut player_moves = 0;
+    loop {
+        if player_moves >= settings.max_moves {
+            return Ok((Winner::Draw, format!("{} moves were made", player_moves)));
+        }
+
+        let started_at = Instant::now();
+        let line = player
+            .read_line(move_timeout)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to read from player: {}", e))?;
+        let player_move = match line {
+            ReadLine::Line(line) => line,
+            ReadLine::TimedOut => {
+                return Ok((
+                    Winner::Referee,
+                    format!("no move within {} ms", settings.move_timeout_ms),
+                ));
+            }
+            ReadLine::Closed => {
+                return Ok((Winner::Referee, "the program exited".to_string()));
+            }
+        };
+        moves.push(Move {
+            side: Side::Player,
+            seat: None,
+            data: player_move.clone(),
+            elapsed_ms: started_at.elapsed().as_millis(),
+        });
+        player_moves += 1;
+
+        match game.play(&player_move).await? {
+            Turn::Reply(reply) => {
+                let sent = player.send_line(&reply).await;
+                moves.push(referee_move(reply));
+                if sent.is_err() {
+                    return Ok((Winner::Referee, "the program stopped reading".to_string()));
+                }
+            }
+            Turn::Over { winner, reason } => return Ok((winner, reason)),
+        }
+    }
+}
+
+// The referee of one game
+enum RunningReferee {
+    PingPong(PingPong),
+    Program(RefereeProgram),
+}
+
+impl GameLogic for RunningReferee {
+    async fn start(&mut self) -> Result<Option<String>, anyhow::Error> {
+        match self {
+            Self::PingPong(game) => game.start().await,
+            Self::Program(game) => game.start().await,
+        }
+    }
+
+    async fn play(&mut self, player_move: &str) -> Result<Turn, anyhow::Error> {
+        match self {
+            Self::PingPong(game) => game.play(player_move).await,
+            Self::Program(game) => game.play(player_move).await,
+        }
+    }
+}
+
+impl MatchLogic for RunningReferee {
+    async fn start_match(&mut self) -> Result<(usize, String), anyhow::Error> {
+        match self {
+            Self::PingPong(game) => game.start_match().await,
+            Self::Program(game) => game.start_match().await,
+        }
+    }
+
+    async fn play_match(
+        &mut self,
+        seat: usize,
+        player_move: &str,
+    ) -> Result<MatchTurn, anyhow::Error> {
+        match self {
+            Self::PingPong(game) => game.play_match(seat, player_move).await,
+            Self::Program(game) => game.play_match(seat, player_move).await,
+        }
+    }
+}
+
+// Compiles a referee program, built in referees need nothing
+pub async fn build_referee(referee: &Referee, job: &SandboxJob) -> anyhow::Result<Option<Vec<u8>>> {
+    let Referee::Program { language, source } = referee else {
+        return Ok(None);
+    };
+
+    let compile = build_file(temp_file(source.as_bytes()).await?, *language, job).await?;
+    let Some(mut artifact) = compile.artifact else {
+        anyhow::bail!("referee failed to compile: {}", compile.output);
+    };
+    let mut program = Vec::new();
+    artifact.read_to_end(&mut program).await?;
+    Ok(Some(program))
+}
+
+async fn start_referee(
+    referee: &Referee,
+    program: Option<Vec<u8>>,
+    job: &SandboxJob,
+) -> anyhow::Result<RunningReferee> {
+    match (referee, program) {
+        (Referee::Program { language, .. }, Some(program)) => Ok(RunningReferee::Program(
+            RefereeProgram::start(temp_file(&program).await?, *language, job).await?,
+        )),
+        (Referee::PingPong { rounds }, _) => Ok(RunningReferee::PingPong(PingPong::new(*rounds))),
+        (Referee::Program { .. }, None) => anyhow::bail!("referee program was not built"),
+    }
+}
+
+// Starts the player and the referee, each in its own sandbox, and plays one game.
+// The whole game has to fit in the player's wall time limit.
+pub async fn run_game(
+    artifact: File,
+    language: Language,
+    limits: Option<ResourceLimits>,
+    settings: &GameSettings,
+    job: &SandboxJob,
+) -> anyhow::Result<GameReport> {
+    let mut preset = CodeRunnerPreset::new(language);
+    if let Some(limits) = limits {
+        preset = preset.with_limits(limits);
+    }
+    let wall_time = preset.limits.wall_time();
+
+    // The referee may know hidden data, so only the player is streamed
+    let referee_job = job.silent();
+    let referee_program = build_referee(&settings.referee, &referee_job).await?;
+
+    let mut player = InteractiveContainer::start(artifact, preset, job)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to start player: {}", e))?;
+    let mut referee = match start_referee(&settings.referee, referee_program, &referee_job).await {
+        Ok(referee) => referee,
+        Err(e) => {
+            if let Err(e) = player.stop(EXIT_GRACE).await {
+                warn!("Failed to stop player: {}", e);
+            }
+            return Err(e);
+        }
+    };
+
+    let mut moves = Vec::new();
+    let played = tokio::time::timeout(
+        wall_time,
+        play_game(&mut referee, &mut player, settings, &mut moves),
+    )
+    .await;
+
+    // Both containers are stopped before any error is returned
+    let run = player.stop(EXIT_GRACE).await;
+    if let RunningReferee::Program(program) = referee {
+        if let Err(e) = program.stop().await {
+            warn!("Failed to stop referee: {}", e);
+        }
+    }
+    let run = run.map_err(|e| anyhow::anyhow!("Failed to stop player: {}", e))?;
+
+    let (winner, reason) = match played {
+        Ok(result) => result?,
+        Err(_) => (
+            Winner::Referee,
+            format!("the game took longer than {} ms", wall_time.as_millis()),
+        ),
+    };
+
+    Ok(GameReport {
+        winner,
+        reason,
+        moves,
+        stderr: run.output.stderr_lossy(),
+        time_ms: run.duration.as_millis(),
+        cpu_time_ms: run.metrics.map(|m| m.cpu_time().as_millis()),
+        peak_memory_kb: run.metrics.map(|m| m.peak_memory_kb()),
+    })
+}
+
+// One side of a match, compiled ahead of time
+pub struct MatchPlayer<'a> {
+    pub artifact: &'a [u8],
+    pub language: Language,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MatchReport {
+    // The seat of the winner, None for a draw
+    pub winner: Option<usize>,
+    pub reason: String,
+    pub moves: Vec<Move>,
+}
+
+// Like play_game, but the referee passes moves between two players.
+// A player that is too slow, exits or stops reading loses the match.
+pub async fn play_match(
+    game: &mut impl MatchLogic,
+    players: &mut [InteractiveContainer; 2],
+    settings: &GameSettings,
+    moves: &mut Vec<Move>,
+) -> anyhow::Result<(Option<usize>, String)> {
+    let move_timeout = Duration::from_millis(settings.move_timeout_ms);
+
+    let (seat, opening) = game.start_match().await?;
+    let mut seat = check_seat(seat)?;
+    let mut message = opening;
+    let mut player_moves = 0;
+
+    loop {
+        let sent = players[seat].send_line(&message).await;
+        moves.push(Move {
+            side: Side::Referee,
+            seat: Some(seat),
+            data: message,
+            elapsed_ms: 0,
+        });
+        if sent.is_err() {
+            return Ok((
+                Some(1 - seat),
+                format!("player {} stopped reading", seat + 1),
+            ));
+        }
+
+        if player_moves >= settings.max_moves {
+            return Ok((None, format!("{} moves were made", player_moves)));
+        }
+
+        let started_at = Instant::now();
+        let line = players[seat]
+            .read_line(move_timeout)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to read from player: {}", e))?;
+        let player_move = match line {
+            ReadLine::Line(line) => line,
+            ReadLine::TimedOut => {
+                return Ok((
+                    Some(1 - seat),
+                    format!(
+                        "player {} made no move within {} ms",
+                        seat + 1,
+                        settings.move_timeout_ms
+                    ),
+                ));
+            }
+            ReadLine::Closed => {
+                return Ok((Some(1 - seat), format!("player {} exited", seat + 1)));
+            }
         };
-        self.result = logs.clone();
-        info!("Player output: {:?}", self.result);
+        moves.push(Move {
+            side: Side::Player,
+            seat: Some(seat),
+            data: player_move.clone(),
+            elapsed_ms: started_at.elapsed().as_millis(),
+        });
+        player_moves += 1;
 
-        Ok(logs)
+        match game.play_match(seat, &player_move).await? {
+            MatchTurn::Send { seat: next, data } => {
+                seat = check_seat(next)?;
+                message = data;
+            }
+            MatchTurn::Over { winner, reason } => {
+                if let Some(winner) = winner {
+                    check_seat(winner)?;
+                }
+                return Ok((winner, reason));
+            }
+        }
     }
 }
 
-pub async fn start_game<T: GameLogic + Send>(game: T) {
-    info!("Starting game");
-    T::start(game);
+fn check_seat(seat: usize) -> anyhow::Result<usize> {
+    if seat < 2 {
+        Ok(seat)
+    } else {
+        anyhow::bail!("referee named an unknown seat {}", seat)
+    }
+}
+
+// Plays one match between two compiled programs, each in its own sandbox.
+// The referee program is compiled once by the caller for all matches.
+pub async fn run_match(
+    players: [MatchPlayer<'_>; 2],
+    referee: &Referee,
+    referee_program: Option<&[u8]>,
+    limits: Option<ResourceLimits>,
+    settings: &GameSettings,
+) -> anyhow::Result<MatchReport> {
+    let job = SandboxJob::anonymous();
+    let roles = ["player-one", "player-two"];
+
+    let mut wall_time = Duration::ZERO;
+    let mut started = Vec::with_capacity(players.len());
+    for (player, role) in players.iter().zip(roles) {
+        let mut preset = CodeRunnerPreset::new(player.language).with_role(role);
+        if let Some(limits) = limits {
+            preset = preset.with_limits(limits);
+        }
+        wall_time = preset.limits.wall_time();
+
+        let program = temp_file(player.artifact).await?;
+        match InteractiveContainer::start(program, preset, &job).await {
+            Ok(container) => started.push(container),
+            Err(e) => {
+                stop_all(started).await;
+                return Err(anyhow::anyhow!("Failed to start player: {}", e));
+            }
+        }
+    }
+    let Ok(mut containers) = <[InteractiveContainer; 2]>::try_from(started) else {
+        anyhow::bail!("a match needs two players");
+    };
+
+    let program = referee_program.map(<[u8]>::to_vec);
+    let mut referee = match start_referee(referee, program, &job).await {
+        Ok(referee) => referee,
+        Err(e) => {
+            stop_all(containers.into()).await;
+            return Err(e);
+        }
+    };
+
+    let mut moves = Vec::new();
+    let played = tokio::time::timeout(
+        wall_time,
+        play_match(&mut referee, &mut containers, settings, &mut moves),
+    )
+    .await;
+
+    stop_all(containers.into()).await;
+    if let RunningReferee::Program(program) = referee {
+        if let Err(e) = program.stop().await {
+            warn!("Failed to stop referee: {}", e);
+        }
+    }
+
+    // Both players were too slow to finish in time
+    let (winner, reason) = match played {
+        Ok(result) => result?,
+        Err(_) => (
+            None,
+            format!("the match took longer than {} ms", wall_time.as_millis()),
+        ),
+    };
+
+    Ok(MatchReport {
+        winner,
+        reason,
+        moves,
+    })
+}
+
+async fn stop_all(containers: Vec<InteractiveContainer>) {
+    for container in containers {
+        if let Err(e) = container.stop(EXIT_GRACE).await {
+            warn!("Failed to stop player: {}", e);
+        }
+    }
 }
@@ -0,0 +1,386 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::Utc;
+use serde_json::Value;
+use tokio::io::AsyncReadExt;
+use tokio::sync::Semaphore;
+use uuid::Uuid;
+
+use crate::database::connection::{
+    get_challenge, get_file_from_id, get_matches, get_ratings, get_tournament,
+    get_tournament_entries, record_round,
+};
+use crate::database::models::{NewMatch, Rating, TournamentEntry, TournamentFormat};
+use crate::docker::job::SandboxJob;
+use crate::docker::languages::Language;
+use crate::docker::limits::ResourceLimits;
+use crate::simulation::sim::{build_referee, run_match, GameSettings, MatchPlayer};
+use crate::utils::temp_file;
+use crate::{api::run_code::build_file, Error, Result};
+
+pub const INITIAL_RATING: f64 = 1500.0;
+
+// How far a single match moves a rating
+const ELO_K: f64 = 32.0;
+
+// Two entries by index, a missing opponent is a bye
+pub type Pairing = (usize, Option<usize>);
+
+// Enough rounds for a single entry to win every match
+pub const fn swiss_rounds(entries: usize) -> usize {
+    if entries < 2 {
+        0
+    } else {
+        (usize::BITS - (entries - 1).leading_zeros()) as usize
+    }
+}
+
+pub const fn round_robin_rounds(entries: usize) -> usize {
+    if entries < 2 {
+        0
+    } else {
+        entries + entries % 2 - 1
+    }
+}
+
+// Circle method: the first seat stays put while the others rotate by one
+// each round. With an odd number of entries everyone sits out once.
+pub fn round_robin_pairings(entries: usize, round: usize) -> Vec<Pairing> {
+    let seats = entries + entries % 2;
+    if seats < 2 {
+        return Vec::new();
+    }
+
+    let mut order: Vec<usize> = (0..seats).collect();
+    order[1..].rotate_right(round % (seats - 1));
+
+    (0..seats / 2)
+        .filter_map(|i| {
+            let (a, b) = (order[i], order[seats - 1 - i]);
+            match (a < entries, b < entries) {
+                (true, true) => Some((a, Some(b))),
+                (true, false) => Some((a, None)),
+                (false, true) => Some((b, None)),
+                (false, false) => None,
+            }
+        })
+        .collect()
+}
+
+// Pairs every entry with the closest ranked entry it has not met yet, best
+// first. If the count is odd the lowest ranked entry without a bye sits out.
+pub fn swiss_pairings(
+    points: &[f64],
+    played: &HashSet<(usize, usize)>,
+    byes: &HashSet<usize>,
+) -> Vec<Pairing> {
+    let mut order: Vec<usize> = (0..points.len()).collect();
+    order.sort_by(|a, b| points[*b].total_cmp(&points[*a]).then(a.cmp(b)));
+
+    let mut pairings = Vec::with_capacity(order.len() / 2 + 1);
+    if order.len() % 2 == 1 {
+        let bye = order
+            .iter()
+            .rev()
+            .copied()
+            .find(|entry| !byes.contains(entry))
+            .unwrap_or(order[order.len() - 1]);
+        order.retain(|entry| *entry != bye);
+        pairings.push((bye, None));
+    }
+
+    let met = |a: usize, b: usize| played.contains(&(a.min(b), a.max(b)));
+    while !order.is_empty() {
+        let first = order.remove(0);
+        // A rematch only if everyone left has been played already
+        let index = order
+            .iter()
+            .position(|other| !met(first, *other))
+            .unwrap_or(0);
+        let second = order.remove(index);
+        pairings.push((first, Some(second)));
+    }
+
+    pairings
+}
+
+pub fn expected_score(rating: f64, opponent: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf((opponent - rating) / 400.0))
+}
+
+// Score is 1 if the first player won, 0.5 for a draw and 0 if it lost
+pub fn elo_update(rating: f64, opponent: f64, score: f64) -> (f64, f64) {
+    let change = ELO_K * (score - expected_score(rating, opponent));
+    (rating + change, opponent - change)
+}
+
+// A program that failed to compile forfeits all of its matches
+struct Contestant {
+    user_id: Uuid,
+    program: Option<(Vec<u8>, Language)>,
+}
+
+async fn compile_entry(entry: &TournamentEntry) -> Result<Contestant> {
+    let file = get_file_from_id(entry.file_id).await?;
+    let language = file
+        .file_type
+        .as_deref()
+        .and_then(Language::from_name)
+        .unwrap_or_default();
+
+    let source = temp_file(&file.file_content.unwrap_or_default()).await?;
+    let compile = build_file(
+        source,
+        language,
+        &SandboxJob::new(Some(entry.user_uuid), None),
+    )
+    .await?;
+    let program = match compile.artifact {
+        Some(mut artifact) => {
+            let mut program = Vec::new();
+            artifact.read_to_end(&mut program).await?;
+            Some((program, language))
+        }
+        None => {
+            info!("Entry of user {} did not compile", entry.user_uuid);
+            None
+        }
+    };
+
+    Ok(Contestant {
+        user_id: entry.user_uuid,
+        program,
+    })
+}
+
+// Plays the remaining rounds of a tournament, one match at a time. Every
+// round is stored as a whole, so a restart plays an interrupted round again.
+// Each compile and match takes one of the worker slots while it runs.
+pub async fn run_tournament(tournament_id: Uuid, slots: &Semaphore) -> Result<()> {
+    let tournament = get_tournament(tournament_id)
+        .await?
+        .ok_or(Error::TournamentNotFound)?;
+    let challenge = get_challenge(tournament.challenge_id)
+        .await?
+        .ok_or(Error::ChallengeNotFound)?;
+    let settings: GameSettings = challenge
+        .game
+        .clone()
+        .and_then(|game| serde_json::from_value(game).ok())
+        .ok_or(Error::InvalidChallenge)?;
+    let limits: Option<ResourceLimits> = challenge
+        .limits
+        .clone()
+        .and_then(|limits| serde_json::from_value(limits).ok());
+
+    // Pairings refer to entries by index, so the order must survive a restart
+    let mut entries = get_tournament_entries(tournament_id).await?;
+    entries.sort_by(|a, b| {
+        a.created_at
+            .cmp(&b.created_at)
+            .then(a.user_uuid.cmp(&b.user_uuid))
+    });
+    let index: HashMap<Uuid, usize> = entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| (entry.user_uuid, i))
+        .collect();
+
+    let slot = || async {
+        slots
+            .acquire()
+            .await
+            .map_err(|_| Error::InternalServerError)
+    };
+
+    let referee_program = {
+        let _slot = slot().await?;
+        build_referee(&settings.referee, &SandboxJob::anonymous()).await?
+    };
+    let mut contestants = Vec::with_capacity(entries.len());
+    for entry in &entries {
+        let _slot = slot().await?;
+        contestants.push(compile_entry(entry).await?);
+    }
+
+    let mut points: Vec<f64> = entries.iter().map(|entry| entry.points).collect();
+    let mut played = HashSet::new();
+    let mut byes = HashSet::new();
+    for previous in get_matches(tournament_id).await? {
+        let Some(&a) = index.get(&previous.player_one) else {
+            continue;
+        };
+        match previous.player_two.and_then(|b| index.get(&b)) {
+            Some(&b) => {
+                played.insert((a.min(b), a.max(b)));
+            }
+            None => {
+                byes.insert(a);
+            }
+        }
+    }
+
+    for round in tournament.played_rounds + 1..=tournament.rounds {
+        info!("Tournament {} playing round {}", tournament_id, round);
+        let pairings = match tournament.format {
+            TournamentFormat::RoundRobin => {
+                round_robin_pairings(entries.len(), usize::try_from(round - 1).unwrap_or(0))
+            }
+            TournamentFormat::Swiss => swiss_pairings(&points, &played, &byes),
+        };
+
+        let mut ratings: HashMap<Uuid, Rating> = get_ratings(challenge.id)
+            .await?
+            .into_iter()
+            .map(|rating| (rating.user_uuid, rating))
+            .collect();
+        let mut rated = HashSet::new();
+        let mut new_matches = Vec::with_capacity(pairings.len());
+
+        for (pair, (a, b)) in pairings.into_iter().enumerate() {
+            let Some(b) = b else {
+                let one = &contestants[a];
+                points[a] += 1.0;
+                byes.insert(a);
+                new_matches.push(NewMatch {
+                    tournament_id,
+                    round,
+                    player_one: one.user_id,
+                    player_two: None,
+                    winner: Some(one.user_id),
+                    reason: "bye".to_string(),
+                    transcript: Value::Array(Vec::new()),
+                });
+                continue;
+            };
+            // Seats alternate between rounds and pairs, so the same entry
+            // does not always get to move first
+            let (a, b) = if (usize::try_from(round).unwrap_or(0) + pair) % 2 == 0 {
+                (b, a)
+            } else {
+                (a, b)
+            };
+            let (one, two) = (&contestants[a], &contestants[b]);
+
+            // A match that could not be played, or had no one to play it, is
+            // recorded without a winner, and neither points nor ratings change
+            let mut unplayed = false;
+            let (winner, reason, transcript) = match (&one.program, &two.program) {
+                (Some((first, first_language)), Some((second, second_language))) => {
+                    let players = [
+                        MatchPlayer {
+                            artifact: first,
+                            language: *first_language,
+                        },
+                        MatchPlayer {
+                            artifact: second,
+                            language: *second_language,
+                        },
+                    ];
+                    let _slot = slot().await?;
+                    let played = run_match(
+                        players,
+                        &settings.referee,
+                        referee_program.as_deref(),
+                        limits,
+                        &settings,
+                    )
+                    .await;
+                    match played {
+                        Ok(report) => (
+                            report.winner,
+                            report.reason,
+                            serde_json::to_value(&report.moves)?,
+                        ),
+                        Err(e) => {
+                            warn!("Tournament {} match failed: {}", tournament_id, e);
+                            unplayed = true;
+                            (
+                                None,
+                                "the match could not be played".to_string(),
+                                Value::Array(Vec::new()),
+                            )
+                        }
+                    }
+                }
+                (Some(_), None) => (
+                    Some(0),
+                    "player 2 did not compile".to_string(),
+                    Value::Array(Vec::new()),
+                ),
+                (None, Some(_)) => (
+                    Some(1),
+                    "player 1 did not compile".to_string(),
+                    Value::Array(Vec::new()),
+                ),
+                (None, None) => {
+                    unplayed = true;
+                    (
+                        None,
+                        "neither player compiled".to_string(),
+                        Value::Array(Vec::new()),
+                    )
+                }
+            };
+
+            played.insert((a.min(b), a.max(b)));
+            new_matches.push(NewMatch {
+                tournament_id,
+                round,
+                player_one: one.user_id,
+                player_two: Some(two.user_id),
+                winner: winner.map(|seat| if seat == 0 { one.user_id } else { two.user_id }),
+                reason,
+                transcript,
+            });
+            if unplayed {
+                continue;
+            }
+
+            let score = match winner {
+                Some(0) => 1.0,
+                Some(_) => 0.0,
+                None => 0.5,
+            };
+            points[a] += score;
+            points[b] += 1.0 - score;
+
+            let rating_of = |user_id: Uuid, ratings: &HashMap<Uuid, Rating>| {
+                ratings
+                    .get(&user_id)
+                    .map_or(INITIAL_RATING, |rating| rating.rating)
+            };
+            let (rating_one, rating_two) = elo_update(
+                rating_of(one.user_id, &ratings),
+                rating_of(two.user_id, &ratings),
+                score,
+            );
+            for (user_id, new_rating) in [(one.user_id, rating_one), (two.user_id, rating_two)] {
+                let rating = ratings.entry(user_id).or_insert_with(|| Rating {
+                    user_uuid: user_id,
+                    challenge_id: challenge.id,
+                    rating: INITIAL_RATING,
+                    games_played: 0,
+                    updated_at: Utc::now().naive_utc(),
+                });
+                rating.rating = new_rating;
+                rating.games_played += 1;
+                rating.updated_at = Utc::now().naive_utc();
+                rated.insert(user_id);
+            }
+        }
+
+        let standings: Vec<(Uuid, f64)> = contestants
+            .iter()
+            .zip(&points)
+            .map(|(contestant, points)| (contestant.user_id, *points))
+            .collect();
+        let changed: Vec<Rating> = ratings
+            .into_values()
+            .filter(|rating| rated.contains(&rating.user_uuid))
+            .collect();
+        record_round(tournament_id, round, new_matches, &standings, &changed).await?;
+    }
+
+    Ok(())
+}
@@ -1,2 +1,6 @@
+mod queue;
+mod sessions;
 mod task;
+pub use queue::*;
+pub use sessions::*;
 pub use task::*;
@@ -0,0 +1,109 @@
+use std::collections::{HashMap, VecDeque};
+
+use uuid::Uuid;
+
+// Something that can wait in the queue on behalf of a user
+pub trait QueueItem {
+    fn id(&self) -> Uuid;
+    fn user_id(&self) -> Uuid;
+}
+
+// FIFO queue per user, served round-robin between users so that
+// one user submitting many jobs cannot starve everybody else.
+pub struct JobQueue<T: QueueItem> {
+    // Users with queued jobs, in the order they will be served
+    rotation: VecDeque<Uuid>,
+    jobs: HashMap<Uuid, VecDeque<T>>,
+    capacity: usize,
+}
+
+impl<T: QueueItem> JobQueue<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            rotation: VecDeque::new(),
+            jobs: HashMap::new(),
+            capacity,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.jobs.values().map(VecDeque::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rotation.is_empty()
+    }
+
+    // Gives the item back if the queue is full
+    pub fn push(&mut self, item: T) -> Result<(), T> {
+        if self.len() >= self.capacity {
+            return Err(item);
+        }
+
+        let user_id = item.user_id();
+        let user_jobs = self.jobs.entry(user_id).or_default();
+        if user_jobs.is_empty() {
+            self.rotation.push_back(user_id);
+        }
+        user_jobs.push_back(item);
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let user_id = self.rotation.pop_front()?;
+        let user_jobs = self.jobs.get_mut(&user_id)?;
+        let item = user_jobs.pop_front();
+
+        if user_jobs.is_empty() {
+            self.jobs.remove(&user_id);
+        } else {
+            // The user goes to the back of the line for their next job
+            self.rotation.push_back(user_id);
+        }
+        item
+    }
+
+    pub fn remove(&mut self, job_id: Uuid) -> Option<T> {
+        let (user_id, index) = self.jobs.iter().find_map(|(user_id, jobs)| {
+            jobs.iter()
+                .position(|j| j.id() == job_id)
+                .map(|index| (*user_id, index))
+        })?;
+
+        let user_jobs = self.jobs.get_mut(&user_id)?;
+        let item = user_jobs.remove(index);
+
+        if user_jobs.is_empty() {
+            self.jobs.remove(&user_id);
+            self.rotation.retain(|u| *u != user_id);
+        }
+        item
+    }
+
+    pub fn user_of(&self, job_id: Uuid) -> Option<Uuid> {
+        self.jobs
+            .iter()
+            .find(|(_, jobs)| jobs.iter().any(|j| j.id() == job_id))
+            .map(|(user_id, _)| *user_id)
+    }
+
+    // Number of jobs that will be started before this one, None if not queued
+    pub fn position(&self, job_id: Uuid) -> Option<usize> {
+        self.order().iter().position(|id| *id == job_id)
+    }
+
+    // Job ids in the order they will be popped
+    pub fn order(&self) -> Vec<Uuid> {
+        let mut order = Vec::with_capacity(self.len());
+        let mut round = 0;
+        while order.len() < self.len() {
+            for user_id in &self.rotation {
+                if let Some(job) = self.jobs.get(user_id).and_then(|jobs| jobs.get(round)) {
+                    order.push(job.id());
+                }
+            }
+            round += 1;
+        }
+        order
+    }
+}
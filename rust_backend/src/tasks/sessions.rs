@@ -0,0 +1,21 @@
+use tokio::time::{interval, Duration, MissedTickBehavior};
+
+use crate::database::connection::delete_expired_sessions;
+
+// Expired sessions are rejected anyway, purging only keeps the table small
+pub const SESSION_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+pub fn start_session_purge(period: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = interval(period);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            match delete_expired_sessions().await {
+                Ok(0) => {}
+                Ok(purged) => info!("Purged {} expired sessions", purged),
+                Err(e) => warn!("Failed to purge expired sessions: {}", e),
+            }
+        }
+    });
+}
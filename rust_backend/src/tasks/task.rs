@@ -1,7 +1,34 @@
 use async_trait::async_trait;
+use chrono::Utc;
+use futures::future::{AbortHandle, AbortRegistration, Abortable, Aborted};
+use std::collections::{HashMap, HashSet};
+use std::env;
 use std::error::Error;
-use tokio::time::sleep;
-use tokio::time::Duration;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{broadcast, Notify, Semaphore, SemaphorePermit};
+use tokio::time::{Duration, Instant};
+use uuid::Uuid;
+
+use serde::Serialize;
+
+use super::queue::{JobQueue, QueueItem};
+use crate::api::run_code::{build_file, job_output_json, run_file};
+use crate::database::connection::{
+    finish_job, finish_tournament, get_running_tournaments, get_unfinished_jobs, insert_simulation,
+    set_job_state, start_job,
+};
+use crate::database::models::{Job, JobState, TournamentState};
+use crate::docker::api::{CompileResult, ContainerOutput};
+use crate::docker::job::{remove_job_containers, JobEvent, SandboxJob};
+use crate::docker::languages::Language;
+use crate::docker::limits::ResourceLimits;
+use crate::docker::profiles::CodeRunnerPreset;
+use crate::simulation::grading::{run_test_cases, Checker, GradingReport, TestCase};
+use crate::simulation::history::{error_simulation, job_score, job_simulation};
+use crate::simulation::scoring::{calculate_score, ScoreBreakdown, ScoreInput, ScoringConfig};
+use crate::simulation::sim::{run_game, GameReport, GameSettings};
+use crate::simulation::tournament::run_tournament;
+use crate::utils::temp_file;
 
 #[derive(Debug)]
 pub enum TaskType {
@@ -22,59 +49,581 @@ pub trait Task {
     fn run(&self) -> Result<TaskStatus, Box<dyn Error>>;
 }
 
+const DEFAULT_WORKERS: usize = 2;
+const DEFAULT_MAX_QUEUED: usize = 100;
+
+// Assumed job duration until the first job has finished
+const INITIAL_JOB_DURATION: Duration = Duration::from_secs(5);
+
+// Events a subscriber can fall behind by before it starts missing output
+const EVENT_BUFFER: usize = 256;
+
+#[derive(Clone, Copy, Debug)]
+pub struct WorkerConfig {
+    pub workers: usize,
+    pub max_queued: usize,
+}
+
+impl WorkerConfig {
+    // Reads WORKER_COUNT and MAX_QUEUED_JOBS, falling back to the defaults
+    pub fn from_env() -> Self {
+        let read = |key: &str, default: usize| {
+            env::var(key)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|v| *v > 0)
+                .unwrap_or(default)
+        };
+        Self {
+            workers: read("WORKER_COUNT", DEFAULT_WORKERS),
+            max_queued: read("MAX_QUEUED_JOBS", DEFAULT_MAX_QUEUED),
+        }
+    }
+}
+
+// A submission waiting to be compiled and run
+pub struct CodeJob {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub file_id: Option<Uuid>,
+    pub language: Language,
+    pub source: Vec<u8>,
+    pub limits: Option<ResourceLimits>,
+    pub challenge_id: Option<Uuid>,
+    // Graded against these instead of a single run if not empty
+    pub test_cases: Vec<TestCase>,
+    // Judges the output of the test cases instead of comparing it
+    pub checker: Option<Checker>,
+    // Played against a referee instead of graded if set
+    pub game: Option<GameSettings>,
+    // Graded jobs are scored from 1 to 100 if set
+    pub scoring: Option<ScoringConfig>,
+    // Input of the single run, test cases bring their own
+    pub stdin: Vec<u8>,
+}
+
+impl CodeJob {
+    pub fn new(user_id: Uuid, language: Language, source: Vec<u8>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            user_id,
+            file_id: None,
+            language,
+            source,
+            limits: None,
+            challenge_id: None,
+            test_cases: Vec::new(),
+            checker: None,
+            game: None,
+            scoring: None,
+            stdin: Vec::new(),
+        }
+    }
+
+    pub fn with_stdin(mut self, stdin: Vec<u8>) -> Self {
+        self.stdin = stdin;
+        self
+    }
+
+    // Graded against the challenge's test cases within its limits
+    pub fn with_challenge(
+        mut self,
+        challenge_id: Uuid,
+        test_cases: Vec<TestCase>,
+        limits: Option<ResourceLimits>,
+    ) -> Self {
+        self.challenge_id = Some(challenge_id);
+        self.test_cases = test_cases;
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_checker(mut self, checker: Option<Checker>) -> Self {
+        self.checker = checker;
+        self
+    }
+
+    pub fn with_game(mut self, game: Option<GameSettings>) -> Self {
+        self.game = game;
+        self
+    }
+
+    pub const fn with_scoring(mut self, scoring: Option<ScoringConfig>) -> Self {
+        self.scoring = scoring;
+        self
+    }
+
+    // Runs are recorded against the file
+    pub const fn with_file(mut self, file_id: Uuid) -> Self {
+        self.file_id = Some(file_id);
+        self
+    }
+}
+
+// Jobs interrupted by a restart are started over, the source is stored with the job
+impl From<Job> for CodeJob {
+    fn from(job: Job) -> Self {
+        Self {
+            id: job.id,
+            user_id: job.user_uuid,
+            file_id: job.file_id,
+            language: Language::from_name(&job.language).unwrap_or_default(),
+            source: job.source,
+            limits: job
+                .limits
+                .and_then(|limits| serde_json::from_value(limits).ok()),
+            challenge_id: job.challenge_id,
+            test_cases: job
+                .test_cases
+                .and_then(|cases| serde_json::from_value(cases).ok())
+                .unwrap_or_default(),
+            checker: job
+                .checker
+                .and_then(|checker| serde_json::from_value(checker).ok()),
+            game: job.game.and_then(|game| serde_json::from_value(game).ok()),
+            scoring: job
+                .scoring
+                .and_then(|scoring| serde_json::from_value(scoring).ok()),
+            stdin: job.stdin,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct JobOutput {
+    pub compile: CompileResult,
+    // None if compilation failed, the job was graded or played
+    pub run: Option<ContainerOutput>,
+    pub grading: Option<GradingReport>,
+    pub game: Option<GameReport>,
+    pub score: Option<ScoreBreakdown>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct QueuePosition {
+    pub job_id: Uuid,
+    // Jobs that will start before this one
+    pub position: usize,
+    pub eta_seconds: u64,
+}
+
+impl QueueItem for CodeJob {
+    fn id(&self) -> Uuid {
+        self.id
+    }
+    fn user_id(&self) -> Uuid {
+        self.user_id
+    }
+}
+
+struct RunningJob {
+    user_id: Uuid,
+    abort: AbortHandle,
+}
+
+struct ManagerState {
+    queue: JobQueue<CodeJob>,
+    running: HashMap<Uuid, RunningJob>,
+    // Live events of queued and running jobs
+    events: HashMap<Uuid, broadcast::Sender<JobEvent>>,
+    average_duration: Duration,
+    tournaments: HashSet<Uuid>,
+}
+
+// Runs submitted code on a bounded pool of workers
 pub struct TaskManager {
-    tasks: Vec<Box<dyn Task + Send>>,
+    config: WorkerConfig,
+    state: Mutex<ManagerState>,
+    notify: Notify,
+    // One per worker, shared with tournaments so together they never run
+    // more sandboxes at once than there are workers
+    slots: Semaphore,
 }
 
 impl TaskManager {
-    pub fn new() -> Self {
-        TaskManager { tasks: Vec::new() }
+    pub fn new(config: WorkerConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(ManagerState {
+                queue: JobQueue::new(config.max_queued),
+                running: HashMap::new(),
+                events: HashMap::new(),
+                average_duration: INITIAL_JOB_DURATION,
+                tournaments: HashSet::new(),
+            }),
+            notify: Notify::new(),
+            slots: Semaphore::new(config.workers),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, ManagerState> {
+        // A panicking worker must not take the whole queue down with it
+        self.state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    pub fn start_workers(self: &Arc<Self>) {
+        info!("Starting {} workers", self.config.workers);
+        for worker in 0..self.config.workers {
+            let tm = Arc::clone(self);
+            tokio::spawn(async move { tm.worker(worker).await });
+        }
+    }
+
+    // Gives the job back if the queue is full.
+    // The job must already be stored, workers only update its state.
+    #[allow(clippy::result_large_err)]
+    pub fn enqueue(&self, job: CodeJob) -> Result<(), CodeJob> {
+        let job_id = job.id;
+        {
+            let mut state = self.lock();
+            state.queue.push(job)?;
+            state
+                .events
+                .insert(job_id, broadcast::channel(EVENT_BUFFER).0);
+        }
+        self.notify.notify_one();
+        Ok(())
+    }
+
+    // None once the job has finished, its result is stored in the database by then
+    pub fn subscribe(&self, job_id: Uuid) -> Option<broadcast::Receiver<JobEvent>> {
+        self.lock()
+            .events
+            .get(&job_id)
+            .map(broadcast::Sender::subscribe)
+    }
+
+    fn emit(&self, job_id: Uuid, event: JobEvent) {
+        if let Some(events) = self.lock().events.get(&job_id) {
+            let _ = events.send(event);
+        }
+    }
+
+    // Sends the final event and closes the job's event stream
+    fn close_events(&self, job_id: Uuid, state: JobState) {
+        let events = self.lock().events.remove(&job_id);
+        if let Some(events) = events {
+            let _ = events.send(JobEvent::Finished { state });
+        }
+    }
+
+    // Requeues the jobs that were queued or running when the backend stopped
+    pub async fn restore(&self) -> crate::Result<usize> {
+        let jobs = get_unfinished_jobs().await?;
+        let mut restored = 0;
+        for job in jobs {
+            let job_id = job.id;
+            if self.enqueue(job.into()).is_err() {
+                warn!("Queue is full, cancelling job {}", job_id);
+                finish_job(job_id, JobState::Cancelled, None, None).await?;
+                continue;
+            }
+            set_job_state(job_id, JobState::Queued).await?;
+            restored += 1;
+        }
+        Ok(restored)
+    }
+
+    // Tournaments run one match at a time, each match takes a worker slot.
+    // Returns false if the tournament is already running.
+    pub fn spawn_tournament(self: &Arc<Self>, tournament_id: Uuid) -> bool {
+        if !self.lock().tournaments.insert(tournament_id) {
+            return false;
+        }
+
+        let tm = Arc::clone(self);
+        tokio::spawn(async move {
+            info!("Starting tournament {}", tournament_id);
+            let state = match run_tournament(tournament_id, &tm.slots).await {
+                Ok(()) => TournamentState::Finished,
+                Err(e) => {
+                    error!("Tournament {} failed: {}", tournament_id, e);
+                    TournamentState::Failed
+                }
+            };
+            if let Err(e) = finish_tournament(tournament_id, state).await {
+                error!("Failed to finish tournament {}: {}", tournament_id, e);
+            }
+            tm.lock().tournaments.remove(&tournament_id);
+            info!("Tournament {} is over", tournament_id);
+        });
+        true
+    }
+
+    // Resumes the tournaments that were running when the backend stopped
+    pub async fn restore_tournaments(self: &Arc<Self>) -> crate::Result<usize> {
+        let tournaments = get_running_tournaments().await?;
+        for tournament in &tournaments {
+            self.spawn_tournament(tournament.id);
+        }
+        Ok(tournaments.len())
+    }
+
+    pub fn position(&self, job_id: Uuid) -> Option<QueuePosition> {
+        let state = self.lock();
+        let position = state.queue.position(job_id)?;
+        let eta = self.eta(&state, position);
+        drop(state);
+        Some(QueuePosition {
+            job_id,
+            position,
+            eta_seconds: eta.as_secs(),
+        })
     }
 
-    pub fn add_task(&mut self, task: Box<dyn Task + Send>) {
-        self.tasks.push(task);
+    // Queue positions of all jobs queued by the user
+    pub fn user_positions(&self, user_id: Uuid) -> Vec<QueuePosition> {
+        let state = self.lock();
+        state
+            .queue
+            .order()
+            .into_iter()
+            .enumerate()
+            .filter(|(_, job_id)| state.queue.user_of(*job_id) == Some(user_id))
+            .map(|(position, job_id)| QueuePosition {
+                job_id,
+                position,
+                eta_seconds: self.eta(&state, position).as_secs(),
+            })
+            .collect()
     }
 
-    pub async fn start_runner(self) {
-        tokio::spawn(async move { runner(self).await });
+    pub fn is_running(&self, job_id: Uuid) -> bool {
+        self.lock().running.contains_key(&job_id)
     }
 
-    fn run_next_task(&self) {
-        if let Some(task) = self.tasks.first() {
-            match task.run() {
-                Ok(TaskStatus::Completed) => {
-                    println!("Task completed");
+    // Removes a queued job or aborts a running one.
+    // Returns false if the job is unknown or not owned by the user.
+    pub async fn cancel(&self, job_id: Uuid, user_id: Uuid) -> bool {
+        enum Cancelled {
+            Queued,
+            Running,
+            No,
+        }
+
+        let cancelled = {
+            let mut state = self.lock();
+            if state.queue.user_of(job_id) == Some(user_id) && state.queue.remove(job_id).is_some()
+            {
+                Cancelled::Queued
+            } else {
+                match state.running.get(&job_id) {
+                    Some(running) if running.user_id == user_id => {
+                        running.abort.abort();
+                        Cancelled::Running
+                    }
+                    _ => Cancelled::No,
                 }
-                Ok(TaskStatus::Failed) => {
-                    println!("Task failed");
+            }
+        };
+
+        match cancelled {
+            Cancelled::Queued => {
+                if let Err(e) = finish_job(job_id, JobState::Cancelled, None, None).await {
+                    error!("Failed to mark job {} as cancelled: {}", job_id, e);
                 }
-                _ => {
-                    println!("Task is still running");
+                self.close_events(job_id, JobState::Cancelled);
+                true
+            }
+            // The worker marks aborted jobs as cancelled,
+            // but aborting the future leaves the container behind
+            Cancelled::Running => {
+                if let Err(e) = remove_job_containers(job_id).await {
+                    warn!("Failed to remove containers of job {}: {}", job_id, e);
                 }
+                true
             }
+            Cancelled::No => false,
         }
     }
-}
 
-struct ExampleTask;
+    fn eta(&self, state: &ManagerState, position: usize) -> Duration {
+        // Every worker takes one job per round
+        let rounds = position / self.config.workers + 1;
+        state.average_duration * rounds as u32
+    }
 
-#[async_trait]
-impl Task for ExampleTask {
-    fn task_type(&self) -> TaskType {
-        TaskType::Maintenance
+    // The job counts as running as soon as it leaves the queue,
+    // so it can be cancelled before its task is spawned
+    fn pop_job(&self) -> Option<(CodeJob, AbortRegistration)> {
+        let mut state = self.lock();
+        let job = state.queue.pop()?;
+        let (abort, registration) = AbortHandle::new_pair();
+        let user_id = job.user_id;
+        state.running.insert(job.id, RunningJob { user_id, abort });
+        drop(state);
+        Some((job, registration))
     }
 
-    fn run(&self) -> Result<TaskStatus, Box<dyn Error>> {
-        println!("Running example task");
-        Ok(TaskStatus::Completed)
+    // Waits for a free slot and a job. The slot is given back while the
+    // queue is empty, so idle workers do not hold up tournaments.
+    async fn next_job(&self) -> (CodeJob, AbortRegistration, SemaphorePermit<'_>) {
+        loop {
+            // Register interest before checking so a push in between is not missed
+            let notified = self.notify.notified();
+            let slot = self
+                .slots
+                .acquire()
+                .await
+                .expect("worker slots are never closed");
+            if let Some((job, registration)) = self.pop_job() {
+                return (job, registration, slot);
+            }
+            drop(slot);
+            notified.await;
+        }
+    }
+
+    async fn worker(&self, worker: usize) {
+        loop {
+            let (job, registration, slot) = self.next_job().await;
+            let job_id = job.id;
+            let language = job.language;
+            let file_id = job.file_id;
+            info!("Worker {} starting job {}", worker, job_id);
+
+            if let Err(e) = start_job(job_id).await {
+                error!("Failed to mark job {} as started: {}", job_id, e);
+            }
+            self.emit(
+                job_id,
+                JobEvent::State {
+                    state: JobState::Compiling,
+                },
+            );
+
+            let events = self.lock().events.get(&job_id).cloned();
+            let ran_at = Utc::now().naive_utc();
+            let started_at = Instant::now();
+            let handle = tokio::spawn(Abortable::new(execute(job, events), registration));
+
+            // Cancelled jobs are not recorded as runs
+            let (state, output, score, simulation) = match handle.await {
+                Ok(Ok(Ok(output))) => {
+                    let simulation =
+                        file_id.map(|file_id| job_simulation(file_id, ran_at, &output));
+                    let score = job_score(&output);
+                    let (state, output) = job_output_json(language, output);
+                    (state, Some(output), score, simulation)
+                }
+                Ok(Ok(Err(e))) => {
+                    error!("Job {} failed: {}", job_id, e);
+                    let simulation =
+                        file_id.map(|file_id| error_simulation(file_id, ran_at, e.to_string()));
+                    (JobState::Failed, None, None, simulation)
+                }
+                Ok(Err(Aborted)) => (JobState::Cancelled, None, None, None),
+                Err(e) => {
+                    error!("Job {} panicked: {}", job_id, e);
+                    let simulation =
+                        file_id.map(|file_id| error_simulation(file_id, ran_at, e.to_string()));
+                    (JobState::Failed, None, None, simulation)
+                }
+            };
+
+            drop(slot);
+            {
+                let mut state = self.lock();
+                state.running.remove(&job_id);
+                // Moving average, recent jobs weigh more
+                state.average_duration = (state.average_duration * 3 + started_at.elapsed()) / 4;
+            }
+
+            if let Err(e) = finish_job(job_id, state, output, score).await {
+                error!("Failed to store the result of job {}: {}", job_id, e);
+            }
+            if let Some(simulation) = simulation {
+                if let Err(e) = insert_simulation(simulation).await {
+                    error!("Failed to record the run of job {}: {}", job_id, e);
+                }
+            }
+            self.close_events(job_id, state);
+            info!("Worker {} finished job {}", worker, job_id);
+        }
     }
 }
 
-async fn runner(tm: TaskManager) {
-    let mut example_task = ExampleTask;
+async fn execute(
+    job: CodeJob,
+    events: Option<broadcast::Sender<JobEvent>>,
+) -> anyhow::Result<JobOutput> {
+    let sandbox = SandboxJob {
+        id: job.id,
+        user_id: Some(job.user_id),
+        file_id: job.file_id,
+        events,
+    };
+
+    let file = temp_file(&job.source).await?;
 
-    loop {
-        tm.run_next_task();
-        sleep(Duration::from_secs(1)).await;
+    let mut compile = build_file(file, job.language, &sandbox).await?;
+    let Some(artifact) = compile.artifact.take() else {
+        return Ok(JobOutput {
+            compile,
+            run: None,
+            grading: None,
+            game: None,
+            score: None,
+        });
+    };
+
+    set_job_state(job.id, JobState::Running)
+        .await
+        .map_err(|_| crate::Error::DatabaseQueryFail)?;
+    sandbox.emit(JobEvent::State {
+        state: JobState::Running,
+    });
+
+    if let Some(settings) = &job.game {
+        let game = run_game(artifact, job.language, job.limits, settings, &sandbox).await?;
+        return Ok(JobOutput {
+            compile,
+            run: None,
+            grading: None,
+            game: Some(game),
+            score: None,
+        });
     }
+
+    if !job.test_cases.is_empty() {
+        let grading = run_test_cases(
+            artifact,
+            job.language,
+            &job.test_cases,
+            job.limits,
+            job.checker.as_ref(),
+            &sandbox,
+        )
+        .await?;
+
+        // Measured against the limits the cases ran with
+        let limits = job
+            .limits
+            .unwrap_or_else(|| CodeRunnerPreset::new(job.language).limits);
+        let score = job.scoring.as_ref().and_then(|config| {
+            let input = ScoreInput::from_report(&grading, &limits, job.source.len());
+            calculate_score(&input, config)
+                .map_err(|e| warn!("Failed to score job {}: {}", job.id, e))
+                .ok()
+        });
+
+        return Ok(JobOutput {
+            compile,
+            run: None,
+            grading: Some(grading),
+            game: None,
+            score,
+        });
+    }
+
+    let run = run_file(artifact, job.language, job.limits, &job.stdin, &sandbox).await?;
+    Ok(JobOutput {
+        compile,
+        run: Some(run),
+        grading: None,
+        game: None,
+        score: None,
+    })
 }
@@ -16,7 +16,31 @@ mod api_tests {
     use tempfile::{tempfile, NamedTempFile};
     use tokio::{fs::File, io::AsyncWriteExt};
 
+    use crate::api::leaderboards::{LeaderboardWindow, Page};
+    use crate::api::run_code::job_output_json;
+    use crate::database::models::{
+        ChallengeTestCase, ContestScoring, ContestSubmission, Simulation, SimulationResult,
+    };
+    use crate::docker::api::{CapturedOutput, CompileResult, ContainerOutput, RunOutcome};
     use crate::docker::common::create_targz_archive;
+    use crate::docker::diagnostics::{parse_diagnostics, Severity};
+    use crate::docker::languages::Language;
+    use crate::docker::limits::{ResourceLimits, COMPILER_LIMITS, RUNNER_LIMITS};
+    use crate::docker::metrics::{parse_metrics, with_metrics};
+    use crate::simulation::contest::{freeze_time, scoreboard, Attempt, AttemptResult};
+    use crate::simulation::grading::{
+        grade_case, Comparison, GradingReport, Judgement, TestCase, Verdict,
+    };
+    use crate::simulation::history::{interval_from_duration, interval_to_millis};
+    use crate::simulation::scoring::{
+        calculate_score, CaseUsage, Easing, ScoreInput, ScoringConfig,
+    };
+    use crate::simulation::sim::{parse_referee_command, GameLogic, PingPong, Turn, Winner};
+    use crate::simulation::tournament::{
+        elo_update, round_robin_pairings, round_robin_rounds, swiss_pairings,
+    };
+    use crate::tasks::{JobOutput, JobQueue, QueueItem};
+    use uuid::Uuid;
 
     use super::*;
 
@@ -38,6 +62,91 @@ mod api_tests {
         }
     }
 
+    // Rows the tests need are inserted through the guard and removed again
+    // when it is dropped, which also happens when the test panics. Rows the
+    // code under test writes for seeded users are removed along with them.
+    struct Seeded {
+        conn: diesel::PgConnection,
+        users: Vec<Uuid>,
+        challenges: Vec<Uuid>,
+    }
+
+    impl Seeded {
+        fn new() -> Self {
+            Self {
+                conn: establish_connection(),
+                users: Vec::new(),
+                challenges: Vec::new(),
+            }
+        }
+
+        // The username is the prefix followed by the id
+        fn user(&mut self, prefix: &str, password_hash: String) -> Uuid {
+            use crate::database::models::NewUser;
+            use crate::schema::users;
+            use diesel::prelude::*;
+
+            let user_id = Uuid::new_v4();
+            diesel::insert_into(users::table)
+                .values(NewUser {
+                    id: user_id,
+                    username: format!("{prefix}_{}", user_id.simple()),
+                    password_hash,
+                })
+                .execute(&mut self.conn)
+                .unwrap();
+            self.users.push(user_id);
+            user_id
+        }
+
+        fn challenge(&mut self) -> Uuid {
+            use crate::database::models::NewChallenge;
+            use crate::schema::challenges;
+            use diesel::prelude::*;
+
+            let challenge_id = diesel::insert_into(challenges::table)
+                .values(NewChallenge {
+                    name: "seeded",
+                    description: "",
+                    allowed_languages: Vec::new(),
+                    limits: None,
+                    is_published: true,
+                    created_by: None,
+                    scoring: None,
+                })
+                .returning(challenges::id)
+                .get_result(&mut self.conn)
+                .unwrap();
+            self.challenges.push(challenge_id);
+            challenge_id
+        }
+    }
+
+    impl Drop for Seeded {
+        fn drop(&mut self) {
+            use crate::schema::{challenges, session_tokens, users};
+            use diesel::prelude::*;
+
+            // Sessions are the only rows that do not cascade with their user
+            let sessions =
+                session_tokens::table.filter(session_tokens::user_uuid.eq_any(&self.users));
+            if let Err(e) = diesel::delete(sessions).execute(&mut self.conn) {
+                error!("Failed to remove seeded sessions: {}", e);
+            }
+            if let Err(e) = diesel::delete(users::table.filter(users::id.eq_any(&self.users)))
+                .execute(&mut self.conn)
+            {
+                error!("Failed to remove seeded users: {}", e);
+            }
+            if let Err(e) =
+                diesel::delete(challenges::table.filter(challenges::id.eq_any(&self.challenges)))
+                    .execute(&mut self.conn)
+            {
+                error!("Failed to remove seeded challenges: {}", e);
+            }
+        }
+    }
+
     async fn perform_login(server: &TestServer, username: &str, password: &str) -> Value {
         server
             .post("/login")
@@ -80,4 +189,1160 @@ mod api_tests {
 
         // Clean up: The temporary file will be deleted when 'temp_file' goes out of scope
     }
+
+    #[test]
+    fn test_runner_limits_host_config() {
+        let host_config = RUNNER_LIMITS.host_config();
+
+        assert_eq!(host_config.network_mode.as_deref(), Some("none"));
+        assert_eq!(host_config.readonly_rootfs, Some(true));
+        assert_eq!(host_config.memory, Some(RUNNER_LIMITS.memory_bytes));
+        assert_eq!(host_config.pids_limit, Some(RUNNER_LIMITS.pids_limit));
+        assert!(host_config
+            .cap_drop
+            .unwrap_or_default()
+            .contains(&"ALL".to_string()));
+
+        // The working directory is capped like /tmp
+        let mounts = host_config.mounts.unwrap_or_default();
+        assert_eq!(mounts.len(), 1);
+        assert_eq!(mounts[0].target.as_deref(), Some("/sandbox"));
+        let options = mounts[0]
+            .volume_options
+            .as_ref()
+            .and_then(|options| options.driver_config.as_ref())
+            .and_then(|driver| driver.options.as_ref())
+            .unwrap();
+        assert_eq!(options["type"], "tmpfs");
+        assert_eq!(
+            options["o"],
+            format!("size={}", RUNNER_LIMITS.sandbox_size_bytes)
+        );
+
+        // Challenge overrides that would fail every run are rejected
+        assert!(RUNNER_LIMITS.is_valid());
+        assert!(COMPILER_LIMITS.is_valid());
+        for invalid in [
+            ResourceLimits {
+                memory_bytes: 0,
+                ..RUNNER_LIMITS
+            },
+            ResourceLimits {
+                cpu_quota: -1,
+                ..RUNNER_LIMITS
+            },
+            ResourceLimits {
+                pids_limit: 0,
+                ..RUNNER_LIMITS
+            },
+            ResourceLimits {
+                wall_time_ms: 0,
+                ..RUNNER_LIMITS
+            },
+        ] {
+            assert!(!invalid.is_valid());
+        }
+    }
+
+    #[test]
+    fn test_parse_diagnostics() {
+        let gcc = "main.c: In function 'main':\n\
+                   main.c:3:5: error: expected ';' before 'return'\n\
+                   Main.java:7: warning: unchecked call";
+        let rustc = "error[E0425]: cannot find value `x` in this scope\n --> main.rs:2:20\n";
+
+        let diagnostics = parse_diagnostics(gcc);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].file, "main.c");
+        assert_eq!(diagnostics[0].line, 3);
+        assert_eq!(diagnostics[0].column, Some(5));
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(diagnostics[1].column, None);
+        assert_eq!(diagnostics[1].severity, Severity::Warning);
+
+        let diagnostics = parse_diagnostics(rustc);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].file, "main.rs");
+        assert_eq!(diagnostics[0].line, 2);
+        assert_eq!(
+            diagnostics[0].message,
+            "cannot find value `x` in this scope"
+        );
+    }
+
+    struct TestJob(Uuid, Uuid);
+
+    impl QueueItem for TestJob {
+        fn id(&self) -> Uuid {
+            self.0
+        }
+        fn user_id(&self) -> Uuid {
+            self.1
+        }
+    }
+
+    #[test]
+    fn test_job_queue_is_fair_between_users() {
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+        let jobs: Vec<Uuid> = (0..4).map(|_| Uuid::new_v4()).collect();
+        let mut queue = JobQueue::new(3);
+
+        assert!(queue.push(TestJob(jobs[0], alice)).is_ok());
+        assert!(queue.push(TestJob(jobs[1], alice)).is_ok());
+        assert!(queue.push(TestJob(jobs[2], bob)).is_ok());
+        assert!(queue.push(TestJob(jobs[3], bob)).is_err());
+
+        // Bob's job is served before Alice's second one
+        assert_eq!(queue.position(jobs[2]), Some(1));
+        assert_eq!(queue.order(), vec![jobs[0], jobs[2], jobs[1]]);
+
+        assert!(queue.remove(jobs[2]).is_some());
+        assert_eq!(queue.pop().map(|j| j.0), Some(jobs[0]));
+        assert_eq!(queue.pop().map(|j| j.0), Some(jobs[1]));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_run_time_interval() {
+        let interval = interval_from_duration(std::time::Duration::from_millis(1500));
+        assert_eq!(interval.microseconds, 1_500_000);
+        assert_eq!(interval_to_millis(&interval), 1500);
+
+        // Postgres may normalize long intervals into days
+        let interval = diesel::pg::data_types::PgInterval::new(1000, 1, 0);
+        assert_eq!(interval_to_millis(&interval), 86_400_001);
+    }
+
+    #[test]
+    fn test_parse_metrics() {
+        use std::time::Duration;
+
+        let wall_time = Duration::from_millis(40);
+
+        let cgroup_v2 =
+            "usage_usec 30000\nuser_usec 25000\nsystem_usec 5000\nmemory_peak 4194304\n";
+        let metrics = parse_metrics(cgroup_v2, wall_time).expect("cgroup v2 metrics");
+        assert_eq!(metrics.cpu_user, Duration::from_millis(25));
+        assert_eq!(metrics.cpu_time(), Duration::from_millis(30));
+        assert_eq!(metrics.peak_memory_kb(), 4096);
+
+        let cgroup_v1 = "user 3\nsystem 1\nmemory_peak 2048\n";
+        let metrics = parse_metrics(cgroup_v1, wall_time).expect("cgroup v1 metrics");
+        assert_eq!(metrics.cpu_time(), Duration::from_millis(40));
+
+        // The wrapper never got to write the memory usage
+        assert_eq!(
+            parse_metrics("user_usec 1\nsystem_usec 1\nmemory_peak \n", wall_time),
+            None
+        );
+        assert_eq!(
+            parse_metrics(
+                "user_usec 1\nuser_usec 0\nsystem_usec 1\nmemory_peak 1\n",
+                wall_time
+            ),
+            None
+        );
+        assert_eq!(
+            parse_metrics("user_usec x\nsystem_usec 1\nmemory_peak 1\n", wall_time),
+            None
+        );
+
+        // Nothing the program left behind can outlive the wrapper or its file
+        let wrapper = with_metrics("./program");
+        let kill = wrapper.find("kill -9 -1").unwrap();
+        let remove = wrapper.find("rm -rf /sandbox/.metrics").unwrap();
+        let write = wrapper.find("> /sandbox/.metrics").unwrap();
+        assert!(wrapper.find("./program").unwrap() < kill && kill < remove && remove < write);
+    }
+
+    #[test]
+    fn test_output_comparison_modes() {
+        assert!(Comparison::Exact.check("1 2\n3\n", "1 2\n3").is_ok());
+        assert_eq!(
+            Comparison::Exact.check("1 2\n3\n", "1  2\n3\n"),
+            Err("line 1: expected `1 2`, got `1  2`".to_string())
+        );
+
+        assert!(Comparison::IgnoreWhitespace
+            .check("1 2\n3", " 1\t2 3 \n")
+            .is_ok());
+        assert!(Comparison::IgnoreWhitespace.check("1 2 3", "1 2").is_err());
+
+        let float = Comparison::Float { tolerance: 1e-3 };
+        assert!(float.check("3.1416 ok", "3.14159 ok").is_ok());
+        assert!(float.check("3.1416", "3.15").is_err());
+        assert!(float.check("NaN inf", "NaN inf").is_ok());
+        assert!(float.check("NaN", "1.0").is_err());
+
+        assert!(Comparison::Regex
+            .check(r"\d+ apples", "42 apples\n")
+            .is_ok());
+        assert!(Comparison::Regex.check(r"\d+", "42 apples").is_err());
+
+        assert!(Comparison::UnorderedLines
+            .check("a\nb\nc\n", "c\na\nb")
+            .is_ok());
+        assert!(Comparison::UnorderedLines.check("a\nb", "a\na").is_err());
+    }
+
+    #[test]
+    fn test_challenge_test_case_comparison() {
+        let stored = |comparison| ChallengeTestCase {
+            id: Uuid::new_v4(),
+            challenge_id: Uuid::new_v4(),
+            name: "sample".to_string(),
+            input: "1 2".to_string(),
+            expected_output: "3".to_string(),
+            comparison,
+            is_sample: true,
+            position: 0,
+        };
+
+        let case = TestCase::from(stored(serde_json::json!({ "mode": "float" })));
+        assert_eq!(case.comparison, Comparison::Float { tolerance: 1e-6 });
+
+        // Unknown modes fall back to an exact comparison
+        let case = TestCase::from(stored(serde_json::json!({ "mode": "fuzzy" })));
+        assert_eq!(case.comparison, Comparison::Exact);
+    }
+
+    #[test]
+    fn test_checker_judgement() {
+        let checker_run = |exit_code, stdout: &str| ContainerOutput {
+            output: CapturedOutput {
+                stdout: stdout.as_bytes().to_vec(),
+                stderr: b"boom".to_vec(),
+                truncated: false,
+            },
+            id: String::new(),
+            exit_code,
+            outcome: RunOutcome::Exited,
+            duration: std::time::Duration::from_millis(5),
+            metrics: None,
+        };
+
+        let judgement = Judgement::from_checker(&checker_run(0, "valid path\n"));
+        assert_eq!(judgement.verdict, Verdict::Accepted);
+        assert_eq!(judgement.score, 1.0);
+        assert_eq!(judgement.message.as_deref(), Some("valid path"));
+
+        let judgement = Judgement::from_checker(&checker_run(1, ""));
+        assert_eq!(judgement.verdict, Verdict::WrongAnswer);
+        assert_eq!(judgement.message, None);
+
+        let judgement = Judgement::from_checker(&checker_run(2, "0.25\npath is too long\n"));
+        assert_eq!(judgement.verdict, Verdict::PartiallyAccepted);
+        assert_eq!(judgement.score, 0.25);
+        assert_eq!(judgement.message.as_deref(), Some("path is too long"));
+
+        // Scores outside 0 to 1 and unknown exit codes are the checker's fault
+        let judgement = Judgement::from_checker(&checker_run(2, "1.5\n"));
+        assert_eq!(judgement.verdict, Verdict::CheckerError);
+        let judgement = Judgement::from_checker(&checker_run(3, ""));
+        assert_eq!(judgement.verdict, Verdict::CheckerError);
+        assert_eq!(judgement.score, 0.0);
+    }
+
+    #[test]
+    fn test_hidden_case_output_is_not_reported() {
+        let case = |hidden| TestCase {
+            name: "secret".to_string(),
+            input: "7 5".to_string(),
+            expected_output: "hidden-answer-12".to_string(),
+            comparison: Comparison::Exact,
+            hidden,
+        };
+        // A wrong answer that echoes its input, a diff would show the expected output
+        let run = ContainerOutput {
+            output: CapturedOutput {
+                stdout: b"hidden-input 7 5\n".to_vec(),
+                stderr: b"debug: hidden-input\n".to_vec(),
+                truncated: false,
+            },
+            id: String::new(),
+            exit_code: 0,
+            outcome: RunOutcome::Exited,
+            duration: std::time::Duration::from_millis(5),
+            metrics: None,
+        };
+        let job_json = |hidden| {
+            let output = JobOutput {
+                compile: CompileResult {
+                    success: true,
+                    exit_code: Some(0),
+                    outcome: RunOutcome::Exited,
+                    diagnostics: Vec::new(),
+                    output: String::new(),
+                    artifact: None,
+                },
+                run: None,
+                grading: Some(GradingReport::new(vec![grade_case(
+                    &case(hidden),
+                    &run,
+                    None,
+                )])),
+                game: None,
+                score: None,
+            };
+            job_output_json(Language::Python, output).1
+        };
+
+        let json = job_json(true);
+        assert_eq!(json["cases"][0]["verdict"], "wrong_answer");
+        assert_eq!(json["cases"][0]["time_ms"], 5);
+        let text = json.to_string();
+        assert!(!text.contains("hidden-answer"));
+        assert!(!text.contains("hidden-input"));
+
+        // Samples keep their output and diff
+        let text = job_json(false).to_string();
+        assert!(text.contains("hidden-answer-12"));
+        assert!(text.contains("hidden-input"));
+    }
+
+    #[tokio::test]
+    async fn test_ping_pong_referee() {
+        let mut game = PingPong::new(2);
+        assert_eq!(game.start().await.unwrap(), Some("ping".to_string()));
+        assert_eq!(
+            game.play("pong\n").await.unwrap(),
+            Turn::Reply("ping".to_string())
+        );
+        assert!(matches!(
+            game.play("pong").await.unwrap(),
+            Turn::Over {
+                winner: Winner::Player,
+                ..
+            }
+        ));
+
+        let mut game = PingPong::new(3);
+        assert!(matches!(
+            game.play("ping").await.unwrap(),
+            Turn::Over {
+                winner: Winner::Referee,
+                ..
+            }
+        ));
+
+        assert_eq!(
+            parse_referee_command("move 3 4").unwrap(),
+            Turn::Reply("3 4".to_string())
+        );
+        assert_eq!(
+            parse_referee_command("winner draw board is full").unwrap(),
+            Turn::Over {
+                winner: Winner::Draw,
+                reason: "board is full".to_string()
+            }
+        );
+        assert!(parse_referee_command("winner nobody").is_err());
+        assert!(parse_referee_command("resign").is_err());
+    }
+
+    #[test]
+    fn test_tournament_pairings_and_ratings() {
+        // Five entries meet each other exactly once and sit out once each
+        let mut met = std::collections::HashSet::new();
+        let mut byes = Vec::new();
+        for round in 0..round_robin_rounds(5) {
+            for (a, b) in round_robin_pairings(5, round) {
+                match b {
+                    Some(b) => assert!(met.insert((a.min(b), a.max(b)))),
+                    None => byes.push(a),
+                }
+            }
+        }
+        assert_eq!(met.len(), 10);
+        byes.sort_unstable();
+        assert_eq!(byes, vec![0, 1, 2, 3, 4]);
+
+        // The leaders play each other unless they already have
+        let points = [2.0, 0.0, 1.0, 1.5];
+        let (none, no_byes) = (
+            std::collections::HashSet::new(),
+            std::collections::HashSet::new(),
+        );
+        assert_eq!(
+            swiss_pairings(&points, &none, &no_byes),
+            vec![(0, Some(3)), (2, Some(1))]
+        );
+        let played = std::collections::HashSet::from([(0, 3)]);
+        assert_eq!(
+            swiss_pairings(&points, &played, &no_byes),
+            vec![(0, Some(2)), (3, Some(1))]
+        );
+        let byes = std::collections::HashSet::from([1]);
+        assert_eq!(swiss_pairings(&[1.0, 0.0, 0.5], &none, &byes)[0], (2, None));
+
+        let (winner, loser) = elo_update(1500.0, 1500.0, 1.0);
+        assert!((winner - 1516.0).abs() < 1e-9);
+        assert!((loser - 1484.0).abs() < 1e-9);
+        let (favourite, _) = elo_update(1800.0, 1400.0, 0.5);
+        assert!(favourite < 1800.0);
+    }
+
+    #[test]
+    fn test_score_breakdown() {
+        let case = |score: f64, cpu_time_ms: u128, peak_memory_kb: u64| CaseUsage {
+            score,
+            cpu_time_ms: Some(cpu_time_ms),
+            peak_memory_kb: Some(peak_memory_kb),
+        };
+        let input = ScoreInput {
+            cases: vec![case(1.0, 250, 1024), case(0.0, 10, 10)],
+            cpu_limit_ms: 1000,
+            memory_limit_kb: 4096,
+            code_bytes: 0,
+        };
+        let linear = ScoringConfig {
+            easing: Easing::Linear,
+            ..ScoringConfig::default()
+        };
+
+        // The failed case earns no resource points however fast it was
+        let breakdown = calculate_score(&input, &linear).unwrap();
+        assert!((breakdown.correctness - 0.5).abs() < 1e-9);
+        assert!((breakdown.cpu - 0.375).abs() < 1e-9);
+        assert!((breakdown.memory - 0.375).abs() < 1e-9);
+        assert!((breakdown.size - 0.5).abs() < 1e-9);
+        assert_eq!(breakdown.score, 47);
+
+        let only_correctness = ScoringConfig {
+            correctness_weight: 1.0,
+            cpu_weight: 0.0,
+            memory_weight: 0.0,
+            size_weight: 0.0,
+            ..linear
+        };
+        let perfect = ScoreInput {
+            cases: vec![case(1.0, 999, 4000)],
+            ..input
+        };
+        assert_eq!(
+            calculate_score(&perfect, &only_correctness).unwrap().score,
+            100
+        );
+        let wrong = ScoreInput {
+            cases: vec![case(0.0, 1, 1)],
+            ..input
+        };
+        assert_eq!(
+            calculate_score(&wrong, &ScoringConfig::default())
+                .unwrap()
+                .score,
+            1
+        );
+
+        // Easing only bends the curve between the ends
+        let quad_in = ScoringConfig {
+            easing: Easing::QuadIn,
+            ..linear
+        };
+        assert!(calculate_score(&input, &quad_in).unwrap().score < breakdown.score);
+
+        let no_weights = ScoringConfig {
+            correctness_weight: 0.0,
+            ..only_correctness
+        };
+        assert!(calculate_score(&input, &no_weights).is_err());
+
+        // The CPU limit follows the quota, half a core gets half the wall time
+        assert_eq!(RUNNER_LIMITS.cpu_time(), RUNNER_LIMITS.wall_time());
+        let half_core = ResourceLimits {
+            cpu_quota: RUNNER_LIMITS.cpu_quota / 2,
+            ..RUNNER_LIMITS
+        };
+        assert_eq!(half_core.cpu_time(), RUNNER_LIMITS.wall_time() / 2);
+    }
+
+    #[tokio::test]
+    async fn test_leaderboard_ranking() {
+        use crate::database::connection::get_leaderboard;
+        use crate::database::models::NewJob;
+        use crate::schema::jobs;
+        use diesel::prelude::*;
+
+        // Far in the future, so no other run falls in the window
+        let at = |minute: u32| {
+            chrono::NaiveDate::from_ymd_opt(2100, 3, 20)
+                .unwrap()
+                .and_hms_opt(12, minute, 0)
+                .unwrap()
+        };
+        let since = Some(at(0));
+
+        let mut seeded = Seeded::new();
+        let (alice, bob, carol) = (
+            seeded.user("ranked", String::new()),
+            seeded.user("ranked", String::new()),
+            seeded.user("ranked", String::new()),
+        );
+        let (first, second) = (seeded.challenge(), seeded.challenge());
+
+        // User, challenge, score, CPU time and minute of the submission
+        let runs = [
+            (alice, first, 80, Some(10), 5),
+            (alice, first, 60, Some(1), 2),
+            (bob, first, 80, Some(50), 1),
+            (carol, first, 80, None, 5),
+            (alice, second, 30, Some(20), 9),
+        ];
+        for (user_uuid, challenge_id, score, cpu_time_ms, minute) in runs {
+            let job_id = Uuid::new_v4();
+            diesel::insert_into(jobs::table)
+                .values(NewJob {
+                    id: job_id,
+                    user_uuid,
+                    file_id: None,
+                    language: "python",
+                    source: b"",
+                    test_cases: None,
+                    stdin: b"",
+                    challenge_id: Some(challenge_id),
+                    limits: None,
+                    checker: None,
+                    game: None,
+                    scoring: None,
+                })
+                .execute(&mut seeded.conn)
+                .unwrap();
+            diesel::update(jobs::table.find(job_id))
+                .set((
+                    jobs::score.eq(score),
+                    jobs::cpu_time_ms.eq(cpu_time_ms),
+                    jobs::created_at.eq(at(minute)),
+                ))
+                .execute(&mut seeded.conn)
+                .unwrap();
+        }
+
+        // Equal scores go to the earlier submission, then to the faster one
+        let (ranked, total) = get_leaderboard(Some(first), since, 10, 0).await.unwrap();
+        let order: Vec<Uuid> = ranked.iter().map(|standing| standing.user_uuid).collect();
+        assert_eq!(order, vec![bob, alice, carol]);
+        assert_eq!(total, 3);
+        assert_eq!(ranked[1].score, 80);
+
+        // Best scores are summed over the challenges
+        let (global, total) = get_leaderboard(None, since, 10, 0).await.unwrap();
+        assert_eq!(total, 3);
+        assert_eq!(global[0].user_uuid, alice);
+        assert_eq!((global[0].score, global[0].solved), (110, 2));
+        assert_eq!(global[0].cpu_time_ms, Some(30));
+        assert_eq!(global[0].submitted_at, at(9));
+        assert_eq!(global[1].user_uuid, bob);
+
+        // Only the requested page is loaded, the total still counts everyone
+        let (page, total) = get_leaderboard(None, since, 1, 2).await.unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].user_uuid, carol);
+        assert_eq!(total, 3);
+
+        let page = Page::new(Some(3), Some(1000));
+        assert_eq!((page.size, page.offset()), (100, 200));
+        assert_eq!(Page::new(Some(0), None).offset(), 0);
+
+        // The week starts on Monday
+        let wednesday = chrono::NaiveDate::from_ymd_opt(2024, 3, 20)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap();
+        assert_eq!(
+            LeaderboardWindow::Week.since(wednesday),
+            chrono::NaiveDate::from_ymd_opt(2024, 3, 18)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+        );
+        assert_eq!(LeaderboardWindow::All.since(wednesday), None);
+    }
+
+    #[test]
+    fn test_contest_scoreboard() {
+        let at = |minute: i64| {
+            chrono::NaiveDate::from_ymd_opt(2024, 3, 25)
+                .unwrap()
+                .and_hms_opt(9, 0, 0)
+                .unwrap()
+                + chrono::Duration::minutes(minute)
+        };
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        let participants = vec![(alice, "alice".to_string()), (bob, "bob".to_string())];
+        let attempt = |user_id, challenge_id, minute, accepted, points| Attempt {
+            user_id,
+            challenge_id,
+            submitted_at: at(minute),
+            result: AttemptResult::Judged { accepted, points },
+        };
+        let attempts = vec![
+            attempt(alice, first, 10, false, 50.0),
+            attempt(alice, first, 30, true, 100.0),
+            // Ignored, the challenge is already solved
+            attempt(alice, first, 40, false, 0.0),
+            attempt(bob, first, 20, true, 100.0),
+            attempt(bob, second, 100, false, 60.0),
+            attempt(alice, second, 150, true, 100.0),
+        ];
+
+        // Both solved one, bob has no penalty for a rejected submission
+        let icpc = scoreboard(
+            ContestScoring::Icpc,
+            at(0),
+            None,
+            &[first, second],
+            &participants,
+            &attempts[..5],
+        );
+        assert_eq!((icpc[0].user_id, icpc[0].penalty), (bob, 20));
+        assert_eq!((icpc[1].user_id, icpc[1].penalty), (alice, 50));
+        assert_eq!(icpc[1].challenges[0].attempts, 2);
+
+        let ioi = scoreboard(
+            ContestScoring::Ioi,
+            at(0),
+            None,
+            &[first, second],
+            &participants,
+            &attempts,
+        );
+        assert_eq!((ioi[0].user_id, ioi[0].points), (alice, 200.0));
+        assert_eq!((ioi[1].user_id, ioi[1].points), (bob, 160.0));
+
+        // The last hour of a three hour contest is hidden
+        let frozen_at = freeze_time(at(0), at(180), 60);
+        assert_eq!(frozen_at, Some(at(120)));
+        let frozen = scoreboard(
+            ContestScoring::Icpc,
+            at(0),
+            frozen_at,
+            &[first, second],
+            &participants,
+            &attempts,
+        );
+        let alice_row = frozen.iter().find(|row| row.user_id == alice).unwrap();
+        assert_eq!(alice_row.solved, 1);
+        assert_eq!(alice_row.challenges[1].pending, 1);
+        assert_eq!(frozen[0].rank, 1);
+        assert_eq!(freeze_time(at(0), at(180), 0), None);
+
+        // Equal results share a rank
+        let tied = scoreboard(
+            ContestScoring::Ioi,
+            at(0),
+            None,
+            &[first],
+            &participants,
+            &[],
+        );
+        assert_eq!((tied[0].rank, tied[1].rank), (1, 1));
+
+        // A program that did not compile is not an attempt, one that ran is
+        let submission = ContestSubmission {
+            id: Uuid::new_v4(),
+            contest_id: Uuid::new_v4(),
+            challenge_id: first,
+            user_uuid: alice,
+            file_id: Uuid::new_v4(),
+            submitted_at: at(10),
+        };
+        let run = |time_taken| Simulation {
+            simulation_id: 1,
+            ran_at: at(11),
+            ran_file_id: submission.file_id,
+            logs: None,
+            result: Some(SimulationResult::Failed),
+            time_taken,
+            cpu_time: None,
+            max_memory_usage: None,
+            score: None,
+            score_breakdown: None,
+        };
+        assert!(Attempt::from_submission(&submission, Some(&run(None))).is_none());
+        let rejected = Attempt::from_submission(
+            &submission,
+            Some(&run(Some(interval_from_duration(
+                std::time::Duration::from_millis(5),
+            )))),
+        )
+        .unwrap();
+        assert_eq!(
+            rejected.result,
+            AttemptResult::Judged {
+                accepted: false,
+                points: 0.0
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_session_expiry_and_revocation() {
+        use crate::api::auth::tokens::{issue_token, SESSION_TOKEN_PREFIX};
+        use crate::database::connection::{
+            delete_expired_sessions, delete_session_token, delete_user_sessions, get_token_owner,
+        };
+        use crate::database::models::NewSessionToken;
+        use crate::error::Error;
+        use crate::schema::session_tokens;
+        use diesel::prelude::*;
+
+        let mut seeded = Seeded::new();
+        let user_id = seeded.user("session", String::new());
+        let now = chrono::Utc::now().naive_utc();
+        let mut log_in = |expiration_date| {
+            let session = issue_token(SESSION_TOKEN_PREFIX);
+            diesel::insert_into(session_tokens::table)
+                .values(NewSessionToken {
+                    id: session.id,
+                    token_hash: &session.hash,
+                    user_uuid: user_id,
+                    expiration_date,
+                })
+                .execute(&mut seeded.conn)
+                .unwrap();
+            session.token
+        };
+        let expired = log_in(now - chrono::Duration::minutes(1));
+        let valid = log_in(now + chrono::Duration::days(1));
+        let other = log_in(now + chrono::Duration::days(1));
+
+        assert!(matches!(
+            get_token_owner(&expired).await,
+            Err(Error::AuthFailTokenExpired)
+        ));
+        assert_eq!(get_token_owner(&valid).await.unwrap().unwrap().id, user_id);
+        let unknown = issue_token(SESSION_TOKEN_PREFIX).token;
+        assert!(get_token_owner(&unknown).await.unwrap().is_none());
+
+        // Deleted sessions stay gone for the requests that follow
+        assert!(delete_expired_sessions().await.unwrap() >= 1);
+        assert!(get_token_owner(&expired).await.unwrap().is_none());
+        assert_eq!(get_token_owner(&valid).await.unwrap().unwrap().id, user_id);
+
+        assert!(delete_session_token(&valid).await.unwrap());
+        assert!(!delete_session_token(&unknown).await.unwrap());
+        assert!(get_token_owner(&valid).await.unwrap().is_none());
+        assert_eq!(get_token_owner(&other).await.unwrap().unwrap().id, user_id);
+
+        assert_eq!(delete_user_sessions(user_id).await.unwrap(), 1);
+        assert!(get_token_owner(&other).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_logout_revokes_session() {
+        use crate::api::authentication::AUTH_TOKEN;
+        use crate::tasks::{TaskManager, WorkerConfig};
+        use crate::AppState;
+        use argon2::password_hash::{rand_core::OsRng, PasswordHasher, SaltString};
+        use argon2::Argon2;
+        use axum::http::StatusCode;
+        use std::sync::Arc;
+
+        let password = "correct horse battery staple";
+        let password_hash = Argon2::default()
+            .hash_password(password.as_bytes(), &SaltString::generate(&mut OsRng))
+            .unwrap()
+            .to_string();
+        let mut seeded = Seeded::new();
+        let user_id = seeded.user("logout", password_hash);
+
+        let state = AppState {
+            tm: Arc::new(TaskManager::new(WorkerConfig::from_env())),
+        };
+        let server = TestServer::new(crate::app(state)).unwrap();
+        let login = server
+            .post("/login")
+            .json(&json!({
+                "username": format!("logout_{}", user_id.simple()),
+                "password": password,
+            }))
+            .await;
+        assert_eq!(login.json::<Value>()["result"]["success"], json!(true));
+        let cookie = login.cookie(AUTH_TOKEN);
+
+        server
+            .get("/profile")
+            .add_cookie(cookie.clone())
+            .await
+            .assert_status_ok();
+        server
+            .post("/logout")
+            .add_cookie(cookie.clone())
+            .await
+            .assert_status_ok();
+
+        // The old cookie no longer belongs to a session
+        let response = server.get("/profile").add_cookie(cookie).await;
+        assert_eq!(response.status_code(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_session_token_hashing() {
+        use crate::api::auth::tokens::{
+            hash_token, issue_token, token_id, verify_token, SESSION_TOKEN_PREFIX,
+        };
+
+        let session = issue_token(SESSION_TOKEN_PREFIX);
+        assert!(session.token.starts_with("ses_"));
+        assert_eq!(
+            token_id(SESSION_TOKEN_PREFIX, &session.token),
+            Some(session.id)
+        );
+        assert_eq!(token_id("other", &session.token), None);
+        assert_eq!(token_id(SESSION_TOKEN_PREFIX, "ses_nonsense"), None);
+
+        // Only the keyed hash is stored
+        assert_eq!(session.hash.len(), 64);
+        assert!(!session.hash.contains(&session.token));
+        assert_eq!(hash_token(&session.token), session.hash);
+        assert!(verify_token(&session.token, &session.hash));
+
+        let other = issue_token(SESSION_TOKEN_PREFIX);
+        assert!(!verify_token(&other.token, &session.hash));
+        assert!(!verify_token(&session.token, "not hex"));
+    }
+
+    #[tokio::test]
+    async fn test_roles_and_teacher_access() {
+        use crate::api::auth::roles::can_view_submissions_of;
+        use crate::ctx::Ctx;
+        use crate::database::connection::{
+            delete_teacher_student, get_students, get_user, update_user_role,
+        };
+        use crate::database::models::{NewTeacherStudent, Role};
+        use crate::schema::{teacher_students, users};
+        use diesel::prelude::*;
+
+        // Every role includes the ones below it
+        assert!(Role::Admin.includes(Role::Teacher));
+        assert!(Role::Teacher.includes(Role::Student));
+        assert!(!Role::Teacher.includes(Role::Admin));
+        assert!(!Role::Student.includes(Role::Teacher));
+
+        let mut seeded = Seeded::new();
+        let (teacher, student, other) = (
+            seeded.user("teacher", String::new()),
+            seeded.user("student", String::new()),
+            seeded.user("other", String::new()),
+        );
+        diesel::update(users::table.find(teacher))
+            .set(users::role.eq(Role::Teacher))
+            .execute(&mut seeded.conn)
+            .unwrap();
+        diesel::insert_into(teacher_students::table)
+            .values(NewTeacherStudent {
+                teacher_uuid: teacher,
+                student_uuid: student,
+            })
+            .execute(&mut seeded.conn)
+            .unwrap();
+
+        assert_eq!(get_user(student).await.unwrap().role, Role::Student);
+        assert!(update_user_role(student, Role::Teacher).await.unwrap());
+        assert!(!update_user_role(Uuid::new_v4(), Role::Teacher)
+            .await
+            .unwrap());
+        let students = get_students(teacher).await.unwrap();
+        assert_eq!(students.len(), 1);
+        assert_eq!(students[0].0, student);
+
+        // Teachers only see their own students, admins everyone
+        let ctx = Ctx::new(teacher, get_user(teacher).await.unwrap().role);
+        assert!(can_view_submissions_of(&ctx, student).await.unwrap());
+        assert!(!can_view_submissions_of(&ctx, other).await.unwrap());
+        assert!(
+            can_view_submissions_of(&Ctx::new(other, Role::Admin), student)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !can_view_submissions_of(&Ctx::new(other, Role::Student), student)
+                .await
+                .unwrap()
+        );
+        // Demoted teachers lose access even while the link remains
+        assert!(
+            !can_view_submissions_of(&Ctx::new(teacher, Role::Student), student)
+                .await
+                .unwrap()
+        );
+
+        assert!(delete_teacher_student(teacher, student).await.unwrap());
+        assert!(!delete_teacher_student(teacher, other).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_protected_routes_reject_anonymous() {
+        use crate::tasks::{TaskManager, WorkerConfig};
+        use crate::AppState;
+        use axum::http::{Method, StatusCode};
+        use std::sync::Arc;
+
+        let state = AppState {
+            tm: Arc::new(TaskManager::new(WorkerConfig::from_env())),
+        };
+        let server = TestServer::new(crate::app(state)).unwrap();
+
+        let id = Uuid::new_v4();
+        let protected = [
+            (Method::POST, "/upload".to_string()),
+            (Method::POST, "/logout".to_string()),
+            (Method::POST, "/logout/all".to_string()),
+            (Method::GET, "/profile".to_string()),
+            (Method::GET, "/files".to_string()),
+            (Method::GET, format!("/files/{id}/runs")),
+            (Method::POST, "/build".to_string()),
+            (Method::GET, "/queue".to_string()),
+            (Method::GET, format!("/jobs/{id}")),
+            (Method::DELETE, format!("/jobs/{id}")),
+            (Method::GET, format!("/jobs/{id}/events")),
+            (Method::GET, "/challenges".to_string()),
+            (Method::POST, "/challenges".to_string()),
+            (Method::GET, format!("/challenges/{id}")),
+            (Method::PUT, format!("/challenges/{id}")),
+            (Method::DELETE, format!("/challenges/{id}")),
+            (Method::GET, format!("/challenges/{id}/tests")),
+            (Method::POST, format!("/challenges/{id}/tests")),
+            (Method::DELETE, format!("/challenges/{id}/tests/{id}")),
+            (Method::PUT, format!("/challenges/{id}/checker")),
+            (Method::DELETE, format!("/challenges/{id}/checker")),
+            (Method::PUT, format!("/challenges/{id}/game")),
+            (Method::DELETE, format!("/challenges/{id}/game")),
+            (Method::POST, format!("/challenges/{id}/submit")),
+            (Method::GET, format!("/challenges/{id}/leaderboard")),
+            (Method::GET, format!("/challenges/{id}/ratings")),
+            (Method::GET, "/leaderboard".to_string()),
+            (Method::GET, "/tournaments".to_string()),
+            (Method::POST, "/tournaments".to_string()),
+            (Method::GET, format!("/tournaments/{id}")),
+            (Method::POST, format!("/tournaments/{id}/entries")),
+            (Method::POST, format!("/tournaments/{id}/start")),
+            (Method::GET, format!("/matches/{id}")),
+            (Method::GET, "/contests".to_string()),
+            (Method::POST, "/contests".to_string()),
+            (Method::GET, format!("/contests/{id}")),
+            (Method::PUT, format!("/contests/{id}")),
+            (Method::DELETE, format!("/contests/{id}")),
+            (Method::PUT, format!("/contests/{id}/challenges/{id}")),
+            (Method::DELETE, format!("/contests/{id}/challenges/{id}")),
+            (
+                Method::POST,
+                format!("/contests/{id}/challenges/{id}/submit"),
+            ),
+            (Method::POST, format!("/contests/{id}/register")),
+            (Method::GET, format!("/contests/{id}/scoreboard")),
+            (Method::PUT, format!("/users/{id}/role")),
+            (Method::PUT, format!("/teachers/{id}/students/{id}")),
+            (Method::DELETE, format!("/teachers/{id}/students/{id}")),
+            (Method::GET, "/students".to_string()),
+            (Method::GET, format!("/students/{id}/files")),
+            (Method::GET, "/tokens".to_string()),
+            (Method::POST, "/tokens".to_string()),
+            (Method::DELETE, format!("/tokens/{id}")),
+            (Method::POST, format!("/tokens/{id}/rotate")),
+        ];
+        for (method, path) in protected {
+            let response = server.method(method.clone(), &path).await;
+            assert_eq!(
+                response.status_code(),
+                StatusCode::UNAUTHORIZED,
+                "{method} {path}"
+            );
+            assert_eq!(response.json::<Value>(), json!({ "error": "NO_AUTH" }));
+        }
+
+        // A cookie that does not belong to a session is no better
+        let response = server
+            .get("/profile")
+            .add_cookie(tower_cookies::Cookie::new(
+                crate::api::authentication::AUTH_TOKEN,
+                "ses_nonsense",
+            ))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::UNAUTHORIZED);
+
+        server.get("/").await.assert_status_ok();
+    }
+
+    #[tokio::test]
+    async fn test_personal_access_tokens() {
+        use crate::api::auth::tokens::{issue_token, PERSONAL_TOKEN_PREFIX, SESSION_TOKEN_PREFIX};
+        use crate::api::authentication::AUTH_TOKEN;
+        use crate::api::personal_tokens::{normalize_scopes, token_expiry};
+        use crate::database::connection::get_personal_token_owner;
+        use crate::database::models::{NewPersonalAccessToken, NewSessionToken, TokenScope};
+        use crate::error::Error;
+        use crate::tasks::{TaskManager, WorkerConfig};
+        use crate::AppState;
+        use axum::http::header::AUTHORIZATION;
+        use axum::http::{HeaderValue, StatusCode};
+        use diesel::prelude::*;
+        use std::sync::Arc;
+
+        assert_eq!(
+            normalize_scopes(&[TokenScope::Read, TokenScope::Submit, TokenScope::Read]),
+            Some(vec![TokenScope::Read, TokenScope::Submit])
+        );
+        assert_eq!(normalize_scopes(&[]), None);
+        let now = chrono::Utc::now().naive_utc();
+        assert_eq!(
+            token_expiry(now, None),
+            Some(now + chrono::Duration::days(30))
+        );
+        assert_eq!(token_expiry(now, Some(0)), None);
+        assert_eq!(token_expiry(now, Some(366)), None);
+
+        let mut seeded = Seeded::new();
+        let user_id = seeded.user("ci", String::new());
+        let mut create = |scopes: &[TokenScope], expires_at| {
+            let issued = issue_token(PERSONAL_TOKEN_PREFIX);
+            diesel::insert_into(crate::schema::personal_access_tokens::table)
+                .values(NewPersonalAccessToken {
+                    id: issued.id,
+                    user_uuid: user_id,
+                    name: "ci",
+                    token_hash: &issued.hash,
+                    scopes,
+                    created_at: now,
+                    expires_at,
+                })
+                .execute(&mut seeded.conn)
+                .unwrap();
+            issued.token
+        };
+        let read = create(&[TokenScope::Read], now + chrono::Duration::days(1));
+        let expired = create(&[TokenScope::Submit], now - chrono::Duration::minutes(1));
+
+        let (owner, scopes) = get_personal_token_owner(&read).await.unwrap().unwrap();
+        assert_eq!((owner.id, scopes), (user_id, vec![TokenScope::Read]));
+        assert!(matches!(
+            get_personal_token_owner(&expired).await,
+            Err(Error::AuthFailTokenExpired)
+        ));
+        // Session tokens are not accepted as personal tokens
+        let session = issue_token(SESSION_TOKEN_PREFIX).token;
+        assert!(get_personal_token_owner(&session).await.unwrap().is_none());
+
+        let state = AppState {
+            tm: Arc::new(TaskManager::new(WorkerConfig::from_env())),
+        };
+        let server = TestServer::new(crate::app(state)).unwrap();
+        let bearer = |token: &str| HeaderValue::from_str(&format!("Bearer {token}")).unwrap();
+
+        let response = server
+            .get("/queue")
+            .add_header(AUTHORIZATION, bearer(&read))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        // Each route declares the scope it needs, account routes need a session
+        for response in [
+            server
+                .post("/build")
+                .add_header(AUTHORIZATION, bearer(&read))
+                .await,
+            server
+                .get("/tokens")
+                .add_header(AUTHORIZATION, bearer(&read))
+                .await,
+        ] {
+            assert_eq!(response.status_code(), StatusCode::FORBIDDEN);
+            assert_eq!(
+                response.json::<Value>(),
+                json!({ "error": "INSUFFICIENT_SCOPE" })
+            );
+        }
+
+        for token in [&expired, &session] {
+            let response = server
+                .get("/queue")
+                .add_header(AUTHORIZATION, bearer(token))
+                .await;
+            assert_eq!(response.status_code(), StatusCode::UNAUTHORIZED);
+        }
+
+        // Tokens are managed over a session
+        let session = issue_token(SESSION_TOKEN_PREFIX);
+        diesel::insert_into(crate::schema::session_tokens::table)
+            .values(NewSessionToken {
+                id: session.id,
+                token_hash: &session.hash,
+                user_uuid: user_id,
+                expiration_date: now + chrono::Duration::days(1),
+            })
+            .execute(&mut seeded.conn)
+            .unwrap();
+        let cookie = tower_cookies::Cookie::new(AUTH_TOKEN, session.token);
+
+        let created = server
+            .post("/tokens")
+            .add_cookie(cookie.clone())
+            .json(&json!({ "name": "deploy", "scopes": ["read"] }))
+            .await;
+        assert_eq!(created.status_code(), StatusCode::CREATED);
+        let created = created.json::<Value>();
+        let token = created["token"].as_str().unwrap().to_string();
+        // The scheme is case-insensitive
+        let response = server
+            .get("/queue")
+            .add_header(
+                AUTHORIZATION,
+                HeaderValue::from_str(&format!("bearer {token}")).unwrap(),
+            )
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let listed = server
+            .get("/tokens")
+            .add_cookie(cookie.clone())
+            .await
+            .json::<Value>();
+        let listed = listed["tokens"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|listed| listed["token_id"] == created["token_id"])
+            .unwrap()
+            .clone();
+        assert_eq!(listed["name"], json!("deploy"));
+        assert!(listed.get("token").is_none());
+        assert!(!listed["last_used_at"].is_null());
+
+        // Rotating replaces the token, the old one stops working
+        let rotated = server
+            .post(&format!(
+                "/tokens/{}/rotate",
+                created["token_id"].as_str().unwrap()
+            ))
+            .add_cookie(cookie.clone())
+            .await;
+        assert_eq!(rotated.status_code(), StatusCode::CREATED);
+        let rotated = rotated.json::<Value>();
+        assert_eq!(rotated["scopes"], json!(["read"]));
+        let replacement = rotated["token"].as_str().unwrap();
+        let response = server
+            .get("/queue")
+            .add_header(AUTHORIZATION, bearer(&token))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::UNAUTHORIZED);
+        let response = server
+            .get("/queue")
+            .add_header(AUTHORIZATION, bearer(replacement))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let revoke = format!("/tokens/{}", rotated["token_id"].as_str().unwrap());
+        server
+            .delete(&revoke)
+            .add_cookie(cookie.clone())
+            .await
+            .assert_status_ok();
+        let response = server
+            .get("/queue")
+            .add_header(AUTHORIZATION, bearer(replacement))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::UNAUTHORIZED);
+        let response = server.delete(&revoke).add_cookie(cookie).await;
+        assert_eq!(response.status_code(), StatusCode::NOT_FOUND);
+    }
 }
@@ -1,3 +1,5 @@
 mod files;
+mod temp_file;
 pub use files::create_file;
 pub use files::get_extension_from_filename;
+pub use temp_file::temp_file;
@@ -0,0 +1,11 @@
+use tempfile::tempfile;
+use tokio::fs::File;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+// An anonymous file with the contents, ready to be read from the start
+pub async fn temp_file(contents: &[u8]) -> std::io::Result<File> {
+    let mut file = File::from_std(tempfile()?);
+    file.write_all(contents).await?;
+    file.seek(std::io::SeekFrom::Start(0)).await?;
+    Ok(file)
+}
@@ -13,7 +13,7 @@ use rocket::{
     http::{Cookie, CookieJar, Status},
     request::{FromRequest, Outcome},
     response::Redirect,
-    serde::{Deserialize, Serialize},
+    serde::{uuid::Uuid, Deserialize, Serialize},
     tokio::io::AsyncReadExt,
 };
 use rocket_dyn_templates::{context, Template};
@@ -46,7 +46,7 @@ struct User {
     created_at: Option<String>,
     last_login_at: Option<String>,
     login_count: Option<u32>,
-    is_admin: Option<bool>,
+    role: Option<String>,
 }
 
 #[rocket::async_trait]
@@ -160,13 +160,35 @@ fn already_reg_and_logged_in(_user: User) -> Redirect {
     Redirect::to(uri!(index))
 }
 
+// The session is ended on the backend too, so the token stops working
 #[post("/log-out")]
-fn do_log_out(cookies: &CookieJar<'_>, _user: User) -> Redirect {
+async fn do_log_out(cookies: &CookieJar<'_>, _user: User) -> Redirect {
+    if let Some(session) = cookies.get("sessionToken") {
+        let _ = client_with_token(session.to_string())
+            .post(api_url("logout"))
+            .send()
+            .await;
+    }
     cookies.remove("sessionToken");
 
     Redirect::to(uri!(logged_out(Some("you have been logged out"))))
 }
 
+#[post("/log-out-all")]
+async fn do_log_out_all(cookies: &CookieJar<'_>, _user: User) -> Redirect {
+    if let Some(session) = cookies.get("sessionToken") {
+        let _ = client_with_token(session.to_string())
+            .post(api_url("logout/all"))
+            .send()
+            .await;
+    }
+    cookies.remove("sessionToken");
+
+    Redirect::to(uri!(logged_out(Some(
+        "you have been logged out on all devices"
+    ))))
+}
+
 #[get("/register?<e>", rank = 2)]
 fn register(e: Option<&str>) -> Template {
     Template::render("register", context! {err_msg: e})
@@ -218,6 +240,7 @@ fn submit_program(user: User, e: Option<&str>) -> Template {
 #[derive(FromForm)]
 struct SubmitProgramForm<'r> {
     file: TempFile<'r>,
+    language: &'r str,
 }
 
 #[derive(Deserialize)]
@@ -242,10 +265,12 @@ async fn do_submit_program(
         .await
         .unwrap();
 
-    let out_form = multipart::Form::new().part(
-        "file",
-        Part::bytes(buf).file_name(in_form.file.name().unwrap_or("program").to_owned()),
-    );
+    let out_form = multipart::Form::new()
+        .text("language", in_form.language.to_owned())
+        .part(
+            "file",
+            Part::bytes(buf).file_name(in_form.file.name().unwrap_or("program").to_owned()),
+        );
 
     let response = client_with_token(cookies.get("sessionToken").unwrap().to_string())
         .post(api_url("upload"))
@@ -281,6 +306,89 @@ async fn list_submissions(user: User, cookies: &CookieJar<'_>, s: Option<bool>)
     Template::render("my_submissions", context! {user, files, s})
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct LeaderboardEntry {
+    rank: u32,
+    username: String,
+    score: i64,
+    solved: u32,
+    cpu_time_ms: Option<i64>,
+    peak_memory_kb: Option<i64>,
+    submitted_at: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Leaderboard {
+    window: String,
+    page: u32,
+    per_page: u32,
+    total: u32,
+    entries: Vec<LeaderboardEntry>,
+}
+
+// The windows the backend ranks over
+#[derive(FromFormField, Clone, Copy, Default)]
+enum LeaderboardWindow {
+    #[field(value = "week")]
+    Week,
+    #[default]
+    #[field(value = "all")]
+    All,
+}
+
+impl LeaderboardWindow {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Week => "week",
+            Self::All => "all",
+        }
+    }
+}
+
+// The global leaderboard, or a single challenge's if one is given.
+// Unknown windows fall back to all time.
+#[get("/leaderboard?<challenge>&<window>&<page>")]
+async fn leaderboard(
+    user: User,
+    cookies: &CookieJar<'_>,
+    challenge: Option<Uuid>,
+    window: Option<LeaderboardWindow>,
+    page: Option<u32>,
+) -> Result<Template, Status> {
+    let endpoint = match challenge {
+        Some(challenge) => format!("challenges/{}/leaderboard", challenge),
+        None => "leaderboard".to_string(),
+    };
+    let response = client_with_token(cookies.get("sessionToken").unwrap().to_string())
+        .get(api_url(&endpoint))
+        .query(&[
+            ("window", window.unwrap_or_default().as_str().to_string()),
+            ("page", page.unwrap_or(1).to_string()),
+        ])
+        .send()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    // Missing or unpublished challenges are not found, anything else is our fault
+    match response.status() {
+        status if status.is_success() => {}
+        reqwest::StatusCode::NOT_FOUND => return Err(Status::NotFound),
+        _ => return Err(Status::InternalServerError),
+    }
+    let leaderboard = response
+        .json::<Leaderboard>()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let has_next = leaderboard.page * leaderboard.per_page < leaderboard.total;
+
+    Ok(Template::render(
+        "leaderboard",
+        context! {user, challenge, leaderboard, has_next},
+    ))
+}
+
 fn render_no_context(template: &'static str) -> Template {
     Template::render(template, HashMap::<&str, &str>::new())
 }
@@ -308,12 +416,14 @@ fn rocket() -> _ {
                 do_login,
                 already_logged_in,
                 do_log_out,
+                do_log_out_all,
                 register,
                 do_register,
                 already_reg_and_logged_in,
                 submit_program,
                 do_submit_program,
-                list_submissions
+                list_submissions,
+                leaderboard
             ],
         )
         .register("/", catchers![not_found, internal_error])